@@ -0,0 +1,226 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::{Add, AddAssign, Div, Mul, Sub};
+
+pub use cie::*;
+pub use composite::*;
+pub use rgba::*;
+pub use space::*;
+pub use spectrum::*;
+pub use transfer::*;
+
+use crate::raster::Raster;
+use crate::rectangle::Coordinate;
+
+mod space;
+mod transfer;
+mod cie;
+mod spectrum;
+mod rgba;
+mod composite;
+
+/// RGB color value with linear floating-point components.
+///
+/// The meaning of the components depends on the color space that the value is in; in the CIE XYZ color space the components r, g and b hold the tristimulus
+/// values X, Y and Z.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rgb {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+// ===== Rgb ===================================================================================================================================================
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb::new(0.0, 0.0, 0.0);
+    pub const WHITE: Rgb = Rgb::new(1.0, 1.0, 1.0);
+
+    #[inline]
+    pub const fn new(r: f32, g: f32, b: f32) -> Rgb {
+        Rgb { r, g, b }
+    }
+
+    #[inline]
+    pub const fn gray(v: f32) -> Rgb {
+        Rgb::new(v, v, v)
+    }
+
+    /// Returns the luminance of a linear sRGB / Rec.709 color.
+    #[inline]
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    #[inline]
+    pub fn max_component(&self) -> f32 {
+        f32::max(self.r, f32::max(self.g, self.b))
+    }
+
+    #[inline]
+    pub fn map<F: Fn(f32) -> f32>(&self, map_fn: F) -> Rgb {
+        Rgb::new(map_fn(self.r), map_fn(self.g), map_fn(self.b))
+    }
+
+    #[inline]
+    pub fn transform(&self, matrix: &[[f32; 3]; 3]) -> Rgb {
+        Rgb::new(
+            matrix[0][0] * self.r + matrix[0][1] * self.g + matrix[0][2] * self.b,
+            matrix[1][0] * self.r + matrix[1][1] * self.g + matrix[1][2] * self.b,
+            matrix[2][0] * self.r + matrix[2][1] * self.g + matrix[2][2] * self.b,
+        )
+    }
+}
+
+impl Add for Rgb {
+    type Output = Rgb;
+
+    #[inline]
+    fn add(self, rhs: Rgb) -> Rgb {
+        Rgb::new(self.r + rhs.r, self.g + rhs.g, self.b + rhs.b)
+    }
+}
+
+impl AddAssign for Rgb {
+    #[inline]
+    fn add_assign(&mut self, rhs: Rgb) {
+        self.r += rhs.r;
+        self.g += rhs.g;
+        self.b += rhs.b;
+    }
+}
+
+impl Sub for Rgb {
+    type Output = Rgb;
+
+    #[inline]
+    fn sub(self, rhs: Rgb) -> Rgb {
+        Rgb::new(self.r - rhs.r, self.g - rhs.g, self.b - rhs.b)
+    }
+}
+
+impl Mul for Rgb {
+    type Output = Rgb;
+
+    #[inline]
+    fn mul(self, rhs: Rgb) -> Rgb {
+        Rgb::new(self.r * rhs.r, self.g * rhs.g, self.b * rhs.b)
+    }
+}
+
+impl Mul<f32> for Rgb {
+    type Output = Rgb;
+
+    #[inline]
+    fn mul(self, rhs: f32) -> Rgb {
+        Rgb::new(self.r * rhs, self.g * rhs, self.b * rhs)
+    }
+}
+
+impl Div<f32> for Rgb {
+    type Output = Rgb;
+
+    #[inline]
+    fn div(self, rhs: f32) -> Rgb {
+        Rgb::new(self.r / rhs, self.g / rhs, self.b / rhs)
+    }
+}
+
+// ===== Raster ================================================================================================================================================
+
+impl<C: Coordinate> Raster<Rgb, C> {
+    /// Converts all elements of this raster from one color space to another.
+    pub fn convert_color_space(&self, from: &ColorSpace, to: &ColorSpace) -> Raster<Rgb, C> {
+        self.map(|color| from.convert(color, to))
+    }
+
+    /// Applies the encoding direction of a transfer function to all elements of this raster.
+    pub fn encode_transfer(&self, transfer: TransferFunction) -> Raster<Rgb, C> {
+        self.map(|color| transfer.encode_rgb(color))
+    }
+
+    /// Applies the decoding direction of a transfer function to all elements of this raster.
+    pub fn decode_transfer(&self, transfer: TransferFunction) -> Raster<Rgb, C> {
+        self.map(|color| transfer.decode_rgb(color))
+    }
+}
+
+impl<C: Coordinate> Raster<f32, C> {
+    /// Applies the encoding direction of a transfer function to all elements of this raster.
+    pub fn encode_transfer(&self, transfer: TransferFunction) -> Raster<f32, C> {
+        self.map(|value| transfer.encode(value))
+    }
+
+    /// Applies the decoding direction of a transfer function to all elements of this raster.
+    pub fn decode_transfer(&self, transfer: TransferFunction) -> Raster<f32, C> {
+        self.map(|value| transfer.decode(value))
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use crate::rectangle::Rectangle;
+
+    use super::*;
+
+    #[test]
+    fn rgb_arithmetic() {
+        let c1 = Rgb::new(0.1, 0.2, 0.3);
+        let c2 = Rgb::new(0.4, 0.5, 0.6);
+        assert_eq!(c1 + c2, Rgb::new(0.1 + 0.4, 0.2 + 0.5, 0.3 + 0.6));
+        assert_eq!(c2 - c1, Rgb::new(0.4 - 0.1, 0.5 - 0.2, 0.6 - 0.3));
+        assert_eq!(c1 * 2.0, Rgb::new(0.2, 0.4, 0.6));
+        assert_eq!(c1 / 2.0, Rgb::new(0.05, 0.1, 0.15));
+        assert_eq!(c1 * c2, Rgb::new(0.1 * 0.4, 0.2 * 0.5, 0.3 * 0.6));
+
+        let mut c3 = c1;
+        c3 += c2;
+        assert_eq!(c3, c1 + c2);
+    }
+
+    #[test]
+    fn rgb_luminance() {
+        assert!((Rgb::WHITE.luminance() - 1.0).abs() < 1e-6);
+        assert_eq!(Rgb::BLACK.luminance(), 0.0);
+    }
+
+    #[test]
+    fn raster_convert_color_space() {
+        let mut raster = Raster::<Rgb>::new(Rectangle::new(0, 0, 4, 3));
+        raster.set(1, 1, Rgb::new(0.2, 0.4, 0.8));
+
+        let converted = raster.convert_color_space(&ColorSpace::LINEAR_SRGB, &ColorSpace::ACES_CG);
+        let back = converted.convert_color_space(&ColorSpace::ACES_CG, &ColorSpace::LINEAR_SRGB);
+
+        let color = back.get(1, 1);
+        assert!((color.r - 0.2).abs() < 1e-5 && (color.g - 0.4).abs() < 1e-5 && (color.b - 0.8).abs() < 1e-5, "round trip failed: {:?}", color);
+        assert_eq!(back.get(0, 0), Rgb::BLACK);
+    }
+
+    #[test]
+    fn raster_encode_decode_transfer() {
+        let mut raster = Raster::<f32>::new(Rectangle::new(0, 0, 4, 3));
+        raster.set(2, 1, 0.5);
+
+        let encoded = raster.encode_transfer(TransferFunction::Srgb);
+        assert!((encoded.get(2, 1) - 0.7353569).abs() < 1e-5);
+
+        let decoded = encoded.decode_transfer(TransferFunction::Srgb);
+        assert!((decoded.get(2, 1) - 0.5).abs() < 1e-5);
+    }
+}
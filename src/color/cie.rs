@@ -0,0 +1,69 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// CIE 1931 2° standard observer color matching functions, using the multi-lobe piecewise Gaussian fit from Wyman, Sloan and Shirley, "Simple Analytic
+// Approximations to the CIE XYZ Color Matching Functions", Journal of Computer Graphics Techniques, vol. 2, no. 2, 2013.
+
+/// Evaluates the CIE 1931 x̄ color matching function at the given wavelength in nanometers.
+pub fn cie_x(wavelength: f32) -> f32 {
+    1.056 * gaussian(wavelength, 599.8, 37.9, 31.0) + 0.362 * gaussian(wavelength, 442.0, 16.0, 26.7) - 0.065 * gaussian(wavelength, 501.1, 20.4, 26.2)
+}
+
+/// Evaluates the CIE 1931 ȳ color matching function at the given wavelength in nanometers.
+pub fn cie_y(wavelength: f32) -> f32 {
+    0.821 * gaussian(wavelength, 568.8, 46.9, 40.5) + 0.286 * gaussian(wavelength, 530.9, 16.3, 31.1)
+}
+
+/// Evaluates the CIE 1931 z̄ color matching function at the given wavelength in nanometers.
+pub fn cie_z(wavelength: f32) -> f32 {
+    1.217 * gaussian(wavelength, 437.0, 11.8, 36.0) + 0.681 * gaussian(wavelength, 459.0, 26.0, 13.8)
+}
+
+#[inline]
+fn gaussian(x: f32, mu: f32, sigma_left: f32, sigma_right: f32) -> f32 {
+    let t = (x - mu) / if x < mu { sigma_left } else { sigma_right };
+    f32::exp(-0.5 * t * t)
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cie_y_peak() {
+        // The luminous efficiency function peaks at 555 nm with a value of 1
+        assert!((cie_y(555.0) - 1.0).abs() < 0.01);
+        assert!(cie_y(555.0) > cie_y(500.0));
+        assert!(cie_y(555.0) > cie_y(600.0));
+    }
+
+    #[test]
+    fn cie_reference_values() {
+        // Reference values from the CIE 1931 table
+        assert!((cie_x(600.0) - 1.0622).abs() < 0.03);
+        assert!((cie_y(600.0) - 0.6310).abs() < 0.03);
+        assert!((cie_z(450.0) - 1.7721).abs() < 0.05);
+    }
+
+    #[test]
+    fn cie_outside_visible_range() {
+        for wavelength in [300.0, 850.0] {
+            assert!(cie_x(wavelength).abs() < 1e-3);
+            assert!(cie_y(wavelength).abs() < 1e-3);
+            assert!(cie_z(wavelength).abs() < 1e-3);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::color::Rgba;
+use crate::raster::Raster;
+use crate::rectangle::Coordinate;
+
+/// Porter-Duff compositing operator, which combines a source color with a destination color; both have premultiplied alpha.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CompositeOperator {
+    /// Source over destination.
+    Over,
+    /// Source inside destination; the destination is only used for its alpha.
+    In,
+    /// Source outside destination; the destination is only used for its alpha.
+    Out,
+    /// Source inside destination, over destination.
+    Atop,
+    /// Source outside destination and destination outside source.
+    Xor,
+    /// Sum of source and destination.
+    Plus,
+}
+
+// ===== CompositeOperator =====================================================================================================================================
+
+impl CompositeOperator {
+    /// Combines a source color with a destination color.
+    #[inline]
+    pub fn apply(&self, source: Rgba, destination: Rgba) -> Rgba {
+        let (fs, fd) = match self {
+            CompositeOperator::Over => (1.0, 1.0 - source.a),
+            CompositeOperator::In => (destination.a, 0.0),
+            CompositeOperator::Out => (1.0 - destination.a, 0.0),
+            CompositeOperator::Atop => (destination.a, 1.0 - source.a),
+            CompositeOperator::Xor => (1.0 - destination.a, 1.0 - source.a),
+            CompositeOperator::Plus => (1.0, 1.0),
+        };
+        source * fs + destination * fd
+    }
+
+    /// Checks if this operator leaves the destination unchanged where the source is transparent.
+    #[inline]
+    fn keeps_destination(&self) -> bool {
+        !matches!(self, CompositeOperator::In | CompositeOperator::Out)
+    }
+}
+
+// ===== Raster ================================================================================================================================================
+
+impl<C: Coordinate> Raster<Rgba, C> {
+    /// Composites a source raster onto this raster. The result covers the union of the rectangles of both rasters; elements outside a raster are
+    /// transparent.
+    pub fn composite(&self, source: &Raster<Rgba, C>, operator: CompositeOperator) -> Raster<Rgba, C> {
+        let mut result = Raster::new(self.rectangle().union(source.rectangle()));
+        result.merge(self, |_, destination| destination);
+        result.composite_in_place(source, operator);
+        result
+    }
+
+    /// Composites a source raster onto this raster, in place. Parts of the source outside this raster are ignored; elements of this raster outside the
+    /// source are composited with a transparent source.
+    pub fn composite_in_place(&mut self, source: &Raster<Rgba, C>, operator: CompositeOperator) {
+        if !operator.keeps_destination() {
+            let source_rectangle = source.rectangle().clone();
+            for (x, y, destination) in self.iter_mut() {
+                if !source_rectangle.contains(x, y) {
+                    *destination = operator.apply(Rgba::TRANSPARENT, *destination);
+                }
+            }
+        }
+
+        self.merge(source, |destination, source| operator.apply(source, destination));
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use crate::color::Rgb;
+    use crate::rectangle::Rectangle;
+
+    use super::*;
+
+    const SOURCE: Rgba = Rgba::new(0.5, 0.0, 0.0, 0.5);
+    const DESTINATION: Rgba = Rgba::new(0.0, 0.0, 0.25, 0.25);
+
+    fn assert_close(actual: Rgba, expected: Rgba) {
+        let difference = actual - expected;
+        assert!(difference.r.abs() < 1e-6 && difference.g.abs() < 1e-6 && difference.b.abs() < 1e-6 && difference.a.abs() < 1e-6,
+            "expected {:?} but got {:?}", expected, actual);
+    }
+
+    #[test]
+    fn composite_operator_apply() {
+        assert_close(CompositeOperator::Over.apply(SOURCE, DESTINATION), Rgba::new(0.5, 0.0, 0.125, 0.625));
+        assert_close(CompositeOperator::In.apply(SOURCE, DESTINATION), Rgba::new(0.125, 0.0, 0.0, 0.125));
+        assert_close(CompositeOperator::Out.apply(SOURCE, DESTINATION), Rgba::new(0.375, 0.0, 0.0, 0.375));
+        assert_close(CompositeOperator::Atop.apply(SOURCE, DESTINATION), Rgba::new(0.125, 0.0, 0.125, 0.25));
+        assert_close(CompositeOperator::Xor.apply(SOURCE, DESTINATION), Rgba::new(0.375, 0.0, 0.125, 0.5));
+        assert_close(CompositeOperator::Plus.apply(SOURCE, DESTINATION), Rgba::new(0.5, 0.0, 0.25, 0.75));
+    }
+
+    #[test]
+    fn composite_operator_over_opaque() {
+        let source = Rgba::opaque(Rgb::new(1.0, 0.5, 0.0));
+        assert_eq!(CompositeOperator::Over.apply(source, DESTINATION), source);
+        assert_eq!(CompositeOperator::Over.apply(Rgba::TRANSPARENT, DESTINATION), DESTINATION);
+    }
+
+    fn layer(rectangle: Rectangle, color: Rgba) -> Raster<Rgba> {
+        Raster::<Rgba>::new(rectangle).map(|_| color)
+    }
+
+    #[test]
+    fn raster_composite_partial_overlap() {
+        let destination = layer(Rectangle::new(0, 0, 4, 4), DESTINATION);
+        let source = layer(Rectangle::new(2, 2, 6, 5), SOURCE);
+
+        let result = destination.composite(&source, CompositeOperator::Over);
+        assert_eq!(*result.rectangle(), Rectangle::new(0, 0, 6, 5));
+        assert_eq!(result.get(0, 0), DESTINATION);
+        assert_eq!(result.get(5, 4), SOURCE);
+        assert_eq!(result.get(0, 4), Rgba::TRANSPARENT);
+        assert_close(result.get(3, 3), CompositeOperator::Over.apply(SOURCE, DESTINATION));
+    }
+
+    #[test]
+    fn raster_composite_in_place_in() {
+        let mut destination = layer(Rectangle::new(0, 0, 4, 4), DESTINATION);
+        let source = layer(Rectangle::new(2, 2, 6, 5), SOURCE);
+
+        destination.composite_in_place(&source, CompositeOperator::In);
+        assert_eq!(*destination.rectangle(), Rectangle::new(0, 0, 4, 4));
+        assert_eq!(destination.get(0, 0), Rgba::TRANSPARENT);
+        assert_close(destination.get(3, 3), Rgba::new(0.125, 0.0, 0.0, 0.125));
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::{Add, AddAssign, Div, Mul, Sub};
+
+use crate::color::Rgb;
+
+/// RGBA color value with linear floating-point components and premultiplied alpha: the color components are already multiplied by the alpha component.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rgba {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+// ===== Rgba ==================================================================================================================================================
+
+impl Rgba {
+    pub const TRANSPARENT: Rgba = Rgba::new(0.0, 0.0, 0.0, 0.0);
+
+    /// Creates a color value from premultiplied components.
+    #[inline]
+    pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Rgba {
+        Rgba { r, g, b, a }
+    }
+
+    /// Creates an opaque color value.
+    #[inline]
+    pub const fn opaque(color: Rgb) -> Rgba {
+        Rgba::new(color.r, color.g, color.b, 1.0)
+    }
+
+    /// Creates a color value from a color that is not premultiplied and an alpha value.
+    #[inline]
+    pub fn from_straight(color: Rgb, alpha: f32) -> Rgba {
+        Rgba::new(color.r * alpha, color.g * alpha, color.b * alpha, alpha)
+    }
+
+    /// Returns the premultiplied color components.
+    #[inline]
+    pub fn rgb(&self) -> Rgb {
+        Rgb::new(self.r, self.g, self.b)
+    }
+
+    /// Returns the color components divided by alpha, or black if alpha is zero.
+    #[inline]
+    pub fn to_straight(&self) -> Rgb {
+        if self.a != 0.0 { self.rgb() / self.a } else { Rgb::BLACK }
+    }
+}
+
+impl From<Rgb> for Rgba {
+    #[inline]
+    fn from(color: Rgb) -> Rgba {
+        Rgba::opaque(color)
+    }
+}
+
+impl Add for Rgba {
+    type Output = Rgba;
+
+    #[inline]
+    fn add(self, rhs: Rgba) -> Rgba {
+        Rgba::new(self.r + rhs.r, self.g + rhs.g, self.b + rhs.b, self.a + rhs.a)
+    }
+}
+
+impl AddAssign for Rgba {
+    #[inline]
+    fn add_assign(&mut self, rhs: Rgba) {
+        self.r += rhs.r;
+        self.g += rhs.g;
+        self.b += rhs.b;
+        self.a += rhs.a;
+    }
+}
+
+impl Sub for Rgba {
+    type Output = Rgba;
+
+    #[inline]
+    fn sub(self, rhs: Rgba) -> Rgba {
+        Rgba::new(self.r - rhs.r, self.g - rhs.g, self.b - rhs.b, self.a - rhs.a)
+    }
+}
+
+impl Mul<f32> for Rgba {
+    type Output = Rgba;
+
+    #[inline]
+    fn mul(self, rhs: f32) -> Rgba {
+        Rgba::new(self.r * rhs, self.g * rhs, self.b * rhs, self.a * rhs)
+    }
+}
+
+impl Div<f32> for Rgba {
+    type Output = Rgba;
+
+    #[inline]
+    fn div(self, rhs: f32) -> Rgba {
+        Rgba::new(self.r / rhs, self.g / rhs, self.b / rhs, self.a / rhs)
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rgba_premultiplied() {
+        let color = Rgba::from_straight(Rgb::new(0.5, 1.0, 0.25), 0.5);
+        assert_eq!(color, Rgba::new(0.25, 0.5, 0.125, 0.5));
+        assert_eq!(color.rgb(), Rgb::new(0.25, 0.5, 0.125));
+        assert_eq!(color.to_straight(), Rgb::new(0.5, 1.0, 0.25));
+        assert_eq!(Rgba::TRANSPARENT.to_straight(), Rgb::BLACK);
+        assert_eq!(Rgba::from(Rgb::WHITE), Rgba::new(1.0, 1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn rgba_arithmetic() {
+        let c1 = Rgba::new(0.1, 0.2, 0.3, 0.4);
+        let c2 = Rgba::new(0.5, 0.5, 0.5, 0.5);
+        assert_eq!(c1 + c2, Rgba::new(0.1 + 0.5, 0.2 + 0.5, 0.3 + 0.5, 0.4 + 0.5));
+        assert_eq!(c2 - c1, Rgba::new(0.5 - 0.1, 0.5 - 0.2, 0.5 - 0.3, 0.5 - 0.4));
+        assert_eq!(c1 * 2.0, Rgba::new(0.2, 0.4, 0.6, 0.8));
+        assert_eq!(c1 / 2.0, Rgba::new(0.05, 0.1, 0.15, 0.2));
+    }
+}
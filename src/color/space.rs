@@ -0,0 +1,193 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::color::{Rgb, TransferFunction};
+
+/// Color space, defined by its primaries and white point (as matrices to and from CIE XYZ) and a transfer function.
+///
+/// All conversions go through CIE XYZ relative to the D65 white point. ACEScg, which has a D60 white point, is chromatically adapted to D65 with the
+/// Bradford transform.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ColorSpace {
+    name: &'static str,
+    to_xyz: [[f32; 3]; 3],
+    from_xyz: [[f32; 3]; 3],
+    transfer: TransferFunction,
+}
+
+// ===== ColorSpace ============================================================================================================================================
+
+impl ColorSpace {
+    const SRGB_TO_XYZ: [[f32; 3]; 3] = [
+        [0.4123908, 0.3575843, 0.1804808],
+        [0.212639, 0.7151687, 0.0721923],
+        [0.0193308, 0.1191948, 0.9505322],
+    ];
+
+    const XYZ_TO_SRGB: [[f32; 3]; 3] = [
+        [3.24097, -1.5373832, -0.4986108],
+        [-0.9692436, 1.8759675, 0.0415551],
+        [0.0556301, -0.203977, 1.0569715],
+    ];
+
+    const REC2020_TO_XYZ: [[f32; 3]; 3] = [
+        [0.636958, 0.1446169, 0.168881],
+        [0.2627002, 0.6779981, 0.0593017],
+        [0.0, 0.0280727, 1.0609851],
+    ];
+
+    const XYZ_TO_REC2020: [[f32; 3]; 3] = [
+        [1.7166512, -0.3556708, -0.2533663],
+        [-0.6666844, 1.6164812, 0.0157685],
+        [0.0176399, -0.0427706, 0.9421031],
+    ];
+
+    const AP1_TO_XYZ: [[f32; 3]; 3] = [
+        [0.6522375, 0.1282361, 0.1699822],
+        [0.2676722, 0.67434, 0.0579878],
+        [-0.0053818, 0.0013691, 1.0930705],
+    ];
+
+    const XYZ_TO_AP1: [[f32; 3]; 3] = [
+        [1.6605853, -0.3152956, -0.2415093],
+        [-0.6599261, 1.6083915, 0.0172986],
+        [0.0090026, -0.0035669, 0.9136433],
+    ];
+
+    const IDENTITY: [[f32; 3]; 3] = [
+        [1.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [0.0, 0.0, 1.0],
+    ];
+
+    /// sRGB primaries with linear values.
+    pub const LINEAR_SRGB: ColorSpace = ColorSpace::new("Linear sRGB", ColorSpace::SRGB_TO_XYZ, ColorSpace::XYZ_TO_SRGB, TransferFunction::Linear);
+
+    /// sRGB primaries with the sRGB transfer function.
+    pub const SRGB: ColorSpace = ColorSpace::new("sRGB", ColorSpace::SRGB_TO_XYZ, ColorSpace::XYZ_TO_SRGB, TransferFunction::Srgb);
+
+    /// Rec.709 primaries (which are the same as the sRGB primaries) with the BT.709 transfer function.
+    pub const REC709: ColorSpace = ColorSpace::new("Rec.709", ColorSpace::SRGB_TO_XYZ, ColorSpace::XYZ_TO_SRGB, TransferFunction::Rec709);
+
+    /// Rec.2020 primaries with linear values.
+    pub const LINEAR_REC2020: ColorSpace = ColorSpace::new("Linear Rec.2020", ColorSpace::REC2020_TO_XYZ, ColorSpace::XYZ_TO_REC2020, TransferFunction::Linear);
+
+    /// Rec.2020 primaries with the BT.2020 transfer function.
+    pub const REC2020: ColorSpace = ColorSpace::new("Rec.2020", ColorSpace::REC2020_TO_XYZ, ColorSpace::XYZ_TO_REC2020, TransferFunction::Rec2020);
+
+    /// ACES AP1 primaries with linear values.
+    pub const ACES_CG: ColorSpace = ColorSpace::new("ACEScg", ColorSpace::AP1_TO_XYZ, ColorSpace::XYZ_TO_AP1, TransferFunction::Linear);
+
+    /// CIE 1931 XYZ (D65).
+    pub const CIE_XYZ: ColorSpace = ColorSpace::new("CIE XYZ", ColorSpace::IDENTITY, ColorSpace::IDENTITY, TransferFunction::Linear);
+
+    #[inline]
+    pub const fn new(name: &'static str, to_xyz: [[f32; 3]; 3], from_xyz: [[f32; 3]; 3], transfer: TransferFunction) -> ColorSpace {
+        ColorSpace { name, to_xyz, from_xyz, transfer }
+    }
+
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    #[inline]
+    pub fn transfer(&self) -> TransferFunction {
+        self.transfer
+    }
+
+    /// Converts a color in this color space to linear CIE XYZ.
+    #[inline]
+    pub fn to_xyz(&self, color: Rgb) -> Rgb {
+        self.transfer.decode_rgb(color).transform(&self.to_xyz)
+    }
+
+    /// Converts a color in linear CIE XYZ to this color space.
+    #[inline]
+    pub fn from_xyz(&self, xyz: Rgb) -> Rgb {
+        self.transfer.encode_rgb(xyz.transform(&self.from_xyz))
+    }
+
+    /// Converts a color in this color space to another color space.
+    pub fn convert(&self, color: Rgb, to: &ColorSpace) -> Rgb {
+        if self.to_xyz == to.to_xyz {
+            // Same primaries, only the transfer function differs
+            to.transfer.encode_rgb(self.transfer.decode_rgb(color))
+        } else {
+            to.from_xyz(self.to_xyz(color))
+        }
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(actual: Rgb, expected: Rgb, tolerance: f32) {
+        assert!((actual.r - expected.r).abs() < tolerance && (actual.g - expected.g).abs() < tolerance && (actual.b - expected.b).abs() < tolerance,
+                "{:?} is not close to {:?}", actual, expected);
+    }
+
+    #[test]
+    fn color_space_matrices_are_inverse() {
+        for space in [ColorSpace::LINEAR_SRGB, ColorSpace::LINEAR_REC2020, ColorSpace::ACES_CG, ColorSpace::CIE_XYZ] {
+            for color in [Rgb::new(1.0, 0.0, 0.0), Rgb::new(0.0, 1.0, 0.0), Rgb::new(0.0, 0.0, 1.0)] {
+                assert_close(color.transform(&space.to_xyz).transform(&space.from_xyz), color, 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn color_space_white_point() {
+        // The white of all D65 color spaces maps to the D65 white point in XYZ
+        let d65 = Rgb::new(0.950456, 1.0, 1.0890578);
+        assert_close(ColorSpace::LINEAR_SRGB.to_xyz(Rgb::WHITE), d65, 1e-4);
+        assert_close(ColorSpace::REC2020.to_xyz(Rgb::WHITE), d65, 1e-4);
+
+        // ACEScg white is adapted to D65
+        assert_close(ColorSpace::ACES_CG.to_xyz(Rgb::WHITE), d65, 1e-4);
+    }
+
+    #[test]
+    fn color_space_convert_srgb_to_aces_cg() {
+        let aces = ColorSpace::LINEAR_SRGB.convert(Rgb::new(1.0, 0.0, 0.0), &ColorSpace::ACES_CG);
+        assert_close(aces, Rgb::new(0.6130974, 0.0701937, 0.0206156), 1e-4);
+    }
+
+    #[test]
+    fn color_space_convert_transfer_only() {
+        let encoded = ColorSpace::LINEAR_SRGB.convert(Rgb::gray(0.5), &ColorSpace::SRGB);
+        assert_close(encoded, Rgb::gray(0.7353569), 1e-6);
+
+        let linear = ColorSpace::SRGB.convert(encoded, &ColorSpace::LINEAR_SRGB);
+        assert_close(linear, Rgb::gray(0.5), 1e-6);
+    }
+
+    #[test]
+    fn color_space_convert_round_trip() {
+        let spaces = [ColorSpace::LINEAR_SRGB, ColorSpace::SRGB, ColorSpace::REC709, ColorSpace::LINEAR_REC2020, ColorSpace::REC2020, ColorSpace::ACES_CG,
+            ColorSpace::CIE_XYZ];
+        let color = Rgb::new(0.3, 0.6, 0.1);
+
+        for from in &spaces {
+            for to in &spaces {
+                let in_from = ColorSpace::LINEAR_SRGB.convert(color, from);
+                let in_to = from.convert(in_from, to);
+                assert_close(to.convert(in_to, &ColorSpace::LINEAR_SRGB), color, 1e-4);
+            }
+        }
+    }
+}
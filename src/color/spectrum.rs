@@ -0,0 +1,392 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::{Add, AddAssign, Div, Mul};
+use std::sync::OnceLock;
+
+use crate::color::{cie_x, cie_y, cie_z, ColorSpace, Rgb};
+use crate::raster::Raster;
+use crate::rectangle::Coordinate;
+
+/// Spectral distribution, stored as the average values in equally sized wavelength bins over the visible range.
+///
+/// Because the bins are fixed, spectra can be accumulated and filtered like any other value. A render function that evaluates wavelengths selected with
+/// `SampledWavelengths` turns its results into a `Spectrum` with `Spectrum::from_wavelength_samples()`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "Vec<f32>", try_from = "Vec<f32>"))]
+pub struct Spectrum {
+    values: [f32; Spectrum::BIN_COUNT],
+}
+
+/// Set of wavelengths for hero wavelength sampling: a hero wavelength and additional wavelengths spaced equally over the visible range.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SampledWavelengths {
+    wavelengths: [f32; SampledWavelengths::COUNT],
+}
+
+// ===== Spectrum ==============================================================================================================================================
+
+impl Spectrum {
+    pub const MIN_WAVELENGTH: f32 = 380.0;
+    pub const MAX_WAVELENGTH: f32 = 780.0;
+    pub const BIN_COUNT: usize = 40;
+    pub const BIN_WIDTH: f32 = (Spectrum::MAX_WAVELENGTH - Spectrum::MIN_WAVELENGTH) / Spectrum::BIN_COUNT as f32;
+
+    #[inline]
+    pub fn new(values: [f32; Spectrum::BIN_COUNT]) -> Spectrum {
+        Spectrum { values }
+    }
+
+    #[inline]
+    pub fn constant(value: f32) -> Spectrum {
+        Spectrum::new([value; Spectrum::BIN_COUNT])
+    }
+
+    /// Creates a spectrum by evaluating a function at the center wavelength of each bin.
+    pub fn from_fn<F: Fn(f32) -> f32>(spectrum_fn: F) -> Spectrum {
+        let mut values = [0.0; Spectrum::BIN_COUNT];
+        for (index, value) in values.iter_mut().enumerate() {
+            *value = spectrum_fn(Spectrum::bin_center(index));
+        }
+        Spectrum::new(values)
+    }
+
+    /// Creates a spectrum from values evaluated at sampled wavelengths.
+    ///
+    /// The result is a Monte Carlo estimate of the spectrum; averaging the results for many sets of sampled wavelengths converges to the bin averages.
+    pub fn from_wavelength_samples(wavelengths: &SampledWavelengths, values: [f32; SampledWavelengths::COUNT]) -> Spectrum {
+        // Each wavelength is uniformly distributed over the visible range
+        let scale = Spectrum::BIN_COUNT as f32 / SampledWavelengths::COUNT as f32;
+
+        let mut spectrum = Spectrum::default();
+        for (&wavelength, &value) in wavelengths.wavelengths.iter().zip(values.iter()) {
+            if let Some(index) = Spectrum::bin_index(wavelength) {
+                spectrum.values[index] += value * scale;
+            }
+        }
+        spectrum
+    }
+
+    #[inline]
+    pub fn values(&self) -> &[f32; Spectrum::BIN_COUNT] {
+        &self.values
+    }
+
+    /// Returns the index of the bin that contains the given wavelength, or `None` if the wavelength is outside the visible range.
+    #[inline]
+    pub fn bin_index(wavelength: f32) -> Option<usize> {
+        if (Spectrum::MIN_WAVELENGTH..Spectrum::MAX_WAVELENGTH).contains(&wavelength) {
+            Some(usize::min(((wavelength - Spectrum::MIN_WAVELENGTH) / Spectrum::BIN_WIDTH) as usize, Spectrum::BIN_COUNT - 1))
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub fn bin_center(index: usize) -> f32 {
+        Spectrum::MIN_WAVELENGTH + (index as f32 + 0.5) * Spectrum::BIN_WIDTH
+    }
+
+    /// Returns the value of the bin that contains the given wavelength, or zero if the wavelength is outside the visible range.
+    #[inline]
+    pub fn evaluate(&self, wavelength: f32) -> f32 {
+        Spectrum::bin_index(wavelength).map_or(0.0, |index| self.values[index])
+    }
+
+    /// Converts this spectrum to CIE XYZ, normalized so that a constant spectrum with value 1 has luminance Y = 1.
+    pub fn to_xyz(&self) -> Rgb {
+        let weights = Spectrum::xyz_weights();
+
+        let mut xyz = Rgb::BLACK;
+        for (&value, weight) in self.values.iter().zip(weights.iter()) {
+            xyz += *weight * value;
+        }
+        xyz
+    }
+
+    /// Converts this spectrum to a color in the given color space.
+    #[inline]
+    pub fn to_rgb(&self, color_space: &ColorSpace) -> Rgb {
+        color_space.from_xyz(self.to_xyz())
+    }
+
+    /// Returns the color matching functions integrated over each bin.
+    fn xyz_weights() -> &'static [Rgb; Spectrum::BIN_COUNT] {
+        static XYZ_WEIGHTS: OnceLock<[Rgb; Spectrum::BIN_COUNT]> = OnceLock::new();
+
+        XYZ_WEIGHTS.get_or_init(|| {
+            const STEPS_PER_BIN: usize = 16;
+
+            let mut weights = [Rgb::BLACK; Spectrum::BIN_COUNT];
+            for (index, weight) in weights.iter_mut().enumerate() {
+                for step in 0..STEPS_PER_BIN {
+                    let wavelength = Spectrum::MIN_WAVELENGTH + (index as f32 + (step as f32 + 0.5) / STEPS_PER_BIN as f32) * Spectrum::BIN_WIDTH;
+                    *weight += Rgb::new(cie_x(wavelength), cie_y(wavelength), cie_z(wavelength));
+                }
+            }
+
+            let y_integral: f32 = weights.iter().map(|weight| weight.g).sum();
+            for weight in &mut weights {
+                *weight = *weight / y_integral;
+            }
+
+            weights
+        })
+    }
+}
+
+impl Default for Spectrum {
+    #[inline]
+    fn default() -> Spectrum {
+        Spectrum::constant(0.0)
+    }
+}
+
+impl Add for Spectrum {
+    type Output = Spectrum;
+
+    #[inline]
+    fn add(mut self, rhs: Spectrum) -> Spectrum {
+        self += rhs;
+        self
+    }
+}
+
+impl AddAssign for Spectrum {
+    #[inline]
+    fn add_assign(&mut self, rhs: Spectrum) {
+        for (value, rhs_value) in self.values.iter_mut().zip(rhs.values.iter()) {
+            *value += *rhs_value;
+        }
+    }
+}
+
+impl Mul for Spectrum {
+    type Output = Spectrum;
+
+    #[inline]
+    fn mul(mut self, rhs: Spectrum) -> Spectrum {
+        for (value, rhs_value) in self.values.iter_mut().zip(rhs.values.iter()) {
+            *value *= *rhs_value;
+        }
+        self
+    }
+}
+
+impl Mul<f32> for Spectrum {
+    type Output = Spectrum;
+
+    #[inline]
+    fn mul(mut self, rhs: f32) -> Spectrum {
+        for value in &mut self.values {
+            *value *= rhs;
+        }
+        self
+    }
+}
+
+impl Div<f32> for Spectrum {
+    type Output = Spectrum;
+
+    #[inline]
+    fn div(mut self, rhs: f32) -> Spectrum {
+        for value in &mut self.values {
+            *value /= rhs;
+        }
+        self
+    }
+}
+
+impl From<Spectrum> for Vec<f32> {
+    #[inline]
+    fn from(spectrum: Spectrum) -> Vec<f32> {
+        spectrum.values.to_vec()
+    }
+}
+
+impl TryFrom<Vec<f32>> for Spectrum {
+    type Error = String;
+
+    fn try_from(values: Vec<f32>) -> Result<Spectrum, String> {
+        let values: [f32; Spectrum::BIN_COUNT] = values.try_into()
+            .map_err(|values: Vec<f32>| format!("spectrum must have {} values but has {}", Spectrum::BIN_COUNT, values.len()))?;
+        Ok(Spectrum::new(values))
+    }
+}
+
+// ===== SampledWavelengths ====================================================================================================================================
+
+impl SampledWavelengths {
+    pub const COUNT: usize = 4;
+
+    /// Selects wavelengths for hero wavelength sampling, using a sample value in the range 0..1 (see `PixelSample::wavelength_sample()`).
+    pub fn sample(u: f32) -> SampledWavelengths {
+        let range = Spectrum::MAX_WAVELENGTH - Spectrum::MIN_WAVELENGTH;
+
+        let mut wavelengths = [0.0; SampledWavelengths::COUNT];
+        for (index, wavelength) in wavelengths.iter_mut().enumerate() {
+            let v = (u + index as f32 / SampledWavelengths::COUNT as f32).fract();
+            *wavelength = f32::min(Spectrum::MIN_WAVELENGTH + v * range, Spectrum::MAX_WAVELENGTH.next_down());
+        }
+
+        SampledWavelengths { wavelengths }
+    }
+
+    #[inline]
+    pub fn hero(&self) -> f32 {
+        self.wavelengths[0]
+    }
+
+    #[inline]
+    pub fn wavelengths(&self) -> &[f32; SampledWavelengths::COUNT] {
+        &self.wavelengths
+    }
+}
+
+// ===== Raster ================================================================================================================================================
+
+impl<C: Coordinate> Raster<Spectrum, C> {
+    /// Converts all elements of this raster to CIE XYZ.
+    pub fn to_xyz(&self) -> Raster<Rgb, C> {
+        self.map(|spectrum| spectrum.to_xyz())
+    }
+
+    /// Converts all elements of this raster to colors in the given color space.
+    pub fn to_rgb(&self, color_space: &ColorSpace) -> Raster<Rgb, C> {
+        self.map(|spectrum| spectrum.to_rgb(color_space))
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use rand::Rng;
+    use rand_xoshiro::rand_core::SeedableRng;
+    use rand_xoshiro::Xoshiro256PlusPlus;
+
+    use crate::rectangle::Rectangle;
+
+    use super::*;
+
+    #[test]
+    fn spectrum_bin_index() {
+        assert_eq!(Spectrum::bin_index(379.9), None);
+        assert_eq!(Spectrum::bin_index(380.0), Some(0));
+        assert_eq!(Spectrum::bin_index(389.9), Some(0));
+        assert_eq!(Spectrum::bin_index(390.0), Some(1));
+        assert_eq!(Spectrum::bin_index(779.9), Some(Spectrum::BIN_COUNT - 1));
+        assert_eq!(Spectrum::bin_index(780.0), None);
+    }
+
+    #[test]
+    fn spectrum_from_fn() {
+        let spectrum = Spectrum::from_fn(|wavelength| wavelength);
+        assert_eq!(spectrum.values()[0], 385.0);
+        assert_eq!(spectrum.evaluate(777.0), 775.0);
+        assert_eq!(spectrum.evaluate(800.0), 0.0);
+    }
+
+    #[test]
+    fn spectrum_arithmetic() {
+        let s1 = Spectrum::constant(2.0);
+        let s2 = Spectrum::constant(3.0);
+        assert_eq!(s1 + s2, Spectrum::constant(5.0));
+        assert_eq!(s1 * s2, Spectrum::constant(6.0));
+        assert_eq!(s1 * 4.0, Spectrum::constant(8.0));
+        assert_eq!(s2 / 2.0, Spectrum::constant(1.5));
+        assert_eq!(Spectrum::default(), Spectrum::constant(0.0));
+    }
+
+    #[test]
+    fn spectrum_try_from_vec() {
+        let spectrum = Spectrum::try_from(vec![1.0; Spectrum::BIN_COUNT]).unwrap();
+        assert_eq!(spectrum, Spectrum::constant(1.0));
+        assert_eq!(Vec::from(spectrum), vec![1.0; Spectrum::BIN_COUNT]);
+        assert!(Spectrum::try_from(vec![1.0; 3]).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn spectrum_serde() {
+        let spectrum = Spectrum::from_fn(|wavelength| wavelength);
+        let data = bincode::serialize(&spectrum).unwrap();
+        assert_eq!(bincode::deserialize::<Spectrum>(&data).unwrap(), spectrum);
+
+        let data = bincode::serialize(&vec![1.0f32; 3]).unwrap();
+        assert!(bincode::deserialize::<Spectrum>(&data).is_err());
+    }
+
+    #[test]
+    fn spectrum_to_xyz_constant() {
+        // A constant spectrum is the equal-energy white point, which has equal X, Y and Z
+        let xyz = Spectrum::constant(1.0).to_xyz();
+        assert!((xyz.g - 1.0).abs() < 1e-5, "Y is not 1: {:?}", xyz);
+        assert!((xyz.r - 1.0).abs() < 0.02, "X is not close to 1: {:?}", xyz);
+        assert!((xyz.b - 1.0).abs() < 0.02, "Z is not close to 1: {:?}", xyz);
+    }
+
+    #[test]
+    fn spectrum_to_rgb() {
+        // Long wavelengths are red
+        let rgb = Spectrum::from_fn(|wavelength| if wavelength > 620.0 { 1.0 } else { 0.0 }).to_rgb(&ColorSpace::LINEAR_SRGB);
+        assert!(rgb.r > rgb.g && rgb.r > rgb.b, "not red: {:?}", rgb);
+    }
+
+    #[test]
+    fn sampled_wavelengths_sample() {
+        let wavelengths = SampledWavelengths::sample(0.0);
+        assert_eq!(wavelengths.wavelengths(), &[380.0, 480.0, 580.0, 680.0]);
+
+        let wavelengths = SampledWavelengths::sample(0.5);
+        assert_eq!(wavelengths.hero(), 580.0);
+        assert_eq!(wavelengths.wavelengths(), &[580.0, 680.0, 380.0, 480.0]);
+
+        for wavelength in SampledWavelengths::sample(0.99999999).wavelengths() {
+            assert!(Spectrum::bin_index(*wavelength).is_some(), "wavelength out of range: {}", wavelength);
+        }
+    }
+
+    #[test]
+    fn spectrum_from_wavelength_samples_converges() {
+        let reference = Spectrum::from_fn(|wavelength| wavelength / 780.0);
+
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(1);
+        let mut sum = Spectrum::default();
+        const N: usize = 100000;
+        for _ in 0..N {
+            let wavelengths = SampledWavelengths::sample(rng.gen());
+            let values = wavelengths.wavelengths().map(|wavelength| reference.evaluate(wavelength));
+            sum += Spectrum::from_wavelength_samples(&wavelengths, values);
+        }
+
+        let estimate = (sum / N as f32).to_xyz();
+        let expected = reference.to_xyz();
+        assert!((estimate.g - expected.g).abs() < 0.01, "{:?} != {:?}", estimate, expected);
+    }
+
+    #[test]
+    fn raster_spectrum_to_rgb() {
+        let mut raster = Raster::<Spectrum>::new(Rectangle::new(0, 0, 2, 2));
+        raster.set(1, 1, Spectrum::constant(1.0));
+
+        let xyz = raster.to_xyz();
+        assert!((xyz.get(1, 1).g - 1.0).abs() < 1e-5);
+        assert_eq!(xyz.get(0, 0), Rgb::BLACK);
+
+        let rgb = raster.to_rgb(&ColorSpace::LINEAR_SRGB);
+        assert!((rgb.get(1, 1).luminance() - 1.0).abs() < 0.01);
+    }
+}
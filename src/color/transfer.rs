@@ -0,0 +1,119 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::color::Rgb;
+
+/// Transfer function (opto-electronic transfer function) that converts between linear values and encoded values.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TransferFunction {
+    /// No transfer function; encoded values are equal to linear values.
+    Linear,
+    /// The piecewise sRGB transfer function (IEC 61966-2-1).
+    Srgb,
+    /// The ITU-R BT.709 opto-electronic transfer function.
+    Rec709,
+    /// The ITU-R BT.2020 opto-electronic transfer function.
+    Rec2020,
+    /// A pure power law with the given gamma.
+    Gamma(f32),
+}
+
+// ===== TransferFunction ======================================================================================================================================
+
+impl TransferFunction {
+    const REC2020_ALPHA: f32 = 1.0992968;
+    const REC2020_BETA: f32 = 0.018053968;
+
+    /// Converts a linear value to an encoded value.
+    ///
+    /// Negative values are encoded by mirroring the transfer function around zero.
+    pub fn encode(&self, v: f32) -> f32 {
+        let a = v.abs();
+        let e = match *self {
+            TransferFunction::Linear => a,
+            TransferFunction::Srgb => if a <= 0.0031308 { 12.92 * a } else { 1.055 * a.powf(1.0 / 2.4) - 0.055 },
+            TransferFunction::Rec709 => if a < 0.018 { 4.5 * a } else { 1.099 * a.powf(0.45) - 0.099 },
+            TransferFunction::Rec2020 => {
+                if a < Self::REC2020_BETA { 4.5 * a } else { Self::REC2020_ALPHA * a.powf(0.45) - (Self::REC2020_ALPHA - 1.0) }
+            }
+            TransferFunction::Gamma(gamma) => a.powf(gamma.recip()),
+        };
+        e.copysign(v)
+    }
+
+    /// Converts an encoded value to a linear value.
+    ///
+    /// Negative values are decoded by mirroring the transfer function around zero.
+    pub fn decode(&self, v: f32) -> f32 {
+        let a = v.abs();
+        let d = match *self {
+            TransferFunction::Linear => a,
+            TransferFunction::Srgb => if a <= 0.04045 { a / 12.92 } else { ((a + 0.055) / 1.055).powf(2.4) },
+            TransferFunction::Rec709 => if a < 0.081 { a / 4.5 } else { ((a + 0.099) / 1.099).powf(1.0 / 0.45) },
+            TransferFunction::Rec2020 => {
+                if a < 4.5 * Self::REC2020_BETA { a / 4.5 } else { ((a + (Self::REC2020_ALPHA - 1.0)) / Self::REC2020_ALPHA).powf(1.0 / 0.45) }
+            }
+            TransferFunction::Gamma(gamma) => a.powf(gamma),
+        };
+        d.copysign(v)
+    }
+
+    #[inline]
+    pub fn encode_rgb(&self, color: Rgb) -> Rgb {
+        color.map(|v| self.encode(v))
+    }
+
+    #[inline]
+    pub fn decode_rgb(&self, color: Rgb) -> Rgb {
+        color.map(|v| self.decode(v))
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ALL: [TransferFunction; 5] =
+        [TransferFunction::Linear, TransferFunction::Srgb, TransferFunction::Rec709, TransferFunction::Rec2020, TransferFunction::Gamma(2.2)];
+
+    #[test]
+    fn transfer_function_encode() {
+        assert_eq!(TransferFunction::Linear.encode(0.25), 0.25);
+        assert!((TransferFunction::Srgb.encode(0.5) - 0.7353569).abs() < 1e-6);
+        assert!((TransferFunction::Srgb.encode(0.001) - 0.01292).abs() < 1e-6);
+        assert!((TransferFunction::Rec709.encode(0.5) - 0.705515).abs() < 1e-6);
+        assert!((TransferFunction::Gamma(2.0).encode(0.25) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn transfer_function_end_points() {
+        for transfer in ALL {
+            assert_eq!(transfer.encode(0.0), 0.0, "{:?} does not map 0 to 0", transfer);
+            assert!((transfer.encode(1.0) - 1.0).abs() < 1e-5, "{:?} does not map 1 to 1", transfer);
+        }
+    }
+
+    #[test]
+    fn transfer_function_round_trip() {
+        for transfer in ALL {
+            for i in -10..=20 {
+                let v = i as f32 / 10.0;
+                let r = transfer.decode(transfer.encode(v));
+                assert!((r - v).abs() < 1e-5, "{:?} round trip failed for {}: {}", transfer, v, r);
+            }
+        }
+    }
+}
@@ -16,6 +16,7 @@ use crate::filter::Filter;
 
 /// Box filter.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BoxFilter {
     radius_x: f32,
     radius_y: f32,
@@ -18,15 +18,26 @@ use crate::filter::Filter;
 
 /// Gaussian filter.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "GaussianFilterParameters", from = "GaussianFilterParameters"))]
 pub struct GaussianFilter {
     radius_x: f32,
     radius_y: f32,
+    sigma: f32,
     two_sigma_sq: f32,
     scale: f32,
     exp_x: f32,
     exp_y: f32,
 }
 
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct GaussianFilterParameters {
+    radius_x: f32,
+    radius_y: f32,
+    sigma: f32,
+}
+
 // ===== GaussianFilter ========================================================================================================================================
 
 impl GaussianFilter {
@@ -36,7 +47,7 @@ impl GaussianFilter {
         let exp_x = scale * f32::exp(-(radius_x * radius_x) / two_sigma_sq);
         let exp_y = scale * f32::exp(-(radius_y * radius_y) / two_sigma_sq);
 
-        GaussianFilter { radius_x, radius_y, two_sigma_sq, scale, exp_x, exp_y }
+        GaussianFilter { radius_x, radius_y, sigma, two_sigma_sq, scale, exp_x, exp_y }
     }
 
     #[inline]
@@ -44,6 +55,11 @@ impl GaussianFilter {
         GaussianFilter::new(1.5, 1.5, 0.5)
     }
 
+    #[inline]
+    pub fn sigma(&self) -> f32 {
+        self.sigma
+    }
+
     #[inline]
     fn gaussian(&self, v: f32) -> f32 {
         self.scale * f32::exp(-(v * v) / self.two_sigma_sq)
@@ -62,6 +78,22 @@ impl Filter for GaussianFilter {
     }
 }
 
+#[cfg(feature = "serde")]
+impl From<GaussianFilter> for GaussianFilterParameters {
+    #[inline]
+    fn from(filter: GaussianFilter) -> GaussianFilterParameters {
+        GaussianFilterParameters { radius_x: filter.radius_x, radius_y: filter.radius_y, sigma: filter.sigma }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<GaussianFilterParameters> for GaussianFilter {
+    #[inline]
+    fn from(parameters: GaussianFilterParameters) -> GaussianFilter {
+        GaussianFilter::new(parameters.radius_x, parameters.radius_y, parameters.sigma)
+    }
+}
+
 // ===== Tests =================================================================================================================================================
 
 #[cfg(test)]
@@ -73,6 +105,7 @@ mod test {
         let filter = GaussianFilter::new(1.0, 2.0, 1.5);
         assert_eq!(filter.radius_x, 1.0, "radius_x is incorrect");
         assert_eq!(filter.radius_y, 2.0, "radius_y is incorrect");
+        assert_eq!(filter.sigma(), 1.5, "sigma is incorrect");
         assert_eq!(filter.two_sigma_sq, 2.0 * 1.5 * 1.5, "two_sigma_sq is incorrect");
         assert_eq!(filter.scale, f32::sqrt(PI * filter.two_sigma_sq).recip(), "scale is incorrect");
         assert_eq!(filter.exp_x, filter.scale * f32::exp(-1.0 / filter.two_sigma_sq), "exp_x is incorrect");
@@ -108,6 +141,17 @@ mod test {
         assert_eq!(filter.evaluate(0.0, 2.001), 0.0);
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn gaussian_filter_serde() {
+        let filter = GaussianFilter::new(1.0, 2.0, 1.5);
+        let data = bincode::serialize(&filter).unwrap();
+        assert_eq!(data.len(), 3 * 4, "only the parameters are serialized");
+
+        let result: GaussianFilter = bincode::deserialize(&data).unwrap();
+        assert_eq!(result.evaluate(0.5, 0.5), filter.evaluate(0.5, 0.5));
+    }
+
     #[test]
     fn gaussian_filter_is_debug() {
         let filter = GaussianFilter::with_defaults();
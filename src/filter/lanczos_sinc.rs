@@ -18,6 +18,7 @@ use crate::filter::Filter;
 
 /// Windowed sinc filter.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LanczosSincFilter {
     radius_x: f32,
     radius_y: f32,
@@ -16,13 +16,26 @@ use crate::filter::Filter;
 
 /// Mitchell-Netravali filter.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "MitchellFilterParameters", from = "MitchellFilterParameters"))]
 pub struct MitchellFilter {
     radius_x: f32,
     radius_y: f32,
+    b: f32,
+    c: f32,
     p1: [f32; 4],
     p2: [f32; 4],
 }
 
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct MitchellFilterParameters {
+    radius_x: f32,
+    radius_y: f32,
+    b: f32,
+    c: f32,
+}
+
 // ===== MitchellFilter ========================================================================================================================================
 
 impl MitchellFilter {
@@ -30,7 +43,7 @@ impl MitchellFilter {
         let p1 = [1.0 - b / 3.0, 0.0, -3.0 + 2.0 * b + c, 2.0 - 1.5 * b - c];
         let p2 = [4.0 / 3.0 * b + 4.0 * c, -2.0 * b - 8.0 * c, b + 5.0 * c, -b / 6.0 - c];
 
-        MitchellFilter { radius_x, radius_y, p1, p2 }
+        MitchellFilter { radius_x, radius_y, b, c, p1, p2 }
     }
 
     #[inline]
@@ -38,6 +51,16 @@ impl MitchellFilter {
         MitchellFilter::new(2.0, 2.0, 1.0 / 3.0, 1.0 / 3.0)
     }
 
+    #[inline]
+    pub fn b(&self) -> f32 {
+        self.b
+    }
+
+    #[inline]
+    pub fn c(&self) -> f32 {
+        self.c
+    }
+
     #[inline]
     fn mitchell(&self, v: f32) -> f32 {
         let x = 2.0 * v.abs();
@@ -63,6 +86,22 @@ impl Filter for MitchellFilter {
     }
 }
 
+#[cfg(feature = "serde")]
+impl From<MitchellFilter> for MitchellFilterParameters {
+    #[inline]
+    fn from(filter: MitchellFilter) -> MitchellFilterParameters {
+        MitchellFilterParameters { radius_x: filter.radius_x, radius_y: filter.radius_y, b: filter.b, c: filter.c }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<MitchellFilterParameters> for MitchellFilter {
+    #[inline]
+    fn from(parameters: MitchellFilterParameters) -> MitchellFilter {
+        MitchellFilter::new(parameters.radius_x, parameters.radius_y, parameters.b, parameters.c)
+    }
+}
+
 // ===== Tests =================================================================================================================================================
 
 #[cfg(test)]
@@ -74,6 +113,7 @@ mod test {
         let filter = MitchellFilter::new(1.0, 2.0, 0.5, 0.75);
         assert_eq!(filter.radius_x, 1.0, "radius_x is incorrect");
         assert_eq!(filter.radius_y, 2.0, "radius_y is incorrect");
+        assert_eq!((filter.b(), filter.c()), (0.5, 0.75), "b and c are incorrect");
         assert_eq!(filter.p1, [0.8333333, 0.0, -1.25, 0.5], "p1 is incorrect");
         assert_eq!(filter.p2, [3.6666667, -7.0, 4.25, -0.8333333], "p2 is incorrect");
     }
@@ -108,6 +148,18 @@ mod test {
         assert_eq!(filter.evaluate(0.0, 2.001), 0.0);
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn mitchell_filter_serde() {
+        let filter = MitchellFilter::new(1.0, 2.0, 0.5, 0.75);
+        let data = bincode::serialize(&filter).unwrap();
+        assert_eq!(data.len(), 4 * 4, "only the parameters are serialized");
+
+        let result: MitchellFilter = bincode::deserialize(&data).unwrap();
+        assert_eq!(result.p1, filter.p1);
+        assert_eq!(result.p2, filter.p2);
+    }
+
     #[test]
     fn mitchell_filter_is_debug() {
         let filter = MitchellFilter::with_defaults();
@@ -0,0 +1,267 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{BufRead, Error, ErrorKind, Read, Result};
+
+#[cfg(feature = "exr")]
+pub use self::exr::*;
+pub use pfm::*;
+#[cfg(feature = "png")]
+pub use self::png::*;
+pub use pnm::*;
+pub use pyramid::*;
+pub use quantize::*;
+
+use crate::color::{Rgb, Rgba};
+
+mod pnm;
+mod pfm;
+mod pyramid;
+mod quantize;
+#[cfg(feature = "png")]
+mod png;
+#[cfg(feature = "exr")]
+mod exr;
+
+/// Element type of a raster that can be written to and read from image files.
+///
+/// Image files store a fixed number of channels per pixel; this trait converts elements to and from channel values.
+pub trait Pixel: Copy + Default {
+    /// Number of channels per pixel.
+    const CHANNEL_COUNT: usize;
+
+    /// Returns the value of the channel with the given index.
+    fn channel(&self, index: usize) -> f32;
+
+    /// Creates an element from channel values; `channels` contains `CHANNEL_COUNT` values.
+    fn from_channels(channels: &[f32]) -> Self;
+}
+
+/// Number of bits per channel for integer image formats.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum BitDepth {
+    Eight,
+    Sixteen,
+}
+
+// ===== Pixel =================================================================================================================================================
+
+impl Pixel for f32 {
+    const CHANNEL_COUNT: usize = 1;
+
+    #[inline]
+    fn channel(&self, _index: usize) -> f32 {
+        *self
+    }
+
+    #[inline]
+    fn from_channels(channels: &[f32]) -> f32 {
+        channels[0]
+    }
+}
+
+impl Pixel for Rgb {
+    const CHANNEL_COUNT: usize = 3;
+
+    #[inline]
+    fn channel(&self, index: usize) -> f32 {
+        match index {
+            0 => self.r,
+            1 => self.g,
+            _ => self.b,
+        }
+    }
+
+    #[inline]
+    fn from_channels(channels: &[f32]) -> Rgb {
+        Rgb::new(channels[0], channels[1], channels[2])
+    }
+}
+
+impl Pixel for Rgba {
+    const CHANNEL_COUNT: usize = 4;
+
+    #[inline]
+    fn channel(&self, index: usize) -> f32 {
+        match index {
+            0 => self.r,
+            1 => self.g,
+            2 => self.b,
+            _ => self.a,
+        }
+    }
+
+    #[inline]
+    fn from_channels(channels: &[f32]) -> Rgba {
+        Rgba::new(channels[0], channels[1], channels[2], channels[3])
+    }
+}
+
+/// A pair of elements, for example a weighted sum and its weight, has the channels of the first element followed by the channels of the second element.
+impl<A: Pixel, B: Pixel> Pixel for (A, B) {
+    const CHANNEL_COUNT: usize = A::CHANNEL_COUNT + B::CHANNEL_COUNT;
+
+    #[inline]
+    fn channel(&self, index: usize) -> f32 {
+        if index < A::CHANNEL_COUNT { self.0.channel(index) } else { self.1.channel(index - A::CHANNEL_COUNT) }
+    }
+
+    #[inline]
+    fn from_channels(channels: &[f32]) -> (A, B) {
+        (A::from_channels(&channels[..A::CHANNEL_COUNT]), B::from_channels(&channels[A::CHANNEL_COUNT..]))
+    }
+}
+
+// ===== BitDepth ==============================================================================================================================================
+
+impl BitDepth {
+    #[inline]
+    pub fn max_value(&self) -> u32 {
+        match self {
+            BitDepth::Eight => 255,
+            BitDepth::Sixteen => 65535,
+        }
+    }
+
+    /// Quantizes a value in the range 0..1 to an integer in the range 0..max_value; values outside the range are clamped.
+    #[inline]
+    pub fn quantize(&self, value: f32) -> u32 {
+        let max_value = self.max_value();
+        if value.is_nan() { 0 } else { (value.clamp(0.0, 1.0) * max_value as f32).round() as u32 }
+    }
+
+    /// Converts an integer in the range 0..max_value to a value in the range 0..1.
+    #[inline]
+    pub fn dequantize(&self, value: u32) -> f32 {
+        value as f32 / self.max_value() as f32
+    }
+}
+
+// ===== Header parsing ========================================================================================================================================
+
+/// Reads the next whitespace-separated token from a Netpbm-style header, skipping comments. Consumes exactly one whitespace character after the token.
+fn read_header_token<R: BufRead>(reader: &mut R) -> Result<String> {
+    let mut token = String::new();
+    let mut byte = [0u8];
+
+    loop {
+        if reader.read(&mut byte)? == 0 {
+            return if token.is_empty() { Err(invalid_data("unexpected end of header")) } else { Ok(token) };
+        }
+
+        let c = byte[0];
+        if c == b'#' && token.is_empty() {
+            // Skip comment until end of line
+            let mut comment = Vec::new();
+            reader.read_until(b'\n', &mut comment)?;
+        } else if c.is_ascii_whitespace() {
+            if !token.is_empty() {
+                return Ok(token);
+            }
+        } else {
+            token.push(c as char);
+        }
+    }
+}
+
+fn read_header_number<R: BufRead>(reader: &mut R, name: &str) -> Result<u32> {
+    let token = read_header_token(reader)?;
+    token.parse().map_err(|_| invalid_data(&format!("invalid {} in header: {}", name, token)))
+}
+
+/// Checks that the image size read from a header fits in memory, and returns the number of bytes of a row of pixel data.
+fn check_image_size<T: Pixel>(width: u32, height: u32, bytes_per_value: usize) -> Result<usize> {
+    let row_size = (width as usize).checked_mul(T::CHANNEL_COUNT * bytes_per_value);
+    let raster_size = (width as usize).checked_mul(height as usize).and_then(|size| size.checked_mul(std::mem::size_of::<T>()));
+    match (row_size, raster_size) {
+        (Some(row_size), Some(_)) => Ok(row_size),
+        _ => Err(invalid_data(&format!("image size too large: {} x {}", width, height))),
+    }
+}
+
+/// Reads a row of pixel data into the buffer. The buffer grows as data is read, so that a header with a huge image size does not cause a huge allocation
+/// before it is clear that the data is actually there.
+fn read_row<R: Read>(reader: &mut R, row_size: usize, data: &mut Vec<u8>) -> Result<()> {
+    data.clear();
+    reader.take(row_size as u64).read_to_end(data)?;
+    if data.len() < row_size {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "unexpected end of image data"));
+    }
+    Ok(())
+}
+
+#[inline]
+fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn pixel_pair() {
+        let pair = (Rgb::new(1.0, 2.0, 3.0), 4.0f32);
+        assert_eq!(<(Rgb, f32)>::CHANNEL_COUNT, 4);
+        assert_eq!((0..4).map(|index| pair.channel(index)).collect::<Vec<_>>(), vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(<(Rgb, f32)>::from_channels(&[1.0, 2.0, 3.0, 4.0]), pair);
+    }
+
+    #[test]
+    fn bit_depth_quantize() {
+        assert_eq!(BitDepth::Eight.quantize(0.0), 0);
+        assert_eq!(BitDepth::Eight.quantize(1.0), 255);
+        assert_eq!(BitDepth::Eight.quantize(0.5), 128);
+        assert_eq!(BitDepth::Eight.quantize(-1.0), 0);
+        assert_eq!(BitDepth::Eight.quantize(2.0), 255);
+        assert_eq!(BitDepth::Eight.quantize(f32::NAN), 0);
+        assert_eq!(BitDepth::Sixteen.quantize(1.0), 65535);
+    }
+
+    #[test]
+    fn bit_depth_dequantize() {
+        assert_eq!(BitDepth::Eight.dequantize(255), 1.0);
+        assert_eq!(BitDepth::Sixteen.dequantize(0), 0.0);
+    }
+
+    #[test]
+    fn read_header_token_skips_comments() {
+        let mut reader = Cursor::new(b"P6\n# comment 1 2 3\n  640 480\n255\nDATA".to_vec());
+        assert_eq!(read_header_token(&mut reader).unwrap(), "P6");
+        assert_eq!(read_header_number(&mut reader, "width").unwrap(), 640);
+        assert_eq!(read_header_number(&mut reader, "height").unwrap(), 480);
+        assert_eq!(read_header_number(&mut reader, "maximum value").unwrap(), 255);
+
+        let mut rest = String::new();
+        reader.read_line(&mut rest).unwrap();
+        assert_eq!(rest, "DATA");
+    }
+
+    #[test]
+    fn read_header_number_invalid() {
+        let mut reader = Cursor::new(b"abc ".to_vec());
+        assert_eq!(read_header_number(&mut reader, "width").unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn check_image_size_limits() {
+        assert_eq!(check_image_size::<Rgb>(640, 480, 2).unwrap(), 640 * 3 * 2);
+        assert_eq!(check_image_size::<f32>(1 << 31, 1, 1).unwrap(), 1 << 31);
+        assert_eq!(check_image_size::<f32>(u32::MAX, u32::MAX, 1).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,262 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{Error, ErrorKind, Result, Seek, Write};
+
+use ::exr::prelude::{AnyChannel, AnyChannels, Encoding, FlatSamples, Image, ImageAttributes, IntegerBounds, Layer, LayerAttributes, SmallVec, Vec2,
+                     WritableImage};
+use ::exr::prelude::f16;
+
+use crate::io::Pixel;
+use crate::raster::Raster;
+use crate::rectangle::{Coordinate, Rectangle};
+
+/// Floating point type used to store the samples of an OpenEXR image.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ExrSampleType {
+    Half,
+    Float,
+}
+
+/// OpenEXR image with one or more channels, built from rasters that all have the same rectangle.
+///
+/// The rectangle of the rasters becomes the data window of the image. Channels of a layer are named `<layer>.<channel>`, for example `diffuse.R`; channels
+/// added with an empty layer name (such as the beauty pass) are named after the channel only.
+#[derive(Clone, Debug)]
+pub struct ExrImage<C: Coordinate = u32> {
+    rectangle: Rectangle<C>,
+    display_window: Rectangle<C>,
+    sample_type: ExrSampleType,
+    channels: Vec<(String, Vec<f32>)>,
+}
+
+// ===== ExrImage ==============================================================================================================================================
+
+impl<C: Coordinate> ExrImage<C> {
+    /// Creates an image without channels. The display window is initially equal to the data window.
+    pub fn new(rectangle: Rectangle<C>, sample_type: ExrSampleType) -> ExrImage<C> {
+        let display_window = rectangle.clone();
+        ExrImage { rectangle, display_window, sample_type, channels: Vec::new() }
+    }
+
+    /// Sets the display window, for example to mark the data outside the display window as overscan.
+    #[inline]
+    pub fn with_display_window(self, display_window: Rectangle<C>) -> ExrImage<C> {
+        ExrImage { display_window, ..self }
+    }
+
+    /// Adds the channels of a raster as a layer. The number of channel names must be equal to the number of channels of the raster's elements.
+    ///
+    /// Returns an error if the rectangle of the raster does not match the data window of the image, if the number of channel names is wrong, or if a channel
+    /// with the same name has already been added.
+    pub fn add_layer<T: Pixel>(&mut self, layer_name: &str, channel_names: &[&str], raster: &Raster<T, C>) -> Result<()> {
+        if *raster.rectangle() != self.rectangle {
+            return Err(Error::new(ErrorKind::InvalidInput, "rectangle of raster does not match the data window of the image"));
+        }
+        if channel_names.len() != T::CHANNEL_COUNT {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("{} channel names given for {} channels", channel_names.len(), T::CHANNEL_COUNT)));
+        }
+
+        let names: Vec<String> = channel_names.iter()
+            .map(|channel_name| if layer_name.is_empty() { channel_name.to_string() } else { format!("{}.{}", layer_name, channel_name) })
+            .collect();
+        for (index, name) in names.iter().enumerate() {
+            if names[..index].contains(name) || self.channel_names().any(|existing| existing == name) {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("duplicate channel name: {}", name)));
+            }
+        }
+
+        for (index, name) in names.into_iter().enumerate() {
+            let samples = self.rectangle.index_iter().map(|(x, y)| raster.get(x, y).channel(index)).collect();
+            self.channels.push((name, samples));
+        }
+
+        Ok(())
+    }
+
+    /// Adds an RGB raster as a layer with the channels R, G and B.
+    #[inline]
+    pub fn add_rgb_layer<T: Pixel>(&mut self, layer_name: &str, raster: &Raster<T, C>) -> Result<()> {
+        self.add_layer(layer_name, &["R", "G", "B"], raster)
+    }
+
+    #[inline]
+    pub fn rectangle(&self) -> &Rectangle<C> {
+        &self.rectangle
+    }
+
+    #[inline]
+    pub fn channel_names(&self) -> impl Iterator<Item=&str> {
+        self.channels.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Writes this image as a single-part scanline OpenEXR file.
+    pub fn write<W: Write + Seek>(&self, writer: W) -> Result<()> {
+        if self.channels.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "image does not contain any channels"));
+        }
+
+        let channels: SmallVec<[AnyChannel<FlatSamples>; 4]> = self.channels.iter()
+            .map(|(name, samples)| {
+                let samples = match self.sample_type {
+                    ExrSampleType::Half => FlatSamples::F16(samples.iter().map(|&sample| f16::from_f32(sample)).collect()),
+                    ExrSampleType::Float => FlatSamples::F32(samples.clone()),
+                };
+                AnyChannel::new(name.as_str(), samples)
+            })
+            .collect();
+
+        let attributes = LayerAttributes { layer_position: to_vec2(&self.rectangle), ..LayerAttributes::default() };
+        let size = (self.rectangle.width() as usize, self.rectangle.height() as usize);
+        let layer = Layer::new(size, attributes, Encoding::default(), AnyChannels::sort(channels));
+
+        let display_window = IntegerBounds::new(to_vec2(&self.display_window),
+                                                (self.display_window.width() as usize, self.display_window.height() as usize));
+        let image = Image::new(ImageAttributes::new(display_window), layer);
+
+        image.write().to_buffered(writer).map_err(to_io_error)
+    }
+}
+
+#[inline]
+fn to_vec2<C: Coordinate>(rectangle: &Rectangle<C>) -> Vec2<i32> {
+    Vec2(rectangle.left.to_i64() as i32, rectangle.top.to_i64() as i32)
+}
+
+fn to_io_error(error: ::exr::error::Error) -> Error {
+    match error {
+        ::exr::error::Error::Io(error) => error,
+        error => Error::new(ErrorKind::InvalidInput, error.to_string()),
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use ::exr::prelude::{ReadChannels, ReadLayers, read};
+
+    use crate::color::Rgb;
+
+    use super::*;
+
+    fn read_image(data: Vec<u8>) -> Image<SmallVec<[Layer<AnyChannels<FlatSamples>>; 2]>> {
+        read().no_deep_data().largest_resolution_level().all_channels().all_layers().all_attributes().from_buffered(Cursor::new(data)).unwrap()
+    }
+
+    #[test]
+    fn exr_image_write_channels() {
+        let rectangle = Rectangle::new(10, 20, 14, 23);
+
+        let mut beauty = Raster::<Rgb>::new(rectangle.clone());
+        beauty.set(11, 21, Rgb::new(1.5, 2.5, 100.0));
+        let mut depth = Raster::<f32>::new(rectangle.clone());
+        depth.set(13, 22, 42.0);
+
+        let mut image = ExrImage::new(rectangle, ExrSampleType::Float);
+        image.add_rgb_layer("", &beauty).unwrap();
+        image.add_layer("depth", &["Z"], &depth).unwrap();
+        assert_eq!(image.channel_names().collect::<Vec<_>>(), vec!["R", "G", "B", "depth.Z"]);
+
+        let mut data = Cursor::new(Vec::new());
+        image.write(&mut data).unwrap();
+
+        let result = read_image(data.into_inner());
+        assert_eq!(result.attributes.display_window, IntegerBounds::new(Vec2(10, 20), (4, 3)));
+
+        let layer = &result.layer_data[0];
+        assert_eq!(layer.attributes.layer_position, Vec2(10, 20));
+        assert_eq!(layer.size, Vec2(4, 3));
+
+        let channel = |name: &str| layer.channel_data.list.iter().find(|channel| channel.name == *name).unwrap();
+        let r: Vec<f32> = channel("R").sample_data.values_as_f32().collect();
+        let z: Vec<f32> = channel("depth.Z").sample_data.values_as_f32().collect();
+        assert_eq!(r[4 + 1], 1.5);
+        assert_eq!(z[2 * 4 + 3], 42.0);
+    }
+
+    #[test]
+    fn exr_image_write_half() {
+        let rectangle = Rectangle::new(0, 0, 2, 2);
+        let mut raster = Raster::<f32>::new(rectangle.clone());
+        raster.set(1, 1, 0.25);
+
+        let mut image = ExrImage::new(rectangle.clone(), ExrSampleType::Half).with_display_window(Rectangle::new(0, 0, 4, 4));
+        image.add_layer("", &["Y"], &raster).unwrap();
+
+        let mut data = Cursor::new(Vec::new());
+        image.write(&mut data).unwrap();
+
+        let result = read_image(data.into_inner());
+        assert_eq!(result.attributes.display_window, IntegerBounds::new(Vec2(0, 0), (4, 4)));
+
+        let samples = &result.layer_data[0].channel_data.list[0].sample_data;
+        assert!(matches!(samples, FlatSamples::F16(_)));
+        assert_eq!(samples.values_as_f32().nth(3), Some(0.25));
+    }
+
+    #[test]
+    fn exr_image_write_overscan() {
+        let rectangle = Rectangle::<i32>::new(-2, -2, 6, 6);
+        let mut raster = Raster::<f32, i32>::new(rectangle.clone());
+        raster.set(-2, -1, 3.0);
+
+        let mut image = ExrImage::new(rectangle, ExrSampleType::Float).with_display_window(Rectangle::new(0, 0, 4, 4));
+        image.add_layer("", &["Y"], &raster).unwrap();
+
+        let mut data = Cursor::new(Vec::new());
+        image.write(&mut data).unwrap();
+
+        let result = read_image(data.into_inner());
+        assert_eq!(result.attributes.display_window, IntegerBounds::new(Vec2(0, 0), (4, 4)));
+
+        let layer = &result.layer_data[0];
+        assert_eq!(layer.attributes.layer_position, Vec2(-2, -2));
+        assert_eq!(layer.channel_data.list[0].sample_data.values_as_f32().nth(8), Some(3.0));
+    }
+
+    #[test]
+    fn exr_image_write_empty() {
+        let image = ExrImage::new(Rectangle::new(0, 0, 2, 2), ExrSampleType::Float);
+        assert!(image.write(Cursor::new(Vec::new())).is_err());
+    }
+
+    #[test]
+    fn exr_image_add_layer_rectangle_mismatch() {
+        let mut image = ExrImage::new(Rectangle::new(0, 0, 2, 2), ExrSampleType::Float);
+        let result = image.add_layer("", &["Y"], &Raster::<f32>::new(Rectangle::new(0, 0, 3, 2)));
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn exr_image_add_layer_channel_count_mismatch() {
+        let mut image = ExrImage::new(Rectangle::new(0, 0, 2, 2), ExrSampleType::Float);
+        let result = image.add_layer("", &["R", "G"], &Raster::<Rgb>::new(Rectangle::new(0, 0, 2, 2)));
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(image.channel_names().count(), 0);
+    }
+
+    #[test]
+    fn exr_image_add_layer_duplicate_names() {
+        let rectangle = Rectangle::new(0, 0, 2, 2);
+        let mut image = ExrImage::new(rectangle.clone(), ExrSampleType::Float);
+        image.add_rgb_layer("diffuse", &Raster::<Rgb>::new(rectangle.clone())).unwrap();
+
+        assert_eq!(image.add_rgb_layer("diffuse", &Raster::<Rgb>::new(rectangle.clone())).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(image.add_layer("", &["Y", "Y"], &Raster::<(f32, f32)>::new(rectangle.clone())).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(image.channel_names().collect::<Vec<_>>(), vec!["diffuse.R", "diffuse.G", "diffuse.B"]);
+    }
+}
@@ -0,0 +1,180 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+
+use crate::io::{check_image_size, invalid_data, Pixel, read_header_number, read_header_token, read_row};
+use crate::raster::Raster;
+use crate::rectangle::{Coordinate, Rectangle};
+
+/// Writes a raster as a little-endian PFM (portable float map) image, which stores 32-bit floating point values without loss.
+///
+/// Single-channel rasters are written as grayscale (Pf) and three-channel rasters as color (PF) images. The offset of the raster's rectangle is not stored.
+pub fn write_pfm<W: Write, T: Pixel, C: Coordinate>(writer: W, raster: &Raster<T, C>) -> Result<()> {
+    let magic = pfm_magic(T::CHANNEL_COUNT)?;
+    let rectangle = raster.rectangle();
+
+    let mut writer = BufWriter::new(writer);
+    write!(writer, "{}\n{} {}\n-1.0\n", magic, rectangle.width(), rectangle.height())?;
+
+    // PFM stores rows from bottom to top
+    for y in (rectangle.top.to_i64()..rectangle.bottom.to_i64()).rev() {
+        for element in raster.row(C::from_i64(y)) {
+            for index in 0..T::CHANNEL_COUNT {
+                writer.write_all(&element.channel(index).to_le_bytes())?;
+            }
+        }
+    }
+
+    writer.flush()
+}
+
+/// Reads a PFM (portable float map) image into a raster with its rectangle at the origin.
+///
+/// The image type must match the element type of the raster: Pf for single-channel and PF for three-channel elements. Both byte orders are supported.
+pub fn read_pfm<R: Read, T: Pixel>(reader: R) -> Result<Raster<T>> {
+    let expected_magic = pfm_magic(T::CHANNEL_COUNT)?;
+
+    let mut reader = BufReader::new(reader);
+    let magic = read_header_token(&mut reader)?;
+    if magic != expected_magic {
+        return Err(invalid_data(&format!("unsupported image type: {} (expected {})", magic, expected_magic)));
+    }
+
+    let width = read_header_number(&mut reader, "width")?;
+    let height = read_header_number(&mut reader, "height")?;
+    let scale_token = read_header_token(&mut reader)?;
+    let scale: f32 = scale_token.parse().map_err(|_| invalid_data(&format!("invalid scale in header: {}", scale_token)))?;
+    if scale == 0.0 || !scale.is_finite() {
+        return Err(invalid_data(&format!("invalid scale in header: {}", scale_token)));
+    }
+    let little_endian = scale < 0.0;
+
+    let row_size = check_image_size::<T>(width, height, 4)?;
+
+    // Elements are only allocated for rows that have actually been read
+    let mut elements = Vec::new();
+    let mut data = Vec::new();
+    let mut channels = vec![0.0; T::CHANNEL_COUNT];
+
+    for _ in 0..height {
+        read_row(&mut reader, row_size, &mut data)?;
+
+        let mut values = data.chunks_exact(4).map(|bytes| {
+            let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            if little_endian { f32::from_le_bytes(bytes) } else { f32::from_be_bytes(bytes) }
+        });
+
+        for _ in 0..width {
+            for channel in channels.iter_mut() {
+                *channel = values.next().unwrap();
+            }
+            elements.push(T::from_channels(&channels));
+        }
+    }
+
+    // PFM stores rows from bottom to top
+    let elements = elements.chunks_exact(width as usize).rev().flatten().copied().collect();
+
+    Ok(Raster::from_vec(Rectangle::new(0, 0, width, height), elements))
+}
+
+fn pfm_magic(channel_count: usize) -> Result<&'static str> {
+    match channel_count {
+        1 => Ok("Pf"),
+        3 => Ok("PF"),
+        _ => Err(Error::new(ErrorKind::InvalidInput, format!("PFM does not support {} channels", channel_count))),
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use crate::color::Rgb;
+
+    use super::*;
+
+    #[test]
+    fn write_pfm_header() {
+        let raster = Raster::<Rgb>::new(Rectangle::new(0, 0, 4, 3));
+        let mut data = Vec::new();
+        write_pfm(&mut data, &raster).unwrap();
+        assert!(data.starts_with(b"PF\n4 3\n-1.0\n"));
+        assert_eq!(data.len(), 12 + 4 * 3 * 3 * 4);
+    }
+
+    #[test]
+    fn write_pfm_bottom_to_top() {
+        let mut raster = Raster::<f32>::new(Rectangle::new(0, 0, 1, 2));
+        raster.set(0, 0, 1.0);
+        raster.set(0, 1, 2.0);
+
+        let mut data = Vec::new();
+        write_pfm(&mut data, &raster).unwrap();
+        assert!(data.ends_with(&[2.0f32.to_le_bytes(), 1.0f32.to_le_bytes()].concat()));
+    }
+
+    #[test]
+    fn pfm_round_trip() {
+        let mut raster = Raster::<Rgb>::new(Rectangle::new(5, 5, 12, 9));
+        for (x, y) in raster.rectangle().clone().index_iter() {
+            raster.set(x, y, Rgb::new(x as f32 * 100.0, -(y as f32), 1e-6));
+        }
+
+        let mut data = Vec::new();
+        write_pfm(&mut data, &raster).unwrap();
+
+        let result: Raster<Rgb> = read_pfm(Cursor::new(data)).unwrap();
+        assert_eq!(*result.rectangle(), Rectangle::new(0, 0, 7, 4));
+        for (x, y) in raster.rectangle().index_iter() {
+            assert_eq!(result.get(x - 5, y - 5), raster.get(x, y), "wrong value at ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn read_pfm_big_endian() {
+        let mut data = b"Pf\n2 1\n1.0\n".to_vec();
+        data.extend_from_slice(&0.5f32.to_be_bytes());
+        data.extend_from_slice(&4.0f32.to_be_bytes());
+
+        let result: Raster<f32> = read_pfm(Cursor::new(data)).unwrap();
+        assert_eq!(result.get(0, 0), 0.5);
+        assert_eq!(result.get(1, 0), 4.0);
+    }
+
+    #[test]
+    fn read_pfm_invalid_scale() {
+        let data = b"Pf\n2 1\n0.0\n".to_vec();
+        let result: Result<Raster<f32>> = read_pfm(Cursor::new(data));
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn read_pfm_truncated_huge_image() {
+        // The header claims about 120 GB of pixel data, but the file ends after a few bytes
+        let data = b"PF\n100000 100000\n-1.0\n\x00\x00\x80\x3f".to_vec();
+        let result: Result<Raster<Rgb>> = read_pfm(Cursor::new(data));
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn read_pfm_too_large() {
+        let data = b"Pf\n4294967295 4294967295\n-1.0\n".to_vec();
+        let result: Result<Raster<f32>> = read_pfm(Cursor::new(data));
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidData);
+    }
+}
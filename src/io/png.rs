@@ -0,0 +1,277 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Debug;
+use std::io::{Error, ErrorKind, Result, Write};
+use std::time::Duration;
+
+use ::png::{BitDepth, ColorType, Encoder, EncodingError, ScaledFloat, SourceChromaticities, SrgbRenderingIntent};
+
+use crate::filter::Filter;
+use crate::raster::Raster;
+use crate::rectangle::Coordinate;
+
+/// Element type of a raster that can be written as a PNG image: 8-bit or 16-bit gray (`u8`, `u16`), RGB (`[u8; 3]`, `[u16; 3]`) or RGBA (`[u8; 4]`,
+/// `[u16; 4]`) values.
+pub trait PngPixel: Copy + Default {
+    const COLOR_TYPE: ColorType;
+    const BIT_DEPTH: BitDepth;
+
+    /// Appends the bytes of this element, in the order in which they appear in a PNG image, to a buffer.
+    fn write_bytes(&self, buffer: &mut Vec<u8>);
+}
+
+/// Options for writing PNG images: color space chunks and text metadata.
+#[derive(Clone, Default, Debug)]
+pub struct PngOptions {
+    srgb: bool,
+    gamma: Option<f32>,
+    text: Vec<(String, String)>,
+}
+
+// ===== PngPixel ==============================================================================================================================================
+
+impl PngPixel for u8 {
+    const COLOR_TYPE: ColorType = ColorType::Grayscale;
+    const BIT_DEPTH: BitDepth = BitDepth::Eight;
+
+    #[inline]
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.push(*self);
+    }
+}
+
+impl PngPixel for u16 {
+    const COLOR_TYPE: ColorType = ColorType::Grayscale;
+    const BIT_DEPTH: BitDepth = BitDepth::Sixteen;
+
+    #[inline]
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl PngPixel for [u8; 3] {
+    const COLOR_TYPE: ColorType = ColorType::Rgb;
+    const BIT_DEPTH: BitDepth = BitDepth::Eight;
+
+    #[inline]
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(self);
+    }
+}
+
+impl PngPixel for [u16; 3] {
+    const COLOR_TYPE: ColorType = ColorType::Rgb;
+    const BIT_DEPTH: BitDepth = BitDepth::Sixteen;
+
+    #[inline]
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        for value in self {
+            buffer.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+impl PngPixel for [u8; 4] {
+    const COLOR_TYPE: ColorType = ColorType::Rgba;
+    const BIT_DEPTH: BitDepth = BitDepth::Eight;
+
+    #[inline]
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(self);
+    }
+}
+
+impl PngPixel for [u16; 4] {
+    const COLOR_TYPE: ColorType = ColorType::Rgba;
+    const BIT_DEPTH: BitDepth = BitDepth::Sixteen;
+
+    #[inline]
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        for value in self {
+            buffer.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+// ===== PngOptions ============================================================================================================================================
+
+impl PngOptions {
+    #[inline]
+    pub fn new() -> PngOptions {
+        PngOptions::default()
+    }
+
+    /// Marks the image as sRGB by writing an sRGB chunk, with gAMA and cHRM chunks for decoders that do not support it.
+    #[inline]
+    pub fn with_srgb(self) -> PngOptions {
+        PngOptions { srgb: true, ..self }
+    }
+
+    /// Writes a gAMA chunk with the given encoding gamma (for example 1 / 2.2).
+    #[inline]
+    pub fn with_gamma(self, gamma: f32) -> PngOptions {
+        PngOptions { gamma: Some(gamma), ..self }
+    }
+
+    /// Adds a text chunk; the keyword must be 1 to 79 Latin-1 characters.
+    pub fn with_text(mut self, keyword: &str, text: &str) -> PngOptions {
+        self.text.push((keyword.to_string(), text.to_string()));
+        self
+    }
+
+    pub fn with_render_time(self, render_time: Duration) -> PngOptions {
+        self.with_text("Render Time", &format!("{} ms", render_time.as_millis()))
+    }
+
+    pub fn with_sample_count(self, sample_count: usize) -> PngOptions {
+        self.with_text("Sample Count", &sample_count.to_string())
+    }
+
+    pub fn with_filter<F: Filter + Debug>(self, filter: &F) -> PngOptions {
+        self.with_text("Filter", &format!("{:?}", filter))
+    }
+
+    #[inline]
+    pub fn text(&self) -> &[(String, String)] {
+        &self.text
+    }
+}
+
+/// Writes a raster as a PNG image. The offset of the raster's rectangle is not stored.
+pub fn write_png<W: Write, T: PngPixel, C: Coordinate>(writer: W, raster: &Raster<T, C>, options: &PngOptions) -> Result<()> {
+    let rectangle = raster.rectangle();
+
+    let mut encoder = Encoder::new(writer, rectangle.width(), rectangle.height());
+    encoder.set_color(T::COLOR_TYPE);
+    encoder.set_depth(T::BIT_DEPTH);
+    if options.srgb {
+        // Fallback values for decoders that do not support the sRGB chunk, as recommended by the PNG specification
+        encoder.set_source_srgb(SrgbRenderingIntent::Perceptual);
+        encoder.set_source_gamma(ScaledFloat::from_scaled(45455));
+        encoder.set_source_chromaticities(SourceChromaticities::new((0.3127, 0.3290), (0.64, 0.33), (0.30, 0.60), (0.15, 0.06)));
+    }
+    if let Some(gamma) = options.gamma {
+        encoder.set_source_gamma(ScaledFloat::new(gamma));
+    }
+    for (keyword, text) in &options.text {
+        encoder.add_text_chunk(keyword.clone(), text.clone()).map_err(to_io_error)?;
+    }
+
+    let mut data = Vec::with_capacity(rectangle.size() * T::COLOR_TYPE.samples() * if T::BIT_DEPTH == BitDepth::Sixteen { 2 } else { 1 });
+    for element in raster.as_slice() {
+        element.write_bytes(&mut data);
+    }
+
+    let mut writer = encoder.write_header().map_err(to_io_error)?;
+    writer.write_image_data(&data).map_err(to_io_error)?;
+    writer.finish().map_err(to_io_error)
+}
+
+fn to_io_error(error: EncodingError) -> Error {
+    match error {
+        EncodingError::IoError(error) => error,
+        error => Error::new(ErrorKind::InvalidInput, error),
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use ::png::Decoder;
+
+    use crate::filter::MitchellFilter;
+    use crate::rectangle::Rectangle;
+
+    use super::*;
+
+    #[test]
+    fn write_png_rgb8() {
+        let mut raster = Raster::<[u8; 3]>::new(Rectangle::new(10, 10, 14, 13));
+        raster.set(11, 12, [10, 20, 30]);
+
+        let mut data = Vec::new();
+        write_png(&mut data, &raster, &PngOptions::new()).unwrap();
+
+        let mut reader = Decoder::new(Cursor::new(data)).read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).unwrap();
+        assert_eq!((info.width, info.height), (4, 3));
+        assert_eq!((info.color_type, info.bit_depth), (ColorType::Rgb, BitDepth::Eight));
+
+        let index = (2 * 4 + 1) * 3;
+        assert_eq!(&buffer[index..index + 3], &[10, 20, 30]);
+    }
+
+    #[test]
+    fn write_png_gray16() {
+        let mut raster = Raster::<u16>::new(Rectangle::new(0, 0, 2, 2));
+        raster.set(1, 0, 0x1234);
+
+        let mut data = Vec::new();
+        write_png(&mut data, &raster, &PngOptions::new()).unwrap();
+
+        let mut reader = Decoder::new(Cursor::new(data)).read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).unwrap();
+        assert_eq!((info.color_type, info.bit_depth), (ColorType::Grayscale, BitDepth::Sixteen));
+        assert_eq!(&buffer[2..4], &[0x12, 0x34]);
+    }
+
+    #[test]
+    fn write_png_metadata() {
+        let raster = Raster::<[u16; 4]>::new(Rectangle::new(0, 0, 2, 2));
+        let options = PngOptions::new()
+            .with_srgb()
+            .with_render_time(Duration::from_millis(1500))
+            .with_sample_count(64)
+            .with_filter(&MitchellFilter::with_defaults());
+
+        let mut data = Vec::new();
+        write_png(&mut data, &raster, &options).unwrap();
+
+        let reader = Decoder::new(Cursor::new(data)).read_info().unwrap();
+        let info = reader.info();
+        assert!(info.srgb.is_some(), "sRGB chunk is missing");
+        assert!(info.source_gamma.is_some(), "gAMA chunk is missing");
+
+        let text: Vec<(&str, &str)> = info.uncompressed_latin1_text.iter().map(|chunk| (chunk.keyword.as_str(), chunk.text.as_str())).collect();
+        assert!(text.contains(&("Render Time", "1500 ms")));
+        assert!(text.contains(&("Sample Count", "64")));
+        assert!(text.iter().any(|(keyword, text)| *keyword == "Filter" && text.starts_with("MitchellFilter")));
+    }
+
+    #[test]
+    fn write_png_gamma() {
+        let raster = Raster::<u8>::new(Rectangle::new(0, 0, 2, 2));
+
+        let mut data = Vec::new();
+        write_png(&mut data, &raster, &PngOptions::new().with_gamma(1.0 / 2.2)).unwrap();
+
+        let reader = Decoder::new(Cursor::new(data)).read_info().unwrap();
+        assert_eq!(reader.info().source_gamma, Some(ScaledFloat::new(1.0 / 2.2)));
+        assert!(reader.info().srgb.is_none());
+    }
+
+    #[test]
+    fn write_png_invalid_keyword() {
+        let raster = Raster::<u8>::new(Rectangle::new(0, 0, 2, 2));
+        let result = write_png(&mut Vec::new(), &raster, &PngOptions::new().with_text("", "empty keyword"));
+        assert!(result.is_err());
+    }
+}
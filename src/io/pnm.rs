@@ -0,0 +1,261 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::marker::PhantomData;
+
+use crate::io::{BitDepth, check_image_size, invalid_data, Pixel, read_header_number, read_header_token, read_row};
+use crate::raster::Raster;
+use crate::rectangle::{Coordinate, Rectangle};
+
+/// Writes a raster as a binary PGM (grayscale) or PPM (RGB) image.
+///
+/// Values in the range 0..1 are quantized to the given bit depth; values outside that range are clamped. Values are written as they are, so a transfer
+/// function should be applied before writing if the image is meant to be displayed. The offset of the raster's rectangle is not stored.
+pub fn write_pnm<W: Write, T: Pixel, C: Coordinate>(writer: W, raster: &Raster<T, C>, bit_depth: BitDepth) -> Result<()> {
+    let rectangle = raster.rectangle();
+
+    let mut writer = PnmWriter::new(writer, rectangle.width(), rectangle.height(), bit_depth)?;
+    for (_, row) in raster.rows() {
+        writer.write_row(row)?;
+    }
+    writer.finish()
+}
+
+/// Writes a binary PGM (grayscale) or PPM (RGB) image row by row, from top to bottom, so that images can be written without having all rows in memory.
+pub struct PnmWriter<W: Write, T: Pixel> {
+    writer: BufWriter<W>,
+    width: u32,
+    remaining_rows: u32,
+    bit_depth: BitDepth,
+    _pixel: PhantomData<T>,
+}
+
+impl<W: Write, T: Pixel> PnmWriter<W, T> {
+    /// Creates a writer for an image with the given size and writes the header.
+    pub fn new(writer: W, width: u32, height: u32, bit_depth: BitDepth) -> Result<PnmWriter<W, T>> {
+        let magic = pnm_magic(T::CHANNEL_COUNT)?;
+
+        let mut writer = BufWriter::new(writer);
+        write!(writer, "{}\n{} {}\n{}\n", magic, width, height, bit_depth.max_value())?;
+
+        Ok(PnmWriter { writer, width, remaining_rows: height, bit_depth, _pixel: PhantomData })
+    }
+
+    /// Writes the next row of the image; the row must contain `width` elements.
+    pub fn write_row(&mut self, row: &[T]) -> Result<()> {
+        if row.len() != self.width as usize {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("row has {} elements but the image width is {}", row.len(), self.width)));
+        }
+        if self.remaining_rows == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "all rows of the image have already been written"));
+        }
+
+        for element in row {
+            for index in 0..T::CHANNEL_COUNT {
+                let value = self.bit_depth.quantize(element.channel(index));
+                match self.bit_depth {
+                    BitDepth::Eight => self.writer.write_all(&[value as u8])?,
+                    BitDepth::Sixteen => self.writer.write_all(&(value as u16).to_be_bytes())?,
+                }
+            }
+        }
+
+        self.remaining_rows -= 1;
+        Ok(())
+    }
+
+    /// Flushes the image; all rows must have been written.
+    pub fn finish(mut self) -> Result<()> {
+        if self.remaining_rows != 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("{} rows of the image have not been written", self.remaining_rows)));
+        }
+        self.writer.flush()
+    }
+}
+
+/// Reads a binary PGM (grayscale) or PPM (RGB) image into a raster with its rectangle at the origin.
+///
+/// The image type must match the element type of the raster: PGM for single-channel and PPM for three-channel elements. Values are scaled to the range 0..1.
+pub fn read_pnm<R: Read, T: Pixel>(reader: R) -> Result<Raster<T>> {
+    let expected_magic = pnm_magic(T::CHANNEL_COUNT)?;
+
+    let mut reader = BufReader::new(reader);
+    let magic = read_header_token(&mut reader)?;
+    if magic != expected_magic {
+        return Err(invalid_data(&format!("unsupported image type: {} (expected {})", magic, expected_magic)));
+    }
+
+    let width = read_header_number(&mut reader, "width")?;
+    let height = read_header_number(&mut reader, "height")?;
+    let max_value = read_header_number(&mut reader, "maximum value")?;
+    if max_value == 0 || max_value > 65535 {
+        return Err(invalid_data(&format!("invalid maximum value in header: {}", max_value)));
+    }
+
+    let bytes_per_value = if max_value < 256 { 1 } else { 2 };
+    let row_size = check_image_size::<T>(width, height, bytes_per_value)?;
+
+    // Elements are only allocated for rows that have actually been read
+    let mut elements = Vec::new();
+    let mut data = Vec::new();
+    let mut channels = vec![0.0; T::CHANNEL_COUNT];
+
+    for _ in 0..height {
+        read_row(&mut reader, row_size, &mut data)?;
+
+        let mut values = data.chunks_exact(bytes_per_value)
+            .map(|bytes| if bytes_per_value == 1 { bytes[0] as u32 } else { u16::from_be_bytes([bytes[0], bytes[1]]) as u32 });
+
+        for _ in 0..width {
+            for channel in channels.iter_mut() {
+                *channel = f32::min(values.next().unwrap() as f32 / max_value as f32, 1.0);
+            }
+            elements.push(T::from_channels(&channels));
+        }
+    }
+
+    Ok(Raster::from_vec(Rectangle::new(0, 0, width, height), elements))
+}
+
+fn pnm_magic(channel_count: usize) -> Result<&'static str> {
+    match channel_count {
+        1 => Ok("P5"),
+        3 => Ok("P6"),
+        _ => Err(Error::new(ErrorKind::InvalidInput, format!("PGM and PPM do not support {} channels", channel_count))),
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use crate::color::Rgb;
+
+    use super::*;
+
+    fn gradient_rgb() -> Raster<Rgb> {
+        let mut raster = Raster::new(Rectangle::new(10, 20, 17, 25));
+        for (x, y) in raster.rectangle().clone().index_iter() {
+            raster.set(x, y, Rgb::new((x - 10) as f32 / 6.0, (y - 20) as f32 / 4.0, 0.25));
+        }
+        raster
+    }
+
+    #[test]
+    fn write_pnm_ppm_header() {
+        let mut data = Vec::new();
+        write_pnm(&mut data, &gradient_rgb(), BitDepth::Eight).unwrap();
+        assert!(data.starts_with(b"P6\n7 5\n255\n"));
+        assert_eq!(data.len(), 11 + 7 * 5 * 3);
+    }
+
+    #[test]
+    fn write_pnm_pgm_header() {
+        let raster = Raster::<f32>::new(Rectangle::new(0, 0, 3, 2));
+        let mut data = Vec::new();
+        write_pnm(&mut data, &raster, BitDepth::Sixteen).unwrap();
+        assert!(data.starts_with(b"P5\n3 2\n65535\n"));
+        assert_eq!(data.len(), 13 + 3 * 2 * 2);
+    }
+
+    #[test]
+    fn pnm_round_trip_8_bit() {
+        let raster = gradient_rgb();
+        let mut data = Vec::new();
+        write_pnm(&mut data, &raster, BitDepth::Eight).unwrap();
+
+        let result: Raster<Rgb> = read_pnm(Cursor::new(data)).unwrap();
+        assert_eq!(*result.rectangle(), Rectangle::new(0, 0, 7, 5));
+        for (x, y) in raster.rectangle().index_iter() {
+            let (expected, actual) = (raster.get(x, y), result.get(x - 10, y - 20));
+            assert!((expected - actual).map(f32::abs).max_component() <= 0.5 / 255.0 + 1e-6, "wrong value at ({}, {}): {:?} != {:?}", x, y, actual, expected);
+        }
+    }
+
+    #[test]
+    fn pnm_round_trip_16_bit() {
+        let mut raster = Raster::<f32>::new(Rectangle::new(0, 0, 5, 4));
+        for (x, y) in raster.rectangle().clone().index_iter() {
+            raster.set(x, y, (x * y) as f32 / 12.0);
+        }
+
+        let mut data = Vec::new();
+        write_pnm(&mut data, &raster, BitDepth::Sixteen).unwrap();
+
+        let result: Raster<f32> = read_pnm(Cursor::new(data)).unwrap();
+        for (x, y) in raster.rectangle().index_iter() {
+            assert!((raster.get(x, y) - result.get(x, y)).abs() <= 0.5 / 65535.0 + 1e-6, "wrong value at ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn pnm_writer_rows() {
+        let raster = gradient_rgb();
+        let mut expected = Vec::new();
+        write_pnm(&mut expected, &raster, BitDepth::Eight).unwrap();
+
+        let mut data = Vec::new();
+        let mut writer = PnmWriter::new(&mut data, raster.rectangle().width(), raster.rectangle().height(), BitDepth::Eight).unwrap();
+        assert!(writer.write_row(&[Rgb::BLACK]).is_err(), "row with wrong width should be rejected");
+        for (_, row) in raster.rows() {
+            writer.write_row(row).unwrap();
+        }
+        writer.finish().unwrap();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn pnm_writer_missing_rows() {
+        let mut writer = PnmWriter::<_, f32>::new(Vec::new(), 2, 2, BitDepth::Eight).unwrap();
+        writer.write_row(&[0.0, 1.0]).unwrap();
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn read_pnm_wrong_type() {
+        let mut data = Vec::new();
+        write_pnm(&mut data, &gradient_rgb(), BitDepth::Eight).unwrap();
+
+        let result: Result<Raster<f32>> = read_pnm(Cursor::new(data));
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn read_pnm_truncated() {
+        let mut data = Vec::new();
+        write_pnm(&mut data, &gradient_rgb(), BitDepth::Eight).unwrap();
+        data.truncate(data.len() - 1);
+
+        let result: Result<Raster<Rgb>> = read_pnm(Cursor::new(data));
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn read_pnm_truncated_huge_image() {
+        // The header claims about 30 GB of pixel data, but the file ends after a few bytes
+        let data = b"P6\n100000 100000\n255\n\x01\x02\x03".to_vec();
+        let result: Result<Raster<Rgb>> = read_pnm(Cursor::new(data));
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn read_pnm_too_large() {
+        let data = b"P6\n4294967295 4294967295\n255\n".to_vec();
+        let result: Result<Raster<Rgb>> = read_pnm(Cursor::new(data));
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,197 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::{create_dir_all, File};
+use std::io::{Error, ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+
+use crate::io::{BitDepth, Pixel, write_pnm};
+use crate::raster::{Pyramid, Raster};
+use crate::rectangle::Coordinate;
+
+/// Directory structure of an exported tile pyramid.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum TileLayout {
+    /// Deep Zoom: a descriptor `<name>.dzi` and tiles `<name>_files/<level>/<column>_<row>.<ext>`, where level 0 is the level of 1x1 elements.
+    DeepZoom,
+    /// XYZ: tiles `<name>/<z>/<x>/<y>.<ext>`, where zoom level 0 is the first level that fits in a single tile.
+    Xyz,
+}
+
+/// Image file format of the tiles of an exported tile pyramid. Tiles are written with 8 bits per channel.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum TileFormat {
+    /// PGM for single-channel and PPM for three-channel elements.
+    Pnm,
+    /// PNG for single-channel, three-channel and four-channel elements.
+    #[cfg(feature = "png")]
+    Png,
+}
+
+/// Writes the levels of a pyramid as tiles of the given size into a directory, for viewers that load zoomable images tile by tile.
+///
+/// Values are written as they are, so a transfer function should be applied to the pyramid's raster before it is built.
+pub fn write_tile_pyramid<T: Pixel, C: Coordinate>(dir: &Path, name: &str, pyramid: &Pyramid<T, C>, tile_size: u32, layout: TileLayout, format: TileFormat)
+                                                   -> Result<()> {
+    if tile_size == 0 {
+        return Err(Error::new(ErrorKind::InvalidInput, "tile size must be greater than zero"));
+    }
+
+    let extension = tile_extension::<T>(format)?;
+    let level_count = pyramid.level_count();
+
+    match layout {
+        TileLayout::DeepZoom => {
+            let base = pyramid.level(0).rectangle();
+            let mut descriptor = File::create(dir.join(format!("{}.dzi", name)))?;
+            write!(descriptor, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                <Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" Format=\"{}\" Overlap=\"0\" TileSize=\"{}\">\n  \
+                <Size Width=\"{}\" Height=\"{}\"/>\n</Image>\n", extension, tile_size, base.width(), base.height())?;
+
+            for (index, level) in pyramid.levels().iter().enumerate() {
+                let level_dir = dir.join(format!("{}_files", name)).join((level_count - 1 - index).to_string());
+                create_dir_all(&level_dir)?;
+                write_tiles(level, tile_size, format, |column, row| Ok(level_dir.join(format!("{}_{}.{}", column, row, extension))))?;
+            }
+        }
+        TileLayout::Xyz => {
+            // Zoom level 0 is the first level that fits in a single tile
+            let top_index = pyramid.levels().iter()
+                .position(|level| level.rectangle().width() <= tile_size && level.rectangle().height() <= tile_size)
+                .unwrap_or(level_count - 1);
+
+            for (index, level) in pyramid.levels()[..=top_index].iter().enumerate() {
+                let zoom_dir = dir.join(name).join((top_index - index).to_string());
+                write_tiles(level, tile_size, format, |column, row| {
+                    let column_dir = zoom_dir.join(column.to_string());
+                    create_dir_all(&column_dir)?;
+                    Ok(column_dir.join(format!("{}.{}", row, extension)))
+                })?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn write_tiles<T: Pixel, C: Coordinate, P: FnMut(u32, u32) -> Result<PathBuf>>(level: &Raster<T, C>, tile_size: u32, format: TileFormat, mut path_fn: P)
+                                                                               -> Result<()> {
+    for (column, row, tile) in level.rectangle().fixed_tile_iter(tile_size, tile_size) {
+        write_tile(&path_fn(column, row)?, &level.crop(&tile), format)?;
+    }
+    Ok(())
+}
+
+fn write_tile<T: Pixel, C: Coordinate>(path: &Path, tile: &Raster<T, C>, format: TileFormat) -> Result<()> {
+    let file = File::create(path)?;
+    match format {
+        TileFormat::Pnm => write_pnm(file, tile, BitDepth::Eight),
+        #[cfg(feature = "png")]
+        TileFormat::Png => {
+            let channel = |element: &T, index: usize| BitDepth::Eight.quantize(element.channel(index)) as u8;
+            let options = crate::io::PngOptions::new();
+            match T::CHANNEL_COUNT {
+                1 => crate::io::write_png(file, &tile.map(|element| channel(&element, 0)), &options),
+                3 => crate::io::write_png(file, &tile.map(|element| [0, 1, 2].map(|index| channel(&element, index))), &options),
+                4 => crate::io::write_png(file, &tile.map(|element| [0, 1, 2, 3].map(|index| channel(&element, index))), &options),
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+fn tile_extension<T: Pixel>(format: TileFormat) -> Result<&'static str> {
+    match (format, T::CHANNEL_COUNT) {
+        (TileFormat::Pnm, 1) => Ok("pgm"),
+        (TileFormat::Pnm, 3) => Ok("ppm"),
+        #[cfg(feature = "png")]
+        (TileFormat::Png, 1 | 3 | 4) => Ok("png"),
+        (_, channel_count) => Err(Error::new(ErrorKind::InvalidInput, format!("unsupported number of channels for {:?} tiles: {}", format, channel_count))),
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use crate::color::Rgb;
+    use crate::rectangle::Rectangle;
+
+    use super::*;
+
+    fn output_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("renderbase-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn pyramid() -> Pyramid<Rgb> {
+        Pyramid::new(&Raster::<Rgb>::new(Rectangle::new(0, 0, 10, 6)).map(|_| Rgb::gray(0.5)))
+    }
+
+    #[test]
+    fn write_tile_pyramid_deep_zoom() {
+        let dir = output_dir("deep-zoom");
+        write_tile_pyramid(&dir, "image", &pyramid(), 4, TileLayout::DeepZoom, TileFormat::Pnm).unwrap();
+
+        let descriptor = fs::read_to_string(dir.join("image.dzi")).unwrap();
+        assert!(descriptor.contains("TileSize=\"4\"") && descriptor.contains("Width=\"10\" Height=\"6\""));
+
+        // Levels 10x6, 5x3, 3x2, 2x1, 1x1 are Deep Zoom levels 4 to 0
+        let files = dir.join("image_files");
+        assert!(files.join("4").join("2_1.ppm").exists());
+        assert!(!files.join("4").join("3_0.ppm").exists());
+        assert!(files.join("3").join("1_0.ppm").exists());
+        assert!(files.join("0").join("0_0.ppm").exists());
+
+        let tile: Raster<Rgb> = crate::io::read_pnm(File::open(files.join("4").join("2_1.ppm")).unwrap()).unwrap();
+        assert_eq!((tile.rectangle().width(), tile.rectangle().height()), (2, 2));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_tile_pyramid_xyz() {
+        let dir = output_dir("xyz");
+        write_tile_pyramid(&dir, "image", &pyramid(), 4, TileLayout::Xyz, TileFormat::Pnm).unwrap();
+
+        // Level 3x2 is the first level that fits in one tile, so the zoom levels are 0 (3x2), 1 (5x3) and 2 (10x6)
+        let root = dir.join("image");
+        assert!(root.join("0").join("0").join("0.ppm").exists());
+        assert!(root.join("1").join("1").join("0.ppm").exists());
+        assert!(root.join("2").join("2").join("1.ppm").exists());
+        assert!(!root.join("3").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn write_tile_pyramid_png() {
+        let dir = output_dir("png");
+        write_tile_pyramid(&dir, "image", &pyramid(), 8, TileLayout::Xyz, TileFormat::Png).unwrap();
+        assert!(dir.join("image").join("1").join("1").join("0.png").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_tile_pyramid_unsupported_channels() {
+        assert_eq!(tile_extension::<f32>(TileFormat::Pnm).unwrap(), "pgm");
+        assert!(tile_extension::<(Rgb, f32)>(TileFormat::Pnm).is_err());
+    }
+}
@@ -0,0 +1,408 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::OnceLock;
+
+use crate::color::Rgb;
+use crate::io::{BitDepth, Pixel};
+use crate::raster::Raster;
+use crate::rectangle::Coordinate;
+use crate::sampler::{blue_noise_mask, BLUE_NOISE_SIZE};
+
+/// Element type of a raster with integer channel values, which is the result of quantizing a raster of floating-point values.
+pub trait QuantizedPixel: Copy + Default {
+    /// Number of channels per pixel.
+    const CHANNEL_COUNT: usize;
+
+    /// Number of bits per channel.
+    const BIT_DEPTH: BitDepth;
+
+    /// Creates an element from integer channel values in the range 0..max_value of the bit depth; `levels` contains `CHANNEL_COUNT` values.
+    fn from_levels(levels: &[u32]) -> Self;
+}
+
+/// Dithering method for quantization, which trades banding in smooth gradients for fine noise.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Dither {
+    /// No dithering; values are rounded to the nearest level.
+    None,
+    /// Ordered dithering with an 8x8 Bayer matrix.
+    Bayer,
+    /// Ordered dithering with a 64x64 blue noise mask, which has no visible pattern.
+    BlueNoise,
+    /// Floyd–Steinberg error diffusion, with serpentine scanning.
+    FloydSteinberg,
+}
+
+/// Palette of at most 256 colors for indexed (GIF-style) images.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Palette {
+    colors: Vec<Rgb>,
+}
+
+// ===== QuantizedPixel ========================================================================================================================================
+
+impl QuantizedPixel for u8 {
+    const CHANNEL_COUNT: usize = 1;
+    const BIT_DEPTH: BitDepth = BitDepth::Eight;
+
+    #[inline]
+    fn from_levels(levels: &[u32]) -> u8 {
+        levels[0] as u8
+    }
+}
+
+impl QuantizedPixel for u16 {
+    const CHANNEL_COUNT: usize = 1;
+    const BIT_DEPTH: BitDepth = BitDepth::Sixteen;
+
+    #[inline]
+    fn from_levels(levels: &[u32]) -> u16 {
+        levels[0] as u16
+    }
+}
+
+impl QuantizedPixel for [u8; 3] {
+    const CHANNEL_COUNT: usize = 3;
+    const BIT_DEPTH: BitDepth = BitDepth::Eight;
+
+    #[inline]
+    fn from_levels(levels: &[u32]) -> [u8; 3] {
+        [levels[0] as u8, levels[1] as u8, levels[2] as u8]
+    }
+}
+
+impl QuantizedPixel for [u16; 3] {
+    const CHANNEL_COUNT: usize = 3;
+    const BIT_DEPTH: BitDepth = BitDepth::Sixteen;
+
+    #[inline]
+    fn from_levels(levels: &[u32]) -> [u16; 3] {
+        [levels[0] as u16, levels[1] as u16, levels[2] as u16]
+    }
+}
+
+impl QuantizedPixel for [u8; 4] {
+    const CHANNEL_COUNT: usize = 4;
+    const BIT_DEPTH: BitDepth = BitDepth::Eight;
+
+    #[inline]
+    fn from_levels(levels: &[u32]) -> [u8; 4] {
+        [levels[0] as u8, levels[1] as u8, levels[2] as u8, levels[3] as u8]
+    }
+}
+
+impl QuantizedPixel for [u16; 4] {
+    const CHANNEL_COUNT: usize = 4;
+    const BIT_DEPTH: BitDepth = BitDepth::Sixteen;
+
+    #[inline]
+    fn from_levels(levels: &[u32]) -> [u16; 4] {
+        [levels[0] as u16, levels[1] as u16, levels[2] as u16, levels[3] as u16]
+    }
+}
+
+// ===== Dither ================================================================================================================================================
+
+impl Dither {
+    /// Returns the threshold in the range 0..1 of an ordered dithering method at the given position; 0.5 means rounding to the nearest level.
+    #[inline]
+    fn threshold(&self, x: i64, y: i64) -> f32 {
+        match self {
+            Dither::Bayer => bayer_matrix()[(y.rem_euclid(8) * 8 + x.rem_euclid(8)) as usize],
+            Dither::BlueNoise => {
+                let size = BLUE_NOISE_SIZE as i64;
+                blue_noise_mask(0)[(y.rem_euclid(size) * size + x.rem_euclid(size)) as usize]
+            }
+            Dither::None | Dither::FloydSteinberg => 0.5,
+        }
+    }
+}
+
+fn bayer_matrix() -> &'static [f32; 64] {
+    static MATRIX: OnceLock<[f32; 64]> = OnceLock::new();
+    MATRIX.get_or_init(|| {
+        let mut matrix = [0.0; 64];
+        for y in 0..8u32 {
+            for x in 0..8u32 {
+                // Interleave the bits of x ^ y and y, in reverse order, to get the index in the recursive Bayer pattern
+                let (a, b) = (x ^ y, y);
+                let rank = ((a & 1) << 5) | ((b & 1) << 4) | ((a & 2) << 2) | ((b & 2) << 1) | ((a & 4) >> 1) | ((b & 4) >> 2);
+                matrix[(y * 8 + x) as usize] = (rank as f32 + 0.5) / 64.0;
+            }
+        }
+        matrix
+    })
+}
+
+// ===== Palette ===============================================================================================================================================
+
+impl Palette {
+    /// Creates a palette with the given colors; there must be between 1 and 256 colors.
+    pub fn new(colors: Vec<Rgb>) -> Palette {
+        assert!(!colors.is_empty() && colors.len() <= 256, "palette must have between 1 and 256 colors but has {}", colors.len());
+        Palette { colors }
+    }
+
+    /// Creates a palette of at most `max_colors` colors that represents the colors of a raster, with the median cut algorithm.
+    pub fn median_cut<C: Coordinate>(raster: &Raster<Rgb, C>, max_colors: usize) -> Palette {
+        assert!(max_colors > 0 && max_colors <= 256, "maximum number of colors must be between 1 and 256 but is {}", max_colors);
+
+        let mut boxes: Vec<Vec<Rgb>> = vec![raster.as_slice().iter().copied().filter(|color| color.r.is_finite() && color.g.is_finite() && color.b.is_finite()).collect()];
+        while boxes.len() < max_colors {
+            // Split the box with the largest range in any channel at the median of that channel
+            let (index, channel, range) = boxes.iter().enumerate()
+                .flat_map(|(index, colors)| (0..3).map(move |channel| (index, channel, channel_range(colors, channel))))
+                .max_by(|a, b| a.2.total_cmp(&b.2))
+                .unwrap();
+            if range <= 0.0 {
+                break;
+            }
+
+            let mut colors = boxes.swap_remove(index);
+            colors.sort_unstable_by(|a, b| a.channel(channel).total_cmp(&b.channel(channel)));
+            let upper = colors.split_off(colors.len() / 2);
+            boxes.push(colors);
+            boxes.push(upper);
+        }
+
+        let colors: Vec<Rgb> = boxes.iter()
+            .filter(|colors| !colors.is_empty())
+            .map(|colors| colors.iter().fold(Rgb::BLACK, |sum, &color| sum + color) / colors.len() as f32)
+            .collect();
+
+        Palette::new(if colors.is_empty() { vec![Rgb::BLACK] } else { colors })
+    }
+
+    #[inline]
+    pub fn colors(&self) -> &[Rgb] {
+        &self.colors
+    }
+
+    /// Returns the index of the palette color nearest to the given color.
+    pub fn nearest_index(&self, color: Rgb) -> usize {
+        let distance = |other: &Rgb| {
+            let d = *other - color;
+            d.r * d.r + d.g * d.g + d.b * d.b
+        };
+        (0..self.colors.len()).min_by(|&a, &b| distance(&self.colors[a]).total_cmp(&distance(&self.colors[b]))).unwrap()
+    }
+}
+
+fn channel_range(colors: &[Rgb], channel: usize) -> f32 {
+    if colors.len() < 2 {
+        return 0.0;
+    }
+    let (min, max) = colors.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), color| {
+        let value = color.channel(channel);
+        (min.min(value), max.max(value))
+    });
+    max - min
+}
+
+// ===== Raster ================================================================================================================================================
+
+impl<T: Pixel, C: Coordinate> Raster<T, C> {
+    /// Quantizes the values in the range 0..1 of this raster to integers with the bit depth of the quantized element type, using a dithering method.
+    /// Values outside the range are clamped. The element types must have the same number of channels.
+    ///
+    /// Values are quantized as they are, so a transfer function should be applied before quantizing if the image is meant to be displayed.
+    pub fn quantize<Q: QuantizedPixel>(&self, dither: Dither) -> Raster<Q, C> {
+        assert_eq!(T::CHANNEL_COUNT, Q::CHANNEL_COUNT, "element types must have the same number of channels");
+
+        let max_value = Q::BIT_DEPTH.max_value();
+        let quantize = |value: f32, threshold: f32| -> u32 {
+            if value.is_nan() { 0 } else { (value.clamp(0.0, 1.0) * max_value as f32 + threshold).floor().min(max_value as f32) as u32 }
+        };
+
+        let mut levels = vec![0; T::CHANNEL_COUNT];
+        if dither != Dither::FloydSteinberg {
+            return self.iter_map(|x, y, element| {
+                let threshold = dither.threshold(x, y);
+                for (index, level) in levels.iter_mut().enumerate() {
+                    *level = quantize(element.channel(index), threshold);
+                }
+                Q::from_levels(&levels)
+            });
+        }
+
+        // Error diffusion works on values scaled to the range 0..max_value
+        let (width, channel_count) = (self.rectangle().width() as usize, T::CHANNEL_COUNT);
+        let mut values: Vec<f32> = self.as_slice().iter()
+            .flat_map(|element| (0..channel_count).map(move |index| element.channel(index)))
+            .map(|value| if value.is_nan() { 0.0 } else { value.clamp(0.0, 1.0) * max_value as f32 })
+            .collect();
+
+        let mut result = Raster::new(self.rectangle().clone());
+        for (row_index, (_, row)) in result.rows_mut().enumerate() {
+            for column in serpentine(width, row_index) {
+                let base = (row_index * width + column) * channel_count;
+                for (index, level) in levels.iter_mut().enumerate() {
+                    let value = values[base + index];
+                    *level = value.round().clamp(0.0, max_value as f32) as u32;
+                    diffuse_error(&mut values, width, channel_count, row_index, column, index, value - *level as f32);
+                }
+                row[column] = Q::from_levels(&levels);
+            }
+        }
+        result
+    }
+
+    fn iter_map<U: Copy + Default, F: FnMut(i64, i64, &T) -> U>(&self, mut map_fn: F) -> Raster<U, C> {
+        let elements = self.iter().map(|(x, y, element)| map_fn(x.to_i64(), y.to_i64(), element)).collect();
+        Raster::from_vec(self.rectangle().clone(), elements)
+    }
+}
+
+impl<C: Coordinate> Raster<Rgb, C> {
+    /// Quantizes this raster to indices of the nearest colors in a palette, using a dithering method.
+    pub fn quantize_to_palette(&self, palette: &Palette, dither: Dither) -> Raster<u8, C> {
+        if dither != Dither::FloydSteinberg {
+            // Offset colors by the threshold, scaled to the typical distance between palette colors
+            let spread = 1.0 / (palette.colors.len() as f32).cbrt();
+            return self.iter_map(|x, y, &color| {
+                let offset = (dither.threshold(x, y) - 0.5) * if dither == Dither::None { 0.0 } else { spread };
+                palette.nearest_index(color + Rgb::gray(offset)) as u8
+            });
+        }
+
+        let width = self.rectangle().width() as usize;
+        let mut values: Vec<f32> = self.as_slice().iter().flat_map(|color| [color.r, color.g, color.b]).collect();
+
+        let mut result = Raster::new(self.rectangle().clone());
+        for (row_index, (_, row)) in result.rows_mut().enumerate() {
+            for column in serpentine(width, row_index) {
+                let base = (row_index * width + column) * 3;
+                let color = Rgb::new(values[base], values[base + 1], values[base + 2]);
+                let index = palette.nearest_index(color);
+                let error = color - palette.colors[index];
+                for (channel, error) in [error.r, error.g, error.b].into_iter().enumerate() {
+                    diffuse_error(&mut values, width, 3, row_index, column, channel, error);
+                }
+                row[column] = index as u8;
+            }
+        }
+        result
+    }
+}
+
+/// Returns the columns of a row in serpentine order: left to right on even rows and right to left on odd rows.
+fn serpentine(width: usize, row_index: usize) -> Box<dyn Iterator<Item=usize>> {
+    if row_index.is_multiple_of(2) { Box::new(0..width) } else { Box::new((0..width).rev()) }
+}
+
+/// Distributes the quantization error of a channel of an element to its unprocessed neighbours with the Floyd–Steinberg weights.
+fn diffuse_error(values: &mut [f32], width: usize, channel_count: usize, row_index: usize, column: usize, channel: usize, error: f32) {
+    let height = values.len() / channel_count / width.max(1);
+    let forward: isize = if row_index.is_multiple_of(2) { 1 } else { -1 };
+
+    for (dx, dy, weight) in [(forward, 0, 7.0), (-forward, 1, 3.0), (0, 1, 5.0), (forward, 1, 1.0)] {
+        let (x, y) = (column as isize + dx, row_index + dy);
+        if x >= 0 && (x as usize) < width && y < height {
+            values[(y * width + x as usize) * channel_count + channel] += error * weight / 16.0;
+        }
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use crate::rectangle::Rectangle;
+
+    use super::*;
+
+    fn gradient() -> Raster<f32> {
+        let mut raster = Raster::new(Rectangle::new(0, 0, 64, 16));
+        for (x, _, element) in raster.iter_mut() {
+            *element = (x as f32 + 0.5) / 64.0 * (4.0 / 255.0);
+        }
+        raster
+    }
+
+    fn mean<Q: Copy + Default + Into<u32>>(raster: &Raster<Q>, column: u32) -> f32 {
+        let r = raster.rectangle();
+        (r.top..r.bottom).map(|y| raster.get(column, y).into() as f32).sum::<f32>() / r.height() as f32
+    }
+
+    #[test]
+    fn quantize_without_dither() {
+        let mut raster = Raster::<f32>::new(Rectangle::new(0, 0, 3, 1));
+        raster.set(1, 0, 0.5);
+        raster.set(2, 0, 2.0);
+
+        let result = raster.quantize::<u8>(Dither::None);
+        assert_eq!(result.as_slice(), &[0, 128, 255]);
+        assert_eq!(raster.quantize::<u16>(Dither::None).get(2, 0), 65535);
+    }
+
+    #[test]
+    fn quantize_dither_preserves_average() {
+        // A gradient between levels 0 and 4 is quantized to a few levels; with dithering, the local average follows the gradient
+        let raster = gradient();
+        for dither in [Dither::Bayer, Dither::BlueNoise, Dither::FloydSteinberg] {
+            let result = raster.quantize::<u8>(dither);
+            for column in [8, 24, 40, 56] {
+                let expected = raster.get(column, 0) * 255.0;
+                let actual: f32 = (column - 4..column + 4).map(|c| mean(&result, c)).sum::<f32>() / 8.0;
+                assert!((actual - expected).abs() < 0.35, "{:?}: expected {} at column {} but got {}", dither, expected, column, actual);
+            }
+        }
+    }
+
+    #[test]
+    fn quantize_rgb() {
+        let raster = Raster::<Rgb>::new(Rectangle::new(0, 0, 2, 2)).map(|_| Rgb::new(0.0, 0.5, 1.0));
+        let result = raster.quantize::<[u8; 3]>(Dither::FloydSteinberg);
+        assert!(result.as_slice().iter().all(|&[r, g, b]| r == 0 && (127..=128).contains(&g) && b == 255));
+    }
+
+    #[test]
+    #[should_panic(expected = "same number of channels")]
+    fn quantize_wrong_channel_count() {
+        Raster::<Rgb>::new(Rectangle::new(0, 0, 2, 2)).quantize::<u8>(Dither::None);
+    }
+
+    #[test]
+    fn bayer_matrix_is_permutation() {
+        let mut ranks: Vec<u32> = bayer_matrix().iter().map(|&t| (t * 64.0 - 0.5).round() as u32).collect();
+        assert_eq!((ranks[0], ranks[1], ranks[8], ranks[9]), (0, 32, 48, 16));
+        ranks.sort_unstable();
+        assert_eq!(ranks, (0..64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn palette_median_cut() {
+        let mut raster = Raster::<Rgb>::new(Rectangle::new(0, 0, 4, 4));
+        for (x, _, element) in raster.iter_mut() {
+            *element = if x < 2 { Rgb::new(1.0, 0.0, 0.0) } else { Rgb::new(0.0, 0.0, 1.0) };
+        }
+
+        let palette = Palette::median_cut(&raster, 4);
+        assert_eq!(palette.colors().len(), 2);
+        assert_eq!(palette.nearest_index(Rgb::new(0.9, 0.1, 0.0)), palette.colors().iter().position(|&c| c == Rgb::new(1.0, 0.0, 0.0)).unwrap());
+    }
+
+    #[test]
+    fn quantize_to_palette() {
+        let palette = Palette::new(vec![Rgb::BLACK, Rgb::WHITE]);
+        let raster = Raster::<Rgb>::new(Rectangle::new(0, 0, 16, 16)).map(|_| Rgb::gray(0.25));
+
+        assert!(raster.quantize_to_palette(&palette, Dither::None).as_slice().iter().all(|&index| index == 0));
+
+        let result = raster.quantize_to_palette(&palette, Dither::FloydSteinberg);
+        let white = result.as_slice().iter().filter(|&&index| index == 1).count();
+        assert!((white as f32 / 256.0 - 0.25).abs() < 0.03, "fraction of white pixels: {}", white as f32 / 256.0);
+    }
+}
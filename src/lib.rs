@@ -17,3 +17,7 @@ pub mod raster;
 pub mod sampler;
 pub mod filter;
 pub mod renderer;
+pub mod color;
+pub mod tonemap;
+pub mod io;
+pub mod metrics;
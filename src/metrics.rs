@@ -0,0 +1,206 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Image comparison metrics and statistics.
+//!
+//! All comparison metrics compare two rasters that must have the same rectangle; the metrics are averaged over all channels of the elements.
+
+pub use flip::*;
+pub use ssim::*;
+pub use statistics::*;
+
+use crate::color::Rgb;
+use crate::io::Pixel;
+use crate::raster::Raster;
+use crate::rectangle::Coordinate;
+
+mod ssim;
+mod flip;
+mod statistics;
+
+/// Returns the mean squared error between two rasters.
+pub fn mse<T: Pixel, C: Coordinate>(raster: &Raster<T, C>, reference: &Raster<T, C>) -> f64 {
+    mean_over_channels(raster, reference, |value, reference_value| {
+        let difference = value as f64 - reference_value as f64;
+        difference * difference
+    })
+}
+
+/// Returns the root mean squared error between two rasters.
+#[inline]
+pub fn rmse<T: Pixel, C: Coordinate>(raster: &Raster<T, C>, reference: &Raster<T, C>) -> f64 {
+    mse(raster, reference).sqrt()
+}
+
+/// Returns the peak signal-to-noise ratio in decibels between two rasters, for the given peak value (for example 1.0). Returns infinity if the rasters are
+/// equal.
+pub fn psnr<T: Pixel, C: Coordinate>(raster: &Raster<T, C>, reference: &Raster<T, C>, peak: f32) -> f64 {
+    let mse = mse(raster, reference);
+    if mse > 0.0 { 10.0 * f64::log10((peak as f64 * peak as f64) / mse) } else { f64::INFINITY }
+}
+
+/// Returns the relative mean squared error of a raster compared to a reference: the squared error divided by the squared reference value plus a small
+/// constant (0.01) that avoids division by zero in dark regions.
+pub fn relative_mse<T: Pixel, C: Coordinate>(raster: &Raster<T, C>, reference: &Raster<T, C>) -> f64 {
+    const EPSILON: f64 = 0.01;
+
+    mean_over_channels(raster, reference, |value, reference_value| {
+        let difference = value as f64 - reference_value as f64;
+        difference * difference / (reference_value as f64 * reference_value as f64 + EPSILON)
+    })
+}
+
+/// Returns a raster with the absolute difference between two rasters, averaged over the channels of the elements.
+pub fn difference<T: Pixel, C: Coordinate>(raster: &Raster<T, C>, reference: &Raster<T, C>) -> Raster<f32, C> {
+    check_rectangles(raster, reference);
+
+    let mut result = Raster::new(raster.rectangle().clone());
+    for (x, y) in raster.rectangle().index_iter() {
+        let (element, reference_element) = (raster.get(x, y), reference.get(x, y));
+        let sum: f32 = (0..T::CHANNEL_COUNT).map(|index| (element.channel(index) - reference_element.channel(index)).abs()).sum();
+        result.set(x, y, sum / T::CHANNEL_COUNT as f32);
+    }
+    result
+}
+
+/// Converts a difference raster (for example the result of `difference()` or `flip()`) to a heat map that goes from black (no difference) through red and
+/// yellow to white (difference greater than or equal to `max_difference`).
+pub fn heat_map<C: Coordinate>(difference: &Raster<f32, C>, max_difference: f32) -> Raster<Rgb, C> {
+    difference.map(|value| {
+        let t = if max_difference > 0.0 { (value / max_difference).clamp(0.0, 1.0) } else if value > 0.0 { 1.0 } else { 0.0 };
+        let t = if t.is_nan() { 1.0 } else { t };
+        Rgb::new(f32::min(3.0 * t, 1.0), (3.0 * t - 1.0).clamp(0.0, 1.0), (3.0 * t - 2.0).clamp(0.0, 1.0))
+    })
+}
+
+/// Asserts that the root mean squared error between two rasters is less than or equal to the given tolerance.
+#[track_caller]
+pub fn assert_rmse_within<T: Pixel, C: Coordinate>(raster: &Raster<T, C>, reference: &Raster<T, C>, tolerance: f64) {
+    let rmse = rmse(raster, reference);
+    assert!(rmse <= tolerance, "rasters differ: RMSE {} is greater than the tolerance {}", rmse, tolerance);
+}
+
+/// Asserts that the peak signal-to-noise ratio between two rasters is greater than or equal to the given minimum, in decibels.
+#[track_caller]
+pub fn assert_psnr_at_least<T: Pixel, C: Coordinate>(raster: &Raster<T, C>, reference: &Raster<T, C>, peak: f32, min_psnr: f64) {
+    let psnr = psnr(raster, reference, peak);
+    assert!(psnr >= min_psnr, "rasters differ: PSNR {} dB is less than the minimum {} dB", psnr, min_psnr);
+}
+
+/// Asserts that the structural similarity between two rasters is greater than or equal to the given minimum.
+#[track_caller]
+pub fn assert_ssim_at_least<T: Pixel, C: Coordinate>(raster: &Raster<T, C>, reference: &Raster<T, C>, min_ssim: f64) {
+    let ssim = ssim(raster, reference);
+    assert!(ssim >= min_ssim, "rasters differ: SSIM {} is less than the minimum {}", ssim, min_ssim);
+}
+
+/// Asserts that the mean FLIP error between two rasters is less than or equal to the given tolerance.
+#[track_caller]
+pub fn assert_flip_within<C: Coordinate>(raster: &Raster<Rgb, C>, reference: &Raster<Rgb, C>, pixels_per_degree: f32, tolerance: f64) {
+    let flip = mean_flip(raster, reference, pixels_per_degree);
+    assert!(flip <= tolerance, "rasters differ: mean FLIP error {} is greater than the tolerance {}", flip, tolerance);
+}
+
+#[track_caller]
+fn check_rectangles<T: Copy + Default, C: Coordinate>(raster: &Raster<T, C>, reference: &Raster<T, C>) {
+    assert_eq!(raster.rectangle(), reference.rectangle(), "rasters must have the same rectangle");
+}
+
+fn mean_over_channels<T: Pixel, C: Coordinate, F: Fn(f32, f32) -> f64>(raster: &Raster<T, C>, reference: &Raster<T, C>, error_fn: F) -> f64 {
+    check_rectangles(raster, reference);
+
+    let count = raster.rectangle().size() * T::CHANNEL_COUNT;
+    if count == 0 {
+        return 0.0;
+    }
+
+    let mut sum = 0.0;
+    for (x, y) in raster.rectangle().index_iter() {
+        let (element, reference_element) = (raster.get(x, y), reference.get(x, y));
+        for index in 0..T::CHANNEL_COUNT {
+            sum += error_fn(element.channel(index), reference_element.channel(index));
+        }
+    }
+    sum / count as f64
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use crate::rectangle::Rectangle;
+
+    use super::*;
+
+    fn constant(value: f32) -> Raster<f32> {
+        Raster::<f32>::new(Rectangle::new(10, 10, 14, 13)).map(|_| value)
+    }
+
+    #[test]
+    fn mse_rmse_psnr() {
+        let (a, b) = (constant(0.5), constant(0.25));
+        assert_eq!(mse(&a, &b), 0.0625);
+        assert_eq!(rmse(&a, &b), 0.25);
+        assert!((psnr(&a, &b, 1.0) - 12.0412).abs() < 1e-3);
+        assert_eq!(psnr(&a, &a, 1.0), f64::INFINITY);
+    }
+
+    #[test]
+    fn relative_mse_scales_with_reference() {
+        let dark = relative_mse(&constant(0.2), &constant(0.1));
+        let bright = relative_mse(&constant(10.1), &constant(10.0));
+        assert!(dark > bright, "relative error in dark regions should be larger");
+    }
+
+    #[test]
+    fn difference_rgb() {
+        let a = Raster::<Rgb>::new(Rectangle::new(0, 0, 2, 1)).map(|_| Rgb::new(0.0, 0.5, 1.0));
+        let mut b = a.clone();
+        b.set(1, 0, Rgb::new(0.3, 0.5, 0.7));
+
+        let result = difference(&a, &b);
+        assert_eq!(result.get(0, 0), 0.0);
+        assert!((result.get(1, 0) - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn heat_map_colors() {
+        let mut raster = Raster::<f32>::new(Rectangle::new(0, 0, 3, 1));
+        raster.set(1, 0, 0.5);
+        raster.set(2, 0, 2.0);
+
+        let result = heat_map(&raster, 1.0);
+        assert_eq!(result.get(0, 0), Rgb::BLACK);
+        assert_eq!(result.get(2, 0), Rgb::WHITE);
+        assert_eq!(result.get(1, 0).r, 1.0);
+    }
+
+    #[test]
+    fn assert_rmse_within_passes() {
+        assert_rmse_within(&constant(0.5), &constant(0.51), 0.02);
+        assert_psnr_at_least(&constant(0.5), &constant(0.51), 1.0, 30.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "RMSE")]
+    fn assert_rmse_within_fails() {
+        assert_rmse_within(&constant(0.5), &constant(0.6), 0.01);
+    }
+
+    #[test]
+    #[should_panic(expected = "same rectangle")]
+    fn mse_different_rectangles() {
+        mse(&constant(0.5), &Raster::<f32>::new(Rectangle::new(0, 0, 4, 3)));
+    }
+}
@@ -0,0 +1,244 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::f32::consts::PI;
+
+use crate::color::{ColorSpace, Rgb};
+use crate::raster::{BorderMode, Raster, SeparableKernel};
+use crate::rectangle::Coordinate;
+
+use super::check_rectangles;
+
+// Perceptual difference based on LDR-FLIP (Andersson et al., "FLIP: A Difference Evaluator for Alternating Images", 2020). Both rasters are filtered with
+// contrast sensitivity functions in the YCxCz opponent color space, after which a Hunt-adjusted color difference is combined with a difference in edges and
+// points detected in the luminance.
+
+/// Reference white (D65) used for the conversions between XYZ, YCxCz and L*a*b*.
+const WHITE: Rgb = Rgb::new(0.950_428_6, 1.0, 1.088_900_5);
+
+/// Returns a raster with the FLIP error for each element, in the range 0.0 (no perceived difference) to 1.0. The rasters must contain linear sRGB values in the
+/// range 0.0 to 1.0. The number of pixels per degree of visual angle determines the viewing conditions; a typical value for a desktop monitor is 67.
+pub fn flip<C: Coordinate>(raster: &Raster<Rgb, C>, reference: &Raster<Rgb, C>, pixels_per_degree: f32) -> Raster<f32, C> {
+    const QC: f32 = 0.7;
+    const PC: f32 = 0.4;
+    const PT: f32 = 0.95;
+    const QF: f32 = 0.5;
+
+    check_rectangles(raster, reference);
+
+    let filtered_test = spatial_filter(raster, pixels_per_degree);
+    let filtered_reference = spatial_filter(reference, pixels_per_degree);
+
+    let features_test = features(raster, pixels_per_degree);
+    let features_reference = features(reference, pixels_per_degree);
+
+    let max_color_difference = hyab(hunt_adjust(linear_rgb_to_lab(Rgb::new(0.0, 1.0, 0.0))), hunt_adjust(linear_rgb_to_lab(Rgb::new(0.0, 0.0, 1.0)))).powf(QC);
+
+    let mut result = Raster::new(raster.rectangle().clone());
+    for (x, y) in raster.rectangle().index_iter() {
+        let lab_test = hunt_adjust(linear_rgb_to_lab(filtered_test.get(x, y)));
+        let lab_reference = hunt_adjust(linear_rgb_to_lab(filtered_reference.get(x, y)));
+        let color_difference = hyab(lab_test, lab_reference).powf(QC);
+
+        let color_error = if color_difference < PC * max_color_difference {
+            PT / (PC * max_color_difference) * color_difference
+        } else {
+            PT + (color_difference - PC * max_color_difference) / (max_color_difference - PC * max_color_difference) * (1.0 - PT)
+        };
+
+        let ((edge_test, point_test), (edge_reference, point_reference)) = (features_test.get(x, y), features_reference.get(x, y));
+        let feature_difference = f32::max((edge_test - edge_reference).abs(), (point_test - point_reference).abs());
+        let feature_error = (feature_difference / f32::sqrt(2.0)).powf(QF);
+
+        result.set(x, y, color_error.clamp(0.0, 1.0).powf(1.0 - feature_error));
+    }
+    result
+}
+
+/// Returns the mean FLIP error between two rasters; see `flip()`.
+pub fn mean_flip<C: Coordinate>(raster: &Raster<Rgb, C>, reference: &Raster<Rgb, C>, pixels_per_degree: f32) -> f64 {
+    let map = flip(raster, reference, pixels_per_degree);
+    let count = map.rectangle().size();
+    if count == 0 {
+        return 0.0;
+    }
+
+    map.rectangle().index_iter().map(|(x, y)| map.get(x, y) as f64).sum::<f64>() / count as f64
+}
+
+/// Filters a raster with the contrast sensitivity functions of the achromatic and the two chromatic channels, and returns the result in linear sRGB, clamped
+/// to the range 0.0 to 1.0.
+fn spatial_filter<C: Coordinate>(raster: &Raster<Rgb, C>, pixels_per_degree: f32) -> Raster<Rgb, C> {
+    // Parameters (a1, b1, a2, b2) of the sums of Gaussians that approximate the contrast sensitivity functions
+    const ACHROMATIC: [f32; 4] = [1.0, 0.0047, 0.0, 1e-5];
+    const RED_GREEN: [f32; 4] = [1.0, 0.0053, 0.0, 1e-5];
+    const BLUE_YELLOW: [f32; 4] = [34.1, 0.04, 13.5, 0.025];
+
+    let max_b = [ACHROMATIC[1], ACHROMATIC[3], RED_GREEN[1], RED_GREEN[3], BLUE_YELLOW[1], BLUE_YELLOW[3]].into_iter().fold(0.0, f32::max);
+    let radius = (3.0 * f32::sqrt(max_b / (2.0 * PI * PI)) * pixels_per_degree).ceil() as i32;
+
+    let ycxcz = raster.map(|color| xyz_to_ycxcz(ColorSpace::LINEAR_SRGB.to_xyz(color)));
+    let y = csf_filter(&ycxcz.map(|color| color.r), ACHROMATIC, radius, pixels_per_degree);
+    let cx = csf_filter(&ycxcz.map(|color| color.g), RED_GREEN, radius, pixels_per_degree);
+    let cz = csf_filter(&ycxcz.map(|color| color.b), BLUE_YELLOW, radius, pixels_per_degree);
+
+    let mut result = Raster::new(raster.rectangle().clone());
+    for (px, py) in raster.rectangle().index_iter() {
+        let color = Rgb::new(y.get(px, py), cx.get(px, py), cz.get(px, py));
+        result.set(px, py, ColorSpace::LINEAR_SRGB.from_xyz(ycxcz_to_xyz(color)).map(|value| value.clamp(0.0, 1.0)));
+    }
+    result
+}
+
+/// Filters a channel with a kernel that is the sum of two Gaussians; each Gaussian is separable, so the channel is filtered with each of them separately and
+/// the results are added.
+fn csf_filter<C: Coordinate>(channel: &Raster<f32, C>, [a1, b1, a2, b2]: [f32; 4], radius: i32, pixels_per_degree: f32) -> Raster<f32, C> {
+    let gaussian = |a: f32, b: f32| -> Vec<f32> {
+        (-radius..=radius).map(|i| {
+            let x = i as f32 / pixels_per_degree;
+            f32::sqrt(a * f32::sqrt(PI / b)) * f32::exp(-PI * PI * x * x / b)
+        }).collect()
+    };
+
+    // The 2D kernel is a1 * G1(x) * G1(y) + a2 * G2(x) * G2(y); the square root of the amplitude is put in each of the 1D kernels
+    let (g1, g2) = (gaussian(a1, b1), gaussian(a2, b2));
+    let (sum1, sum2) = (g1.iter().sum::<f32>().powi(2), g2.iter().sum::<f32>().powi(2));
+    let total = sum1 + sum2;
+
+    let mut result = channel.convolve_separable(&SeparableKernel::new(g1.clone(), g1), BorderMode::Clamp);
+    if a2 != 0.0 {
+        let second = channel.convolve_separable(&SeparableKernel::new(g2.clone(), g2), BorderMode::Clamp);
+        result.merge(&second, |first, second| first + second);
+    }
+    result.map(|value| value / total)
+}
+
+/// Returns the edge and point feature magnitudes of the normalized luminance of a raster.
+fn features<C: Coordinate>(raster: &Raster<Rgb, C>, pixels_per_degree: f32) -> Raster<(f32, f32), C> {
+    const FEATURE_WIDTH: f32 = 0.082;
+
+    let sigma = 0.5 * FEATURE_WIDTH * pixels_per_degree;
+    let radius = (3.0 * sigma).ceil() as i32;
+
+    let gaussian: Vec<f32> = (-radius..=radius).map(|i| f32::exp(-((i * i) as f32) / (2.0 * sigma * sigma))).collect();
+    let gaussian_sum: f32 = gaussian.iter().sum();
+    let gaussian: Vec<f32> = gaussian.into_iter().map(|weight| weight / gaussian_sum).collect();
+
+    let first_derivative = normalize_signed((-radius..=radius).zip(&gaussian).map(|(i, &weight)| -(i as f32) * weight).collect());
+    let second_derivative = normalize_signed((-radius..=radius).zip(&gaussian)
+        .map(|(i, &weight)| ((i * i) as f32 / (sigma * sigma) - 1.0) * weight).collect());
+
+    let luminance = raster.map(|color| (xyz_to_ycxcz(ColorSpace::LINEAR_SRGB.to_xyz(color.map(|value| value.clamp(0.0, 1.0)))).r + 16.0) / 116.0);
+
+    let magnitude = |kernel: &[f32]| -> Raster<f32, C> {
+        let mut horizontal = luminance.convolve_separable(&SeparableKernel::new(kernel.to_vec(), gaussian.clone()), BorderMode::Clamp);
+        let vertical = luminance.convolve_separable(&SeparableKernel::new(gaussian.clone(), kernel.to_vec()), BorderMode::Clamp);
+        horizontal.merge(&vertical, |h, v| f32::sqrt(h * h + v * v));
+        horizontal
+    };
+
+    let mut result = magnitude(&first_derivative).map(|edge| (edge, 0.0));
+    result.merge(&magnitude(&second_derivative), |(edge, _), point| (edge, point));
+    result
+}
+
+/// Scales the positive weights of a kernel so that they sum to 1.0 and the negative weights so that they sum to -1.0.
+fn normalize_signed(weights: Vec<f32>) -> Vec<f32> {
+    let positive: f32 = weights.iter().filter(|&&weight| weight > 0.0).sum();
+    let negative: f32 = -weights.iter().filter(|&&weight| weight < 0.0).sum::<f32>();
+    weights.into_iter().map(|weight| {
+        if weight > 0.0 { weight / positive } else if weight < 0.0 { weight / negative } else { 0.0 }
+    }).collect()
+}
+
+fn xyz_to_ycxcz(xyz: Rgb) -> Rgb {
+    let (x, y, z) = (xyz.r / WHITE.r, xyz.g / WHITE.g, xyz.b / WHITE.b);
+    Rgb::new(116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z))
+}
+
+fn ycxcz_to_xyz(ycxcz: Rgb) -> Rgb {
+    let y = (ycxcz.r + 16.0) / 116.0;
+    let x = ycxcz.g / 500.0 + y;
+    let z = y - ycxcz.b / 200.0;
+    Rgb::new(x * WHITE.r, y * WHITE.g, z * WHITE.b)
+}
+
+fn linear_rgb_to_lab(color: Rgb) -> Rgb {
+    const DELTA: f32 = 6.0 / 29.0;
+
+    let f = |t: f32| if t > DELTA * DELTA * DELTA { t.cbrt() } else { t / (3.0 * DELTA * DELTA) + 4.0 / 29.0 };
+
+    let xyz = ColorSpace::LINEAR_SRGB.to_xyz(color);
+    let (fx, fy, fz) = (f(xyz.r / WHITE.r), f(xyz.g / WHITE.g), f(xyz.b / WHITE.b));
+    Rgb::new(116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+fn hunt_adjust(lab: Rgb) -> Rgb {
+    Rgb::new(lab.r, 0.01 * lab.r * lab.g, 0.01 * lab.r * lab.b)
+}
+
+fn hyab(a: Rgb, b: Rgb) -> f32 {
+    let (dl, da, db) = (a.r - b.r, a.g - b.g, a.b - b.b);
+    dl.abs() + f32::sqrt(da * da + db * db)
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use crate::rectangle::Rectangle;
+
+    use super::*;
+
+    fn checkerboard(a: Rgb, b: Rgb) -> Raster<Rgb> {
+        let mut raster = Raster::new(Rectangle::new(0, 0, 24, 24));
+        for (x, y) in raster.rectangle().clone().index_iter() {
+            raster.set(x, y, if (x / 4 + y / 4) % 2 == 0 { a } else { b });
+        }
+        raster
+    }
+
+    #[test]
+    fn flip_equal() {
+        let raster = checkerboard(Rgb::gray(0.2), Rgb::new(0.8, 0.4, 0.1));
+        assert!(mean_flip(&raster, &raster, 67.0) < 1e-4);
+    }
+
+    #[test]
+    fn flip_increases_with_difference() {
+        let reference = checkerboard(Rgb::gray(0.2), Rgb::gray(0.8));
+        let similar = checkerboard(Rgb::gray(0.22), Rgb::gray(0.78));
+        let different = checkerboard(Rgb::new(0.8, 0.1, 0.1), Rgb::new(0.1, 0.1, 0.8));
+
+        let small = mean_flip(&similar, &reference, 67.0);
+        let large = mean_flip(&different, &reference, 67.0);
+        assert!(small > 0.0 && small < large, "similar: {}, different: {}", small, large);
+
+        let map = flip(&different, &reference, 67.0);
+        assert!(map.rectangle().index_iter().all(|(x, y)| (0.0..=1.0).contains(&map.get(x, y))));
+    }
+
+    #[test]
+    fn lab_white() {
+        let lab = linear_rgb_to_lab(Rgb::WHITE);
+        assert!((lab.r - 100.0).abs() < 0.1 && lab.g.abs() < 0.1 && lab.b.abs() < 0.1, "{:?}", lab);
+    }
+
+    #[test]
+    fn ycxcz_round_trip() {
+        let xyz = Rgb::new(0.3, 0.4, 0.5);
+        let result = ycxcz_to_xyz(xyz_to_ycxcz(xyz));
+        assert!((result.r - xyz.r).abs() < 1e-5 && (result.g - xyz.g).abs() < 1e-5 && (result.b - xyz.b).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,117 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::io::Pixel;
+use crate::raster::{BorderMode, Raster, SeparableKernel};
+use crate::rectangle::Coordinate;
+
+use super::check_rectangles;
+
+/// Returns the mean structural similarity index (SSIM) between two rasters, averaged over the channels of the elements. The result is 1.0 for equal rasters
+/// and decreases as the rasters differ more; values are assumed to be in the range 0.0 to 1.0.
+///
+/// Local statistics are computed with an 11x11 Gaussian window with standard deviation 1.5, as in the original paper by Wang et al.
+pub fn ssim<T: Pixel, C: Coordinate>(raster: &Raster<T, C>, reference: &Raster<T, C>) -> f64 {
+    let map = ssim_map(raster, reference);
+    let count = map.rectangle().size();
+    if count == 0 {
+        return 1.0;
+    }
+
+    map.rectangle().index_iter().map(|(x, y)| map.get(x, y) as f64).sum::<f64>() / count as f64
+}
+
+/// Returns a raster with the local structural similarity index (SSIM) for each element, averaged over the channels of the elements.
+pub fn ssim_map<T: Pixel, C: Coordinate>(raster: &Raster<T, C>, reference: &Raster<T, C>) -> Raster<f32, C> {
+    const C1: f32 = 0.01 * 0.01;
+    const C2: f32 = 0.03 * 0.03;
+
+    check_rectangles(raster, reference);
+
+    let kernel = gaussian_window(1.5, 5);
+    let mut result = Raster::<f32, C>::new(raster.rectangle().clone());
+
+    for index in 0..T::CHANNEL_COUNT {
+        let a = raster.map(|element| element.channel(index));
+        let b = reference.map(|element| element.channel(index));
+
+        let mean_a = a.convolve_separable(&kernel, BorderMode::Mirror);
+        let mean_b = b.convolve_separable(&kernel, BorderMode::Mirror);
+        let mean_aa = a.map(|value| value * value).convolve_separable(&kernel, BorderMode::Mirror);
+        let mean_bb = b.map(|value| value * value).convolve_separable(&kernel, BorderMode::Mirror);
+        let mut ab = a.clone();
+        ab.merge(&b, |value_a, value_b| value_a * value_b);
+        let mean_ab = ab.convolve_separable(&kernel, BorderMode::Mirror);
+
+        for (x, y) in raster.rectangle().index_iter() {
+            let (mu_a, mu_b) = (mean_a.get(x, y), mean_b.get(x, y));
+            let variance_a = mean_aa.get(x, y) - mu_a * mu_a;
+            let variance_b = mean_bb.get(x, y) - mu_b * mu_b;
+            let covariance = mean_ab.get(x, y) - mu_a * mu_b;
+
+            let ssim = ((2.0 * mu_a * mu_b + C1) * (2.0 * covariance + C2)) /
+                ((mu_a * mu_a + mu_b * mu_b + C1) * (variance_a + variance_b + C2));
+
+            *result.get_mut(x, y) += ssim / T::CHANNEL_COUNT as f32;
+        }
+    }
+
+    result
+}
+
+fn gaussian_window(sigma: f32, radius: i32) -> SeparableKernel {
+    let weights: Vec<f32> = (-radius..=radius).map(|i| f32::exp(-((i * i) as f32) / (2.0 * sigma * sigma))).collect();
+    let sum: f32 = weights.iter().sum();
+    let weights: Vec<f32> = weights.into_iter().map(|weight| weight / sum).collect();
+    SeparableKernel::new(weights.clone(), weights)
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use crate::rectangle::Rectangle;
+
+    use super::*;
+
+    fn gradient() -> Raster<f32> {
+        let mut raster = Raster::new(Rectangle::new(0, 0, 32, 24));
+        for (x, y) in raster.rectangle().clone().index_iter() {
+            raster.set(x, y, ((x + 2 * y) % 16) as f32 / 16.0);
+        }
+        raster
+    }
+
+    #[test]
+    fn ssim_equal() {
+        let raster = gradient();
+        assert!((ssim(&raster, &raster) - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn ssim_decreases_with_noise() {
+        let raster = gradient();
+        let mut slightly_noisy = raster.clone();
+        let mut very_noisy = raster.clone();
+        for (x, y) in raster.rectangle().index_iter() {
+            let noise = if (x * 7 + y * 13) % 3 == 0 { 1.0 } else { -1.0 };
+            *slightly_noisy.get_mut(x, y) += noise * 0.02;
+            *very_noisy.get_mut(x, y) += noise * 0.2;
+        }
+
+        let slightly = ssim(&slightly_noisy, &raster);
+        let very = ssim(&very_noisy, &raster);
+        assert!(slightly < 1.0 && very < slightly, "slightly noisy: {}, very noisy: {}", slightly, very);
+    }
+}
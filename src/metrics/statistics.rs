@@ -0,0 +1,328 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Range;
+
+use crate::io::Pixel;
+use crate::raster::Raster;
+use crate::rectangle::Coordinate;
+
+/// Statistics of the elements of a raster, per channel.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Statistics {
+    element_count: usize,
+    non_finite_element_count: usize,
+    channels: Vec<ChannelStatistics>,
+}
+
+/// Statistics of one channel of the elements of a raster. The minimum, maximum, sum and mean only include finite values.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ChannelStatistics {
+    min: f32,
+    max: f32,
+    sum: f64,
+    finite_count: usize,
+    nan_count: usize,
+    infinite_count: usize,
+}
+
+/// Histogram of values with bins of equal width over a range. Values outside the range are counted separately; NaN values are not counted.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Histogram {
+    range: Range<f32>,
+    bins: Vec<usize>,
+    underflow_count: usize,
+    overflow_count: usize,
+}
+
+// ===== Statistics ============================================================================================================================================
+
+impl Statistics {
+    /// Returns the number of elements.
+    #[inline]
+    pub fn element_count(&self) -> usize {
+        self.element_count
+    }
+
+    /// Returns the number of elements that have at least one channel with a NaN or infinite value.
+    #[inline]
+    pub fn non_finite_element_count(&self) -> usize {
+        self.non_finite_element_count
+    }
+
+    /// Checks if there are elements with NaN or infinite values.
+    #[inline]
+    pub fn has_non_finite(&self) -> bool {
+        self.non_finite_element_count > 0
+    }
+
+    /// Returns the statistics of all channels.
+    #[inline]
+    pub fn channels(&self) -> &[ChannelStatistics] {
+        &self.channels
+    }
+
+    /// Returns the statistics of the channel with the given index.
+    #[inline]
+    pub fn channel(&self, index: usize) -> &ChannelStatistics {
+        &self.channels[index]
+    }
+}
+
+// ===== ChannelStatistics =====================================================================================================================================
+
+impl ChannelStatistics {
+    fn new() -> ChannelStatistics {
+        ChannelStatistics { min: f32::INFINITY, max: f32::NEG_INFINITY, sum: 0.0, finite_count: 0, nan_count: 0, infinite_count: 0 }
+    }
+
+    fn add(&mut self, value: f32) -> bool {
+        if value.is_finite() {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+            self.sum += value as f64;
+            self.finite_count += 1;
+            true
+        } else {
+            if value.is_nan() { self.nan_count += 1; } else { self.infinite_count += 1; }
+            false
+        }
+    }
+
+    /// Returns the minimum finite value, or `None` if there are no finite values.
+    #[inline]
+    pub fn min(&self) -> Option<f32> {
+        if self.finite_count > 0 { Some(self.min) } else { None }
+    }
+
+    /// Returns the maximum finite value, or `None` if there are no finite values.
+    #[inline]
+    pub fn max(&self) -> Option<f32> {
+        if self.finite_count > 0 { Some(self.max) } else { None }
+    }
+
+    /// Returns the sum of the finite values.
+    #[inline]
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// Returns the mean of the finite values, or `None` if there are no finite values.
+    #[inline]
+    pub fn mean(&self) -> Option<f64> {
+        if self.finite_count > 0 { Some(self.sum / self.finite_count as f64) } else { None }
+    }
+
+    /// Returns the number of finite values.
+    #[inline]
+    pub fn finite_count(&self) -> usize {
+        self.finite_count
+    }
+
+    /// Returns the number of NaN values.
+    #[inline]
+    pub fn nan_count(&self) -> usize {
+        self.nan_count
+    }
+
+    /// Returns the number of positive and negative infinite values.
+    #[inline]
+    pub fn infinite_count(&self) -> usize {
+        self.infinite_count
+    }
+}
+
+// ===== Histogram =============================================================================================================================================
+
+impl Histogram {
+    /// Creates a new empty histogram with the given number of bins over a range of values.
+    pub fn new(bin_count: usize, range: Range<f32>) -> Histogram {
+        assert!(bin_count > 0, "bin count must be greater than zero");
+        assert!(range.start < range.end, "range must not be empty");
+        Histogram { range, bins: vec![0; bin_count], underflow_count: 0, overflow_count: 0 }
+    }
+
+    /// Adds a value to this histogram.
+    pub fn add(&mut self, value: f32) {
+        if value < self.range.start {
+            self.underflow_count += 1;
+        } else if value >= self.range.end {
+            self.overflow_count += 1;
+        } else if !value.is_nan() {
+            let bin_count = self.bins.len();
+            let index = ((value - self.range.start) / (self.range.end - self.range.start) * bin_count as f32) as usize;
+            self.bins[index.min(bin_count - 1)] += 1;
+        }
+    }
+
+    /// Returns the range of values of this histogram.
+    #[inline]
+    pub fn range(&self) -> &Range<f32> {
+        &self.range
+    }
+
+    /// Returns the counts of the bins.
+    #[inline]
+    pub fn bins(&self) -> &[usize] {
+        &self.bins
+    }
+
+    /// Returns the range of values of the bin with the given index.
+    pub fn bin_range(&self, index: usize) -> Range<f32> {
+        let width = (self.range.end - self.range.start) / self.bins.len() as f32;
+        (self.range.start + index as f32 * width)..(self.range.start + (index + 1) as f32 * width)
+    }
+
+    /// Returns the number of values that were less than the start of the range.
+    #[inline]
+    pub fn underflow_count(&self) -> usize {
+        self.underflow_count
+    }
+
+    /// Returns the number of values that were greater than or equal to the end of the range.
+    #[inline]
+    pub fn overflow_count(&self) -> usize {
+        self.overflow_count
+    }
+}
+
+// ===== Raster ================================================================================================================================================
+
+impl<T: Pixel, C: Coordinate> Raster<T, C> {
+    /// Computes statistics of the elements of this raster.
+    pub fn statistics(&self) -> Statistics {
+        let mut channels = vec![ChannelStatistics::new(); T::CHANNEL_COUNT];
+        let mut non_finite_element_count = 0;
+
+        for (x, y) in self.rectangle().index_iter() {
+            let element = self.get(x, y);
+            let mut finite = true;
+            for (index, channel) in channels.iter_mut().enumerate() {
+                finite &= channel.add(element.channel(index));
+            }
+            if !finite {
+                non_finite_element_count += 1;
+            }
+        }
+
+        Statistics { element_count: self.rectangle().size(), non_finite_element_count, channels }
+    }
+
+    /// Returns the percentiles of the finite values of a channel, for percentages in the range 0.0 to 100.0, using linear interpolation between the nearest
+    /// values. Returns `None` if there are no finite values.
+    pub fn percentiles(&self, channel_index: usize, percentages: &[f32]) -> Option<Vec<f32>> {
+        let mut values: Vec<f32> = self.rectangle().index_iter()
+            .map(|(x, y)| self.get(x, y).channel(channel_index))
+            .filter(|value| value.is_finite())
+            .collect();
+
+        if values.is_empty() {
+            return None;
+        }
+
+        values.sort_unstable_by(f32::total_cmp);
+
+        Some(percentages.iter().map(|&percentage| {
+            let position = (percentage.clamp(0.0, 100.0) / 100.0) * (values.len() - 1) as f32;
+            let (index, t) = (position.floor() as usize, position.fract());
+            let next = values[(index + 1).min(values.len() - 1)];
+            values[index] + (next - values[index]) * t
+        }).collect())
+    }
+
+    /// Returns the percentile of the finite values of a channel; see `percentiles()`.
+    pub fn percentile(&self, channel_index: usize, percentage: f32) -> Option<f32> {
+        self.percentiles(channel_index, &[percentage]).map(|values| values[0])
+    }
+
+    /// Computes a histogram of the values of a channel with the given number of bins over a range of values.
+    pub fn histogram(&self, channel_index: usize, bin_count: usize, range: Range<f32>) -> Histogram {
+        let mut histogram = Histogram::new(bin_count, range);
+        for (x, y) in self.rectangle().index_iter() {
+            histogram.add(self.get(x, y).channel(channel_index));
+        }
+        histogram
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use crate::color::Rgb;
+    use crate::rectangle::Rectangle;
+
+    use super::*;
+
+    fn ramp() -> Raster<f32> {
+        let mut raster = Raster::new(Rectangle::new(0, 0, 5, 2));
+        for (x, y) in raster.rectangle().clone().index_iter() {
+            raster.set(x, y, (y * 5 + x) as f32);
+        }
+        raster
+    }
+
+    #[test]
+    fn statistics_ramp() {
+        let statistics = ramp().statistics();
+        assert_eq!(statistics.element_count(), 10);
+        assert!(!statistics.has_non_finite());
+
+        let channel = statistics.channel(0);
+        assert_eq!((channel.min(), channel.max()), (Some(0.0), Some(9.0)));
+        assert_eq!(channel.sum(), 45.0);
+        assert_eq!(channel.mean(), Some(4.5));
+    }
+
+    #[test]
+    fn statistics_non_finite() {
+        let mut raster = Raster::<Rgb>::new(Rectangle::new(0, 0, 3, 1));
+        raster.set(0, 0, Rgb::new(f32::NAN, 1.0, f32::INFINITY));
+        raster.set(1, 0, Rgb::new(2.0, f32::NEG_INFINITY, 3.0));
+
+        let statistics = raster.statistics();
+        assert_eq!(statistics.non_finite_element_count(), 2);
+        assert_eq!(statistics.channel(0).nan_count(), 1);
+        assert_eq!(statistics.channel(1).infinite_count(), 1);
+        assert_eq!(statistics.channel(2).infinite_count(), 1);
+        assert_eq!(statistics.channel(0).max(), Some(2.0));
+        assert_eq!(statistics.channel(2).finite_count(), 2);
+    }
+
+    #[test]
+    fn statistics_empty() {
+        let statistics = Raster::<f32>::new(Rectangle::new(0, 0, 0, 0)).statistics();
+        assert_eq!(statistics.channel(0).min(), None);
+        assert_eq!(statistics.channel(0).mean(), None);
+    }
+
+    #[test]
+    fn raster_percentiles() {
+        let mut raster = ramp();
+        raster.set(0, 0, f32::NAN);
+
+        assert_eq!(raster.percentiles(0, &[0.0, 50.0, 100.0]), Some(vec![1.0, 5.0, 9.0]));
+        assert_eq!(raster.percentile(0, 25.0), Some(3.0));
+        assert_eq!(Raster::<f32>::new(Rectangle::new(0, 0, 0, 0)).percentile(0, 50.0), None);
+    }
+
+    #[test]
+    fn raster_histogram() {
+        let histogram = ramp().histogram(0, 4, 1.0..9.0);
+        assert_eq!(histogram.bins(), &[2, 2, 2, 2]);
+        assert_eq!(histogram.underflow_count(), 1);
+        assert_eq!(histogram.overflow_count(), 1);
+        assert_eq!(histogram.bin_range(1), 3.0..5.0);
+    }
+}
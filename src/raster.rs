@@ -12,18 +12,61 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::rectangle::Rectangle;
+use crossbeam_utils::thread;
 
+pub use convolve::*;
+pub use iter::*;
+pub use pyramid::*;
+pub use tiled::*;
+pub use view::*;
+
+use crate::rectangle::{Coordinate, Rectangle};
+
+mod transform;
+mod resample;
+mod convolve;
+mod iter;
+mod view;
+mod tiled;
+mod pyramid;
+
+/// Rectangular grid of elements.
+///
+/// With the `serde` feature, a raster is serialized as its rectangle followed by the elements as one flat sequence in row-major order, so that binary formats
+/// store the elements without any per-row overhead.
 #[derive(Clone)]
-pub struct Raster<T: Copy + Default> {
-    rectangle: Rectangle,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RasterData<T, C>"))]
+pub struct Raster<T: Copy + Default, C: Coordinate = u32> {
+    rectangle: Rectangle<C>,
+    elements: Vec<T>,
+}
+
+/// Determines the value of elements outside the rectangle of a raster.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BorderMode {
+    /// Elements outside the raster have the default value.
+    Default,
+    /// Elements outside the raster have the value of the nearest element at the edge.
+    Clamp,
+    /// The raster repeats in all directions.
+    Wrap,
+    /// The raster is mirrored at its edges.
+    Mirror,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct RasterData<T, C: Coordinate> {
+    rectangle: Rectangle<C>,
     elements: Vec<T>,
 }
 
 // ===== Raster ================================================================================================================================================
 
-impl<T: Copy + Default> Raster<T> {
-    pub fn new(rectangle: Rectangle) -> Raster<T> {
+impl<T: Copy + Default, C: Coordinate> Raster<T, C> {
+    pub fn new(rectangle: Rectangle<C>) -> Raster<T, C> {
         let size = rectangle.size();
         let mut elements = Vec::with_capacity(size);
         elements.resize_with(size, T::default);
@@ -31,30 +74,85 @@ impl<T: Copy + Default> Raster<T> {
         Raster { rectangle, elements }
     }
 
+    /// Creates a raster from a vector of elements in row-major order; the number of elements must be equal to the size of the rectangle.
+    pub fn from_vec(rectangle: Rectangle<C>, elements: Vec<T>) -> Raster<T, C> {
+        assert_eq!(elements.len(), rectangle.size(), "number of elements must be equal to the size of the rectangle");
+        Raster { rectangle, elements }
+    }
+
     #[inline]
-    pub fn rectangle(&self) -> &Rectangle {
+    pub fn rectangle(&self) -> &Rectangle<C> {
         &self.rectangle
     }
 
     #[inline]
-    pub fn get(&self, x: u32, y: u32) -> T {
+    pub fn get(&self, x: C, y: C) -> T {
         let index = self.rectangle.linear_index(x, y);
         self.elements[index]
     }
 
     #[inline]
-    pub fn get_mut(&mut self, x: u32, y: u32) -> &mut T {
+    pub fn get_mut(&mut self, x: C, y: C) -> &mut T {
         let index = self.rectangle.linear_index(x, y);
         &mut self.elements[index]
     }
 
     #[inline]
-    pub fn set(&mut self, x: u32, y: u32, value: T) {
+    pub fn set(&mut self, x: C, y: C, value: T) {
         let index = self.rectangle.linear_index(x, y);
         self.elements[index] = value;
     }
 
-    pub fn merge<U: Copy + Default, F: FnMut(T, U) -> T>(&mut self, other: &Raster<U>, mut merge_fn: F) {
+    /// Returns the elements of this raster in row-major order.
+    #[inline]
+    pub fn as_slice(&self) -> &[T] {
+        &self.elements
+    }
+
+    /// Returns the mutable elements of this raster in row-major order.
+    #[inline]
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.elements
+    }
+
+    /// Consumes this raster and returns its elements in row-major order.
+    #[inline]
+    pub fn into_vec(self) -> Vec<T> {
+        self.elements
+    }
+
+    /// Returns the element at the given position, which may be outside the rectangle of this raster, in which case the border mode determines the value.
+    pub fn get_bordered(&self, x: i64, y: i64, border_mode: BorderMode) -> T {
+        let r = &self.rectangle;
+        if r.is_empty() {
+            return T::default();
+        }
+
+        let map = |v: i64, min: C, max: C| -> Option<C> {
+            let (min, max) = (min.to_i64(), max.to_i64());
+            if v >= min && v < max {
+                return Some(C::from_i64(v));
+            }
+
+            let size = max - min;
+            match border_mode {
+                BorderMode::Default => None,
+                BorderMode::Clamp => Some(C::from_i64(v.clamp(min, max - 1))),
+                BorderMode::Wrap => Some(C::from_i64(min + (v - min).rem_euclid(size))),
+                BorderMode::Mirror => {
+                    let p = (v - min).rem_euclid(2 * size);
+                    Some(C::from_i64(min + if p < size { p } else { 2 * size - 1 - p }))
+                }
+            }
+        };
+
+        match (map(x, r.left, r.right), map(y, r.top, r.bottom)) {
+            (Some(x), Some(y)) => self.get(x, y),
+            _ => T::default(),
+        }
+    }
+
+    pub fn merge<U: Copy + Default, F: FnMut(T, U) -> T>(&mut self, other: &Raster<U, C>, mut merge_fn: F) {
         if let Some(intersection) = self.rectangle.intersection(other.rectangle()) {
             for (x, y) in intersection.index_iter() {
                 let index = self.rectangle.linear_index(x, y);
@@ -64,7 +162,7 @@ impl<T: Copy + Default> Raster<T> {
         }
     }
 
-    pub fn map<U: Copy + Default, F: FnMut(T) -> U>(&self, mut map_fn: F) -> Raster<U> {
+    pub fn map<U: Copy + Default, F: FnMut(T) -> U>(&self, mut map_fn: F) -> Raster<U, C> {
         let rectangle = self.rectangle.clone();
 
         let mut elements = Vec::with_capacity(self.elements.capacity());
@@ -76,6 +174,103 @@ impl<T: Copy + Default> Raster<T> {
     }
 }
 
+impl<T: Copy + Default + Send + Sync, C: Coordinate> Raster<T, C> {
+    /// Maps all elements of this raster like `map()`, distributing the work by rows over multiple threads.
+    pub fn map_parallel<U: Copy + Default + Send + Sync, F: Fn(T) -> U + Sync>(&self, map_fn: F) -> Raster<U, C> {
+        Raster::from_rows_parallel(self.rectangle.clone(), |y, row| {
+            for (element, &source) in row.iter_mut().zip(self.row(y)) {
+                *element = map_fn(source);
+            }
+        })
+    }
+
+    /// Maps all elements of this raster in place, distributing the work by rows over multiple threads.
+    pub fn map_mut_parallel<F: Fn(T) -> T + Sync>(&mut self, map_fn: F) {
+        self.update_rows_parallel(|_, row| {
+            for element in row {
+                *element = map_fn(*element);
+            }
+        });
+    }
+
+    /// Combines the elements of this raster and another raster with the same rectangle into a new raster, distributing the work by rows over multiple
+    /// threads.
+    pub fn zip_map_parallel<U, V, F>(&self, other: &Raster<U, C>, zip_fn: F) -> Raster<V, C>
+        where
+            U: Copy + Default + Send + Sync,
+            V: Copy + Default + Send + Sync,
+            F: Fn(T, U) -> V + Sync
+    {
+        assert_eq!(self.rectangle, other.rectangle, "rasters must have the same rectangle");
+
+        Raster::from_rows_parallel(self.rectangle.clone(), |y, row| {
+            for ((element, &a), &b) in row.iter_mut().zip(self.row(y)).zip(other.row(y)) {
+                *element = zip_fn(a, b);
+            }
+        })
+    }
+
+    /// Merges another raster into this raster like `merge()`, distributing the work by rows over multiple threads.
+    pub fn merge_parallel<U: Copy + Default + Send + Sync, F: Fn(T, U) -> T + Sync>(&mut self, other: &Raster<U, C>, merge_fn: F) {
+        if let Some(intersection) = self.rectangle.intersection(other.rectangle()) {
+            let start = (intersection.left.to_i64() - self.rectangle.left.to_i64()) as usize;
+            let other_start = (intersection.left.to_i64() - other.rectangle.left.to_i64()) as usize;
+            let width = intersection.width() as usize;
+
+            self.update_rows_parallel(|y, row| {
+                if y >= intersection.top && y < intersection.bottom {
+                    for (element, &source) in row[start..start + width].iter_mut().zip(&other.row(y)[other_start..]) {
+                        *element = merge_fn(*element, source);
+                    }
+                }
+            });
+        }
+    }
+
+    /// Creates a raster by computing its rows in parallel. The row function is called with the y coordinate and the elements of a row.
+    pub(crate) fn from_rows_parallel<F: Fn(C, &mut [T]) + Sync>(rectangle: Rectangle<C>, row_fn: F) -> Raster<T, C> {
+        let mut raster = Raster::new(rectangle);
+        raster.update_rows_parallel(row_fn);
+        raster
+    }
+
+    /// Updates the rows of this raster in parallel, in bands of rows distributed over the available CPUs. The row function is called with the y
+    /// coordinate and the elements of a row.
+    pub(crate) fn update_rows_parallel<F: Fn(C, &mut [T]) + Sync>(&mut self, row_fn: F) {
+        let (width, height) = (self.rectangle.width() as usize, self.rectangle.height() as usize);
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        let thread_count = usize::min(num_cpus::get(), height);
+        let rows_per_band = height.div_ceil(thread_count);
+        let row_fn = &row_fn;
+
+        thread::scope(|scope| {
+            for (top, band) in self.row_chunks_mut(rows_per_band as u32) {
+                scope.spawn(move |_| {
+                    for (row_index, row) in band.chunks_mut(width).enumerate() {
+                        row_fn(top.offset(row_index as i64), row);
+                    }
+                });
+            }
+        }).unwrap();
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: Copy + Default, C: Coordinate> TryFrom<RasterData<T, C>> for Raster<T, C> {
+    type Error = String;
+
+    fn try_from(data: RasterData<T, C>) -> Result<Raster<T, C>, String> {
+        if data.elements.len() == data.rectangle.size() {
+            Ok(Raster { rectangle: data.rectangle, elements: data.elements })
+        } else {
+            Err(format!("raster with rectangle {:?} must have {} elements but has {}", data.rectangle, data.rectangle.size(), data.elements.len()))
+        }
+    }
+}
+
 // ===== Tests =================================================================================================================================================
 
 #[cfg(test)]
@@ -102,34 +297,163 @@ mod test {
         assert_eq!(raster.get(12, 40), 64u8);
     }
 
+    #[test]
+    fn raster_get_bordered() {
+        let mut raster = Raster::<u8>::new(Rectangle::new(10, 20, 13, 21));
+        raster.set(10, 20, 1);
+        raster.set(11, 20, 2);
+        raster.set(12, 20, 3);
+
+        let row = |y: i64, border_mode: BorderMode| -> Vec<u8> { (6..17).map(|x| raster.get_bordered(x, y, border_mode)).collect() };
+        assert_eq!(row(20, BorderMode::Default), vec![0, 0, 0, 0, 1, 2, 3, 0, 0, 0, 0]);
+        assert_eq!(row(20, BorderMode::Clamp), vec![1, 1, 1, 1, 1, 2, 3, 3, 3, 3, 3]);
+        assert_eq!(row(20, BorderMode::Wrap), vec![3, 1, 2, 3, 1, 2, 3, 1, 2, 3, 1]);
+        assert_eq!(row(20, BorderMode::Mirror), vec![3, 3, 2, 1, 1, 2, 3, 3, 2, 1, 1]);
+        assert_eq!(row(-5, BorderMode::Default), vec![0; 11]);
+        assert_eq!(row(-5, BorderMode::Clamp), row(20, BorderMode::Clamp));
+    }
+
+    #[test]
+    fn raster_negative_coordinates() {
+        let mut raster = Raster::<i32, i32>::new(Rectangle::new(-2, -1, 2, 1));
+        for (x, y, element) in raster.iter_mut() {
+            *element = x * 10 + y;
+        }
+        assert_eq!(raster.get(-2, -1), -21);
+        assert_eq!(raster.row(0), &[-20, -10, 0, 10]);
+        assert_eq!(raster.get_bordered(-3, 0, BorderMode::Mirror), -20);
+        assert_eq!(raster.get_bordered(2, -2, BorderMode::Wrap), -20);
+    }
+
+    #[test]
+    fn raster_from_rows_parallel() {
+        let raster = Raster::<u32>::from_rows_parallel(Rectangle::new(10, 20, 30, 85), |y, row| {
+            for (i, element) in row.iter_mut().enumerate() {
+                *element = y * 1000 + 10 + i as u32;
+            }
+        });
+
+        for (x, y) in raster.rectangle.index_iter() {
+            assert_eq!(raster.get(x, y), y * 1000 + x, "wrong value at ({}, {})", x, y);
+        }
+    }
+
     #[test]
     fn raster_merge() {
         let mut source = Raster::<i16>::new(Rectangle::new(0, 0, 50, 80));
-        let mut v = 0i16;
-        for (x, y) in source.rectangle.index_iter() {
-            source.set(x, y, v);
-            v += 1;
+        for (v, (x, y)) in source.rectangle.index_iter().enumerate() {
+            source.set(x, y, v as i16);
         }
         let source = source; // Reassign to make it immutable
 
         let mut target = Raster::<i16>::new(Rectangle::new(10, 20, 100, 220));
         target.merge(&source, |v1, v2| { v1 + v2 });
 
-        todo!()
+        // Elements inside the intersection are merged, elements outside are unchanged
+        for (x, y) in target.rectangle.index_iter() {
+            let expected = if x < 50 && y < 80 { source.get(x, y) } else { 0 };
+            assert_eq!(target.get(x, y), expected, "wrong value at ({}, {})", x, y);
+        }
     }
 
     #[test]
     fn raster_map() {
         let mut source = Raster::<i16>::new(Rectangle::new(0, 0, 50, 80));
-        let mut v = 0i16;
-        for (x, y) in source.rectangle.index_iter() {
-            source.set(x, y, v);
-            v += 1;
+        for (v, (x, y)) in source.rectangle.index_iter().enumerate() {
+            source.set(x, y, v as i16);
         }
         let source = source; // Reassign to make it immutable
 
         let result = source.map(|v| { v + 1 });
 
-        todo!()
+        assert_eq!(*result.rectangle(), *source.rectangle());
+        for (x, y) in source.rectangle.index_iter() {
+            assert_eq!(result.get(x, y), source.get(x, y) + 1, "wrong value at ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn raster_map_parallel() {
+        let mut source = Raster::<u32>::new(Rectangle::new(10, 20, 110, 220));
+        for (x, y, element) in source.iter_mut() {
+            *element = x + y * 1000;
+        }
+
+        let result = source.map_parallel(|v| v as u64 * 2);
+        assert_eq!(result.as_slice(), source.map(|v| v as u64 * 2).as_slice());
+
+        let mut result = source.clone();
+        result.map_mut_parallel(|v| v + 1);
+        assert_eq!(result.as_slice(), source.map(|v| v + 1).as_slice());
+    }
+
+    #[test]
+    fn raster_zip_map_parallel() {
+        let a = Raster::<u32>::new(Rectangle::new(10, 20, 50, 60)).map(|_| 3);
+        let mut b = Raster::<u16>::new(Rectangle::new(10, 20, 50, 60));
+        b.set(12, 30, 4);
+
+        let result = a.zip_map_parallel(&b, |a, b| a * b as u32 + 1);
+        assert_eq!(result.get(12, 30), 13);
+        assert_eq!(result.get(49, 59), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "same rectangle")]
+    fn raster_zip_map_parallel_different_rectangles() {
+        let a = Raster::<u32>::new(Rectangle::new(10, 20, 50, 60));
+        a.zip_map_parallel(&Raster::<u32>::new(Rectangle::new(0, 0, 40, 40)), |a, b| a + b);
+    }
+
+    #[test]
+    fn raster_merge_parallel() {
+        let mut target = Raster::<u32>::new(Rectangle::new(10, 20, 100, 220)).map(|_| 1);
+        let mut other = Raster::<u32>::new(Rectangle::new(50, 100, 150, 250));
+        for (x, y, element) in other.iter_mut() {
+            *element = x + y;
+        }
+
+        let mut expected = target.clone();
+        expected.merge(&other, |t, o| t + o);
+        target.merge_parallel(&other, |t, o| t + o);
+        assert_eq!(target.as_slice(), expected.as_slice());
+    }
+
+    #[test]
+    fn raster_from_vec() {
+        let mut raster = Raster::from_vec(Rectangle::new(10, 20, 12, 22), vec![1u8, 2, 3, 4]);
+        assert_eq!(raster.get(11, 20), 2);
+        assert_eq!(raster.get(10, 21), 3);
+
+        raster.as_mut_slice()[3] = 5;
+        assert_eq!(raster.as_slice(), &[1, 2, 3, 5]);
+        assert_eq!(raster.into_vec(), vec![1, 2, 3, 5]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn raster_from_vec_wrong_element_count() {
+        Raster::from_vec(Rectangle::new(0, 0, 2, 2), vec![1u8, 2, 3]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn raster_serde() {
+        let mut raster = Raster::<u16>::new(Rectangle::new(10, 20, 14, 23));
+        raster.set(12, 21, 1234);
+
+        let data = bincode::serialize(&raster).unwrap();
+        assert_eq!(data.len(), 4 * 4 + 8 + 4 * 3 * 2, "elements are not stored compactly");
+
+        let result: Raster<u16> = bincode::deserialize(&data).unwrap();
+        assert_eq!(*result.rectangle(), *raster.rectangle());
+        assert_eq!(result.elements, raster.elements);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn raster_serde_wrong_element_count() {
+        let data = bincode::serialize(&(Rectangle::new(0, 0, 2, 2), vec![1u8, 2, 3])).unwrap();
+        assert!(bincode::deserialize::<Raster<u8>>(&data).is_err());
     }
 }
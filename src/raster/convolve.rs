@@ -0,0 +1,272 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::{Add, Mul};
+
+use crate::filter::Filter;
+use crate::raster::{BorderMode, Raster};
+use crate::rectangle::Coordinate;
+
+/// Two-dimensional convolution kernel.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Kernel {
+    width: u32,
+    height: u32,
+    origin_x: u32,
+    origin_y: u32,
+    weights: Vec<f32>,
+}
+
+/// Separable convolution kernel, which consists of a horizontal and a vertical one-dimensional kernel.
+///
+/// Convolving with a separable kernel takes time proportional to the width plus the height of the kernel, instead of the width times the height.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SeparableKernel {
+    horizontal: Vec<f32>,
+    vertical: Vec<f32>,
+}
+
+// ===== Kernel ================================================================================================================================================
+
+impl Kernel {
+    /// Creates a kernel with the given weights in row-major order and its origin in the center.
+    pub fn new(width: u32, height: u32, weights: Vec<f32>) -> Kernel {
+        assert_eq!(weights.len(), width as usize * height as usize, "number of weights must be equal to width * height");
+        Kernel { width, height, origin_x: width.saturating_sub(1) / 2, origin_y: height.saturating_sub(1) / 2, weights }
+    }
+
+    /// Creates a kernel by evaluating a filter at integer offsets within its radius. The weights are normalized so that they add up to 1.
+    pub fn from_filter<F: Filter>(filter: &F) -> Kernel {
+        let (radius_x, radius_y) = filter.radius();
+        let (rx, ry) = (radius_x.floor() as i32, radius_y.floor() as i32);
+
+        let mut weights = Vec::with_capacity(((2 * rx + 1) * (2 * ry + 1)) as usize);
+        for y in -ry..=ry {
+            for x in -rx..=rx {
+                weights.push(filter.evaluate(x as f32, y as f32));
+            }
+        }
+
+        Kernel::new((2 * rx + 1) as u32, (2 * ry + 1) as u32, normalize(weights))
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    #[inline]
+    pub fn weight(&self, x: u32, y: u32) -> f32 {
+        self.weights[(y * self.width + x) as usize]
+    }
+}
+
+// ===== SeparableKernel =======================================================================================================================================
+
+impl SeparableKernel {
+    /// Creates a kernel from horizontal and vertical weights, each with its origin in the center.
+    #[inline]
+    pub fn new(horizontal: Vec<f32>, vertical: Vec<f32>) -> SeparableKernel {
+        SeparableKernel { horizontal, vertical }
+    }
+
+    /// Creates a kernel by evaluating a filter along the x and y axes at integer offsets within its radius. The weights in each direction are normalized so
+    /// that they add up to 1.
+    ///
+    /// This is exact for filters that are separable, which includes all filters in this crate.
+    pub fn from_filter<F: Filter>(filter: &F) -> SeparableKernel {
+        let (radius_x, radius_y) = filter.radius();
+        let (rx, ry) = (radius_x.floor() as i32, radius_y.floor() as i32);
+
+        let horizontal = (-rx..=rx).map(|x| filter.evaluate(x as f32, 0.0)).collect();
+        let vertical = (-ry..=ry).map(|y| filter.evaluate(0.0, y as f32)).collect();
+
+        SeparableKernel::new(normalize(horizontal), normalize(vertical))
+    }
+
+    #[inline]
+    pub fn horizontal(&self) -> &[f32] {
+        &self.horizontal
+    }
+
+    #[inline]
+    pub fn vertical(&self) -> &[f32] {
+        &self.vertical
+    }
+}
+
+fn normalize(mut weights: Vec<f32>) -> Vec<f32> {
+    let sum: f32 = weights.iter().sum();
+    if sum != 0.0 {
+        for weight in &mut weights {
+            *weight /= sum;
+        }
+    }
+    weights
+}
+
+// ===== Raster ================================================================================================================================================
+
+impl<T: Copy + Default + Add<Output=T> + Mul<f32, Output=T> + Send + Sync, C: Coordinate> Raster<T, C> {
+    /// Convolves this raster with a kernel. The work is distributed over multiple threads.
+    ///
+    /// Element (x, y) of the result is the sum of the kernel weights at offset (dx, dy) from the kernel origin times the element at (x - dx, y - dy), so
+    /// that convolving an impulse reproduces the kernel. The border mode determines how elements outside this raster are handled.
+    pub fn convolve(&self, kernel: &Kernel, border_mode: BorderMode) -> Raster<T, C> {
+        Raster::from_rows_parallel(self.rectangle.clone(), |y, row| {
+            for (i, element) in row.iter_mut().enumerate() {
+                let (x, y) = (self.rectangle.left.to_i64() + i as i64, y.to_i64());
+
+                let mut sum = T::default();
+                for ky in 0..kernel.height {
+                    for kx in 0..kernel.width {
+                        let weight = kernel.weight(kx, ky);
+                        if weight != 0.0 {
+                            let sx = x - (kx as i64 - kernel.origin_x as i64);
+                            let sy = y - (ky as i64 - kernel.origin_y as i64);
+                            sum = sum + self.get_bordered(sx, sy, border_mode) * weight;
+                        }
+                    }
+                }
+                *element = sum;
+            }
+        })
+    }
+
+    /// Convolves this raster with a separable kernel, first horizontally and then vertically. The work is distributed over multiple threads.
+    pub fn convolve_separable(&self, kernel: &SeparableKernel, border_mode: BorderMode) -> Raster<T, C> {
+        let horizontal = self.convolve_1d(&kernel.horizontal, 1, 0, border_mode);
+        horizontal.convolve_1d(&kernel.vertical, 0, 1, border_mode)
+    }
+
+    fn convolve_1d(&self, weights: &[f32], step_x: i64, step_y: i64, border_mode: BorderMode) -> Raster<T, C> {
+        let origin = (weights.len().saturating_sub(1) / 2) as i64;
+
+        Raster::from_rows_parallel(self.rectangle.clone(), |y, row| {
+            for (i, element) in row.iter_mut().enumerate() {
+                let (x, y) = (self.rectangle.left.to_i64() + i as i64, y.to_i64());
+
+                let mut sum = T::default();
+                for (k, &weight) in weights.iter().enumerate() {
+                    let offset = k as i64 - origin;
+                    sum = sum + self.get_bordered(x - offset * step_x, y - offset * step_y, border_mode) * weight;
+                }
+                *element = sum;
+            }
+        })
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use crate::filter::{BoxFilter, GaussianFilter, MitchellFilter};
+    use crate::rectangle::Rectangle;
+
+    use super::*;
+
+    fn impulse() -> Raster<f32> {
+        let mut raster = Raster::new(Rectangle::new(10, 20, 19, 27));
+        raster.set(14, 23, 1.0);
+        raster
+    }
+
+    #[test]
+    fn kernel_new() {
+        let kernel = Kernel::new(3, 1, vec![1.0, 2.0, 3.0]);
+        assert_eq!((kernel.width(), kernel.height()), (3, 1));
+        assert_eq!((kernel.origin_x, kernel.origin_y), (1, 0));
+        assert_eq!(kernel.weight(2, 0), 3.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn kernel_new_wrong_weight_count() {
+        Kernel::new(3, 3, vec![1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn kernel_from_filter() {
+        let kernel = Kernel::from_filter(&BoxFilter::new(1.0, 0.5));
+        assert_eq!((kernel.width(), kernel.height()), (3, 1));
+        assert_eq!(kernel.weights, vec![1.0 / 3.0; 3]);
+    }
+
+    #[test]
+    fn separable_kernel_from_filter() {
+        let kernel = SeparableKernel::from_filter(&GaussianFilter::new(2.0, 1.0, 1.0));
+        assert_eq!(kernel.horizontal().len(), 5);
+        assert_eq!(kernel.vertical().len(), 3);
+        assert!((kernel.horizontal().iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        assert!((kernel.vertical().iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        assert_eq!(kernel.horizontal()[0], kernel.horizontal()[4]);
+    }
+
+    #[test]
+    fn raster_convolve_impulse() {
+        // Convolving an impulse reproduces the kernel
+        let kernel = Kernel::new(3, 3, (1..=9).map(|v| v as f32).collect());
+        let result = impulse().convolve(&kernel, BorderMode::Default);
+
+        assert_eq!(result.get(13, 22), 1.0);
+        assert_eq!(result.get(14, 22), 2.0);
+        assert_eq!(result.get(14, 23), 5.0);
+        assert_eq!(result.get(15, 24), 9.0);
+        assert_eq!(result.get(12, 23), 0.0);
+    }
+
+    #[test]
+    fn raster_convolve_separable_impulse() {
+        let kernel = SeparableKernel::new(vec![1.0, 2.0, 3.0], vec![1.0, 10.0, 100.0]);
+        let result = impulse().convolve_separable(&kernel, BorderMode::Default);
+
+        assert_eq!(result.get(13, 22), 1.0);
+        assert_eq!(result.get(15, 22), 3.0);
+        assert_eq!(result.get(15, 24), 300.0);
+        assert_eq!(result.get(14, 23), 20.0);
+    }
+
+    #[test]
+    fn raster_convolve_separable_matches_convolve() {
+        let filter = MitchellFilter::with_defaults();
+        let mut raster = Raster::<f32>::new(Rectangle::new(0, 0, 17, 11));
+        for (x, y) in raster.rectangle.clone().index_iter() {
+            raster.set(x, y, ((x * 7 + y * 13) % 5) as f32);
+        }
+
+        let separable = raster.convolve_separable(&SeparableKernel::from_filter(&filter), BorderMode::Mirror);
+        let full = raster.convolve(&Kernel::from_filter(&filter), BorderMode::Mirror);
+        for (x, y) in raster.rectangle.index_iter() {
+            assert!((separable.get(x, y) - full.get(x, y)).abs() < 1e-4, "results differ at ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn raster_convolve_border_mode() {
+        let mut raster = Raster::<f32>::new(Rectangle::new(0, 0, 4, 4));
+        for (x, y) in raster.rectangle.clone().index_iter() {
+            raster.set(x, y, 2.0);
+        }
+
+        let kernel = SeparableKernel::from_filter(&BoxFilter::new(1.0, 1.0));
+        assert_eq!(raster.convolve_separable(&kernel, BorderMode::Clamp).get(0, 0), 2.0);
+        assert!((raster.convolve_separable(&kernel, BorderMode::Default).get(0, 0) - 2.0 * 4.0 / 9.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,264 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::slice::{Chunks, ChunksMut, Iter, IterMut};
+
+use crate::raster::Raster;
+use crate::rectangle::Coordinate;
+
+/// Iterator over the coordinates and elements of a raster, in row-major order.
+pub struct RasterIter<'a, T, C: Coordinate = u32> {
+    elements: Iter<'a, T>,
+    left: C,
+    right: C,
+    x: i64,
+    y: i64,
+}
+
+/// Iterator over the coordinates and mutable elements of a raster, in row-major order.
+pub struct RasterIterMut<'a, T, C: Coordinate = u32> {
+    elements: IterMut<'a, T>,
+    left: C,
+    right: C,
+    x: i64,
+    y: i64,
+}
+
+/// Iterator over chunks of consecutive rows of a raster. Each item is the y coordinate of the first row and the elements of the rows in the chunk.
+pub struct RowChunks<'a, T, C: Coordinate = u32> {
+    chunks: Chunks<'a, T>,
+    y: C,
+    rows_per_chunk: u32,
+}
+
+/// Iterator over mutable chunks of consecutive rows of a raster. Each item is the y coordinate of the first row and the elements of the rows in the chunk.
+///
+/// The chunks are disjoint, so they can be processed on different threads.
+pub struct RowChunksMut<'a, T, C: Coordinate = u32> {
+    chunks: ChunksMut<'a, T>,
+    y: C,
+    rows_per_chunk: u32,
+}
+
+// ===== Raster ================================================================================================================================================
+
+impl<T: Copy + Default, C: Coordinate> Raster<T, C> {
+    /// Returns an iterator over the coordinates and elements of this raster, in row-major order.
+    #[inline]
+    pub fn iter(&self) -> RasterIter<'_, T, C> {
+        let (left, right, top) = (self.rectangle.left, self.rectangle.right, self.rectangle.top);
+        RasterIter { elements: self.elements.iter(), left, right, x: left.to_i64(), y: top.to_i64() }
+    }
+
+    /// Returns an iterator over the coordinates and mutable elements of this raster, in row-major order.
+    #[inline]
+    pub fn iter_mut(&mut self) -> RasterIterMut<'_, T, C> {
+        let (left, right, top) = (self.rectangle.left, self.rectangle.right, self.rectangle.top);
+        RasterIterMut { elements: self.elements.iter_mut(), left, right, x: left.to_i64(), y: top.to_i64() }
+    }
+
+    /// Returns the elements of the row with the given y coordinate.
+    #[inline]
+    pub fn row(&self, y: C) -> &[T] {
+        let start = self.rectangle.linear_index(self.rectangle.left, y);
+        &self.elements[start..start + self.rectangle.width() as usize]
+    }
+
+    /// Returns the mutable elements of the row with the given y coordinate.
+    #[inline]
+    pub fn row_mut(&mut self, y: C) -> &mut [T] {
+        let start = self.rectangle.linear_index(self.rectangle.left, y);
+        let width = self.rectangle.width() as usize;
+        &mut self.elements[start..start + width]
+    }
+
+    /// Returns an iterator over the rows of this raster.
+    #[inline]
+    pub fn rows(&self) -> RowChunks<'_, T, C> {
+        self.row_chunks(1)
+    }
+
+    /// Returns an iterator over the mutable rows of this raster.
+    #[inline]
+    pub fn rows_mut(&mut self) -> RowChunksMut<'_, T, C> {
+        self.row_chunks_mut(1)
+    }
+
+    /// Returns an iterator over chunks of the given number of consecutive rows; the last chunk may have fewer rows.
+    pub fn row_chunks(&self, rows_per_chunk: u32) -> RowChunks<'_, T, C> {
+        assert!(rows_per_chunk > 0, "rows per chunk must be greater than zero");
+        let chunk_size = rows_per_chunk as usize * self.rectangle.width().max(1) as usize;
+        RowChunks { chunks: self.elements.chunks(chunk_size), y: self.rectangle.top, rows_per_chunk }
+    }
+
+    /// Returns an iterator over mutable chunks of the given number of consecutive rows; the last chunk may have fewer rows.
+    pub fn row_chunks_mut(&mut self, rows_per_chunk: u32) -> RowChunksMut<'_, T, C> {
+        assert!(rows_per_chunk > 0, "rows per chunk must be greater than zero");
+        let chunk_size = rows_per_chunk as usize * self.rectangle.width().max(1) as usize;
+        RowChunksMut { chunks: self.elements.chunks_mut(chunk_size), y: self.rectangle.top, rows_per_chunk }
+    }
+}
+
+// ===== RasterIter ============================================================================================================================================
+
+impl<'a, T, C: Coordinate> Iterator for RasterIter<'a, T, C> {
+    type Item = (C, C, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let element = self.elements.next()?;
+        let (x, y) = (C::from_i64(self.x), C::from_i64(self.y));
+
+        self.x += 1;
+        if self.x >= self.right.to_i64() {
+            self.x = self.left.to_i64();
+            self.y += 1;
+        }
+
+        Some((x, y, element))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.elements.size_hint()
+    }
+}
+
+impl<T, C: Coordinate> ExactSizeIterator for RasterIter<'_, T, C> {}
+
+// ===== RasterIterMut =========================================================================================================================================
+
+impl<'a, T, C: Coordinate> Iterator for RasterIterMut<'a, T, C> {
+    type Item = (C, C, &'a mut T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let element = self.elements.next()?;
+        let (x, y) = (C::from_i64(self.x), C::from_i64(self.y));
+
+        self.x += 1;
+        if self.x >= self.right.to_i64() {
+            self.x = self.left.to_i64();
+            self.y += 1;
+        }
+
+        Some((x, y, element))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.elements.size_hint()
+    }
+}
+
+impl<T, C: Coordinate> ExactSizeIterator for RasterIterMut<'_, T, C> {}
+
+// ===== RowChunks =============================================================================================================================================
+
+impl<'a, T, C: Coordinate> Iterator for RowChunks<'a, T, C> {
+    type Item = (C, &'a [T]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let chunk = self.chunks.next()?;
+        let y = self.y;
+        self.y = self.y.offset(self.rows_per_chunk as i64);
+        Some((y, chunk))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.chunks.size_hint()
+    }
+}
+
+impl<T, C: Coordinate> ExactSizeIterator for RowChunks<'_, T, C> {}
+
+// ===== RowChunksMut ==========================================================================================================================================
+
+impl<'a, T, C: Coordinate> Iterator for RowChunksMut<'a, T, C> {
+    type Item = (C, &'a mut [T]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let chunk = self.chunks.next()?;
+        let y = self.y;
+        self.y = self.y.offset(self.rows_per_chunk as i64);
+        Some((y, chunk))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.chunks.size_hint()
+    }
+}
+
+impl<T, C: Coordinate> ExactSizeIterator for RowChunksMut<'_, T, C> {}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use crate::rectangle::Rectangle;
+
+    use super::*;
+
+    fn raster() -> Raster<u32> {
+        Raster::from_vec(Rectangle::new(10, 20, 13, 22), vec![1, 2, 3, 4, 5, 6])
+    }
+
+    #[test]
+    fn raster_iter() {
+        let raster = raster();
+        let items: Vec<(u32, u32, u32)> = raster.iter().map(|(x, y, &element)| (x, y, element)).collect();
+        assert_eq!(items, vec![(10, 20, 1), (11, 20, 2), (12, 20, 3), (10, 21, 4), (11, 21, 5), (12, 21, 6)]);
+        assert_eq!(raster.iter().len(), 6);
+    }
+
+    #[test]
+    fn raster_iter_mut() {
+        let mut raster = raster();
+        for (x, y, element) in raster.iter_mut() {
+            *element = x * 100 + y;
+        }
+        assert_eq!(raster.get(12, 21), 1221);
+        assert_eq!(raster.get(10, 20), 1020);
+    }
+
+    #[test]
+    fn raster_row() {
+        let mut raster = raster();
+        assert_eq!(raster.row(21), &[4, 5, 6]);
+
+        raster.row_mut(20)[1] = 7;
+        assert_eq!(raster.get(11, 20), 7);
+    }
+
+    #[test]
+    fn raster_rows() {
+        let raster = raster();
+        let rows: Vec<(u32, &[u32])> = raster.rows().collect();
+        assert_eq!(rows, vec![(20, &[1, 2, 3][..]), (21, &[4, 5, 6][..])]);
+    }
+
+    #[test]
+    fn raster_row_chunks_mut() {
+        let mut raster = Raster::<u32>::new(Rectangle::new(0, 5, 2, 10));
+        let chunks: Vec<(u32, usize)> = raster.row_chunks_mut(2).map(|(y, chunk)| {
+            chunk.fill(y);
+            (y, chunk.len())
+        }).collect();
+
+        assert_eq!(chunks, vec![(5, 4), (7, 4), (9, 2)]);
+        assert_eq!(raster.get(1, 8), 7);
+        assert_eq!(raster.get(0, 9), 9);
+        assert_eq!(raster.row_chunks(2).len(), 3);
+    }
+}
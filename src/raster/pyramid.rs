@@ -0,0 +1,152 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::{Add, Div, Mul};
+
+use crate::filter::Filter;
+use crate::raster::Raster;
+use crate::rectangle::{Coordinate, Rectangle};
+
+/// Image pyramid (mipmap) of a raster: a sequence of levels in which each level has half the width and height of the previous level, rounded up, down to a
+/// level of 1x1 elements. Level 0 is the original raster. The rectangles of all levels have the same top left corner.
+#[derive(Clone)]
+pub struct Pyramid<T: Copy + Default, C: Coordinate = u32> {
+    levels: Vec<Raster<T, C>>,
+}
+
+// ===== Pyramid ===============================================================================================================================================
+
+impl<T: Copy + Default + Add<Output=T> + Mul<f32, Output=T> + Div<f32, Output=T>, C: Coordinate> Pyramid<T, C> {
+    /// Builds a pyramid in which each element of a level is the average of the corresponding 2x2 elements of the previous level.
+    pub fn new(raster: &Raster<T, C>) -> Pyramid<T, C> {
+        Pyramid::build(raster, downsample_box)
+    }
+
+    /// Builds a pyramid in which each level is computed from the previous level by resampling with a filter.
+    pub fn with_filter<F: Filter>(raster: &Raster<T, C>, filter: &F) -> Pyramid<T, C> {
+        Pyramid::build(raster, |level| {
+            let (width, height) = half_size(level.rectangle());
+            level.resample(width, height, filter)
+        })
+    }
+
+    fn build<D: Fn(&Raster<T, C>) -> Raster<T, C>>(raster: &Raster<T, C>, downsample_fn: D) -> Pyramid<T, C> {
+        let mut levels = vec![raster.clone()];
+        loop {
+            let last = levels.last().unwrap();
+            let r = last.rectangle();
+            if r.width() <= 1 && r.height() <= 1 {
+                break;
+            }
+            let next = downsample_fn(last);
+            levels.push(next);
+        }
+
+        Pyramid { levels }
+    }
+}
+
+impl<T: Copy + Default, C: Coordinate> Pyramid<T, C> {
+    /// Returns the number of levels.
+    #[inline]
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Returns the level with the given index; level 0 is the original raster.
+    #[inline]
+    pub fn level(&self, index: usize) -> &Raster<T, C> {
+        &self.levels[index]
+    }
+
+    /// Returns all levels, from the original raster to the level of 1x1 elements.
+    #[inline]
+    pub fn levels(&self) -> &[Raster<T, C>] {
+        &self.levels
+    }
+}
+
+fn half_size<C: Coordinate>(rectangle: &Rectangle<C>) -> (u32, u32) {
+    (rectangle.width().div_ceil(2).max(1), rectangle.height().div_ceil(2).max(1))
+}
+
+/// Averages blocks of 2x2 elements; at the right and bottom edges of rasters with an odd width or height, blocks with fewer elements are averaged.
+fn downsample_box<T: Copy + Default + Add<Output=T> + Mul<f32, Output=T> + Div<f32, Output=T>, C: Coordinate>(raster: &Raster<T, C>) -> Raster<T, C> {
+    let r = raster.rectangle();
+    let (width, height) = half_size(r);
+
+    let mut result = Raster::new(Rectangle::new(r.left, r.top, r.left.offset(width as i64), r.top.offset(height as i64)));
+    for (x, y, element) in result.iter_mut() {
+        let (sx, sy) = (r.left.offset(2 * (x.to_i64() - r.left.to_i64())), r.top.offset(2 * (y.to_i64() - r.top.to_i64())));
+
+        let mut sum = T::default();
+        let mut count = 0;
+        for (px, py) in Rectangle::new(sx, sy, sx.offset(2).min(r.right), sy.offset(2).min(r.bottom)).index_iter() {
+            sum = sum + raster.get(px, py);
+            count += 1;
+        }
+        if count > 0 {
+            *element = sum / count as f32;
+        }
+    }
+    result
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use crate::filter::TriangleFilter;
+
+    use super::*;
+
+    fn ramp(rectangle: Rectangle) -> Raster<f32> {
+        let mut raster = Raster::new(rectangle);
+        for (x, y, element) in raster.iter_mut() {
+            *element = (x + y) as f32;
+        }
+        raster
+    }
+
+    #[test]
+    fn pyramid_levels() {
+        let pyramid = Pyramid::new(&ramp(Rectangle::new(10, 20, 15, 28)));
+        let sizes: Vec<(u32, u32)> = pyramid.levels().iter().map(|level| (level.rectangle().width(), level.rectangle().height())).collect();
+        assert_eq!(sizes, vec![(5, 8), (3, 4), (2, 2), (1, 1)]);
+        assert_eq!(pyramid.level_count(), 4);
+        assert!(pyramid.levels().iter().all(|level| (level.rectangle().left, level.rectangle().top) == (10, 20)));
+    }
+
+    #[test]
+    fn pyramid_box_average() {
+        let pyramid = Pyramid::new(&ramp(Rectangle::new(0, 0, 3, 2)));
+        let level = pyramid.level(1);
+        assert_eq!(level.get(0, 0), 1.0);
+        assert_eq!(level.get(1, 0), 2.5);
+    }
+
+    #[test]
+    fn pyramid_with_filter() {
+        let raster = Raster::<f32>::new(Rectangle::new(0, 0, 16, 9)).map(|_| 0.5);
+        let pyramid = Pyramid::with_filter(&raster, &TriangleFilter::new(1.0, 1.0));
+        assert_eq!(pyramid.level_count(), 5);
+        assert!(pyramid.levels().iter().all(|level| level.as_slice().iter().all(|&value| (value - 0.5).abs() < 1e-6)));
+    }
+
+    #[test]
+    fn pyramid_single_element() {
+        let pyramid = Pyramid::new(&ramp(Rectangle::new(0, 0, 1, 1)));
+        assert_eq!(pyramid.level_count(), 1);
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::{Add, Div, Mul};
+
+use crate::filter::Filter;
+use crate::raster::{BorderMode, Raster};
+use crate::rectangle::{Coordinate, Rectangle};
+
+// ===== Raster ================================================================================================================================================
+
+impl<T: Copy + Default + Add<Output=T> + Mul<f32, Output=T> + Div<f32, Output=T>, C: Coordinate> Raster<T, C> {
+    /// Resamples this raster to a raster of the given size, using a filter as the resampling kernel. The top left corner of the rectangle stays at the
+    /// same position.
+    ///
+    /// When reducing the size, the filter is scaled to cover the source pixels that map onto a destination pixel, to avoid aliasing. Elements outside this
+    /// raster are clamped to the nearest edge.
+    pub fn resample<F: Filter>(&self, width: u32, height: u32, filter: &F) -> Raster<T, C> {
+        let (left, top) = (self.rectangle.left, self.rectangle.top);
+        let rectangle = Rectangle::new(left, top, left.offset(width as i64), top.offset(height as i64));
+        if self.rectangle.is_empty() {
+            return Raster::new(rectangle);
+        }
+
+        // Number of source pixels per destination pixel, and the scale of the filter
+        let scale_x = self.rectangle.width() as f32 / width as f32;
+        let scale_y = self.rectangle.height() as f32 / height as f32;
+        let filter_scale_x = f32::max(scale_x, 1.0);
+        let filter_scale_y = f32::max(scale_y, 1.0);
+
+        let (radius_x, radius_y) = filter.radius();
+        let (radius_x, radius_y) = (radius_x * filter_scale_x, radius_y * filter_scale_y);
+
+        let mut elements = Vec::with_capacity(rectangle.size());
+        for (x, y) in rectangle.index_iter() {
+            // Center of the destination pixel in source coordinates, relative to the top left corner
+            let center_x = ((x.to_i64() - left.to_i64()) as f32 + 0.5) * scale_x;
+            let center_y = ((y.to_i64() - top.to_i64()) as f32 + 0.5) * scale_y;
+
+            let (min_x, max_x) = ((center_x - radius_x - 0.5).floor() as i64, (center_x + radius_x - 0.5).ceil() as i64);
+            let (min_y, max_y) = ((center_y - radius_y - 0.5).floor() as i64, (center_y + radius_y - 0.5).ceil() as i64);
+
+            let mut sum = T::default();
+            let mut weight_sum = 0.0;
+            for sy in min_y..=max_y {
+                for sx in min_x..=max_x {
+                    let weight = filter.evaluate((sx as f32 + 0.5 - center_x) / filter_scale_x, (sy as f32 + 0.5 - center_y) / filter_scale_y);
+                    if weight != 0.0 {
+                        sum = sum + self.get_bordered(left.to_i64() + sx, top.to_i64() + sy, BorderMode::Clamp) * weight;
+                        weight_sum += weight;
+                    }
+                }
+            }
+
+            elements.push(if weight_sum != 0.0 {
+                sum / weight_sum
+            } else {
+                // The filter is too narrow to reach any source pixel; use the nearest one
+                self.get_bordered(left.to_i64() + center_x as i64, top.to_i64() + center_y as i64, BorderMode::Clamp)
+            });
+        }
+
+        Raster { rectangle, elements }
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use crate::filter::{BoxFilter, MitchellFilter, TriangleFilter};
+
+    use super::*;
+
+    fn ramp(width: u32, height: u32) -> Raster<f32> {
+        let mut raster = Raster::new(Rectangle::new(5, 7, 5 + width, 7 + height));
+        for (x, y) in raster.rectangle.index_iter() {
+            raster.set(x, y, (x - 5) as f32);
+        }
+        raster
+    }
+
+    #[test]
+    fn raster_resample_same_size_box() {
+        let raster = ramp(8, 4);
+        let result = raster.resample(8, 4, &BoxFilter::with_defaults());
+        assert_eq!(result.rectangle, raster.rectangle);
+        assert_eq!(result.elements, raster.elements);
+    }
+
+    #[test]
+    fn raster_resample_downsample_box() {
+        let result = ramp(8, 4).resample(4, 2, &BoxFilter::with_defaults());
+        assert_eq!(result.rectangle, Rectangle::new(5, 7, 9, 9));
+
+        // Each destination pixel is the average of two source pixels
+        assert_eq!(result.get(5, 7), 0.5);
+        assert_eq!(result.get(6, 7), 2.5);
+        assert_eq!(result.get(8, 8), 6.5);
+    }
+
+    #[test]
+    fn raster_resample_upsample_triangle() {
+        let result = ramp(4, 4).resample(8, 8, &TriangleFilter::new(1.0, 1.0));
+        assert_eq!(result.rectangle, Rectangle::new(5, 7, 13, 15));
+
+        // Linear interpolation of a ramp, away from the edges, is a ramp
+        for x in 6..12 {
+            let expected = ((x - 5) as f32 + 0.5) * 0.5 - 0.5;
+            assert!((result.get(x, 10) - expected).abs() < 1e-5, "wrong value at {}: {} != {}", x, result.get(x, 10), expected);
+        }
+    }
+
+    #[test]
+    fn raster_resample_constant() {
+        let mut raster = Raster::<f32>::new(Rectangle::new(0, 0, 13, 9));
+        for (x, y) in raster.rectangle.clone().index_iter() {
+            raster.set(x, y, 3.0);
+        }
+
+        for (width, height) in [(5, 4), (13, 9), (40, 21)] {
+            let result = raster.resample(width, height, &MitchellFilter::with_defaults());
+            for value in &result.elements {
+                assert!((value - 3.0).abs() < 1e-4, "constant raster is not preserved: {}", value);
+            }
+        }
+    }
+}
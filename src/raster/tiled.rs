@@ -0,0 +1,455 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Result, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::io::Pixel;
+use crate::raster::Raster;
+use crate::rectangle::{Coordinate, Rectangle};
+
+/// Configuration of the storage of a `TiledRaster`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TileStorage {
+    tile_size: u32,
+    max_resident_tiles: usize,
+    scratch_dir: Option<PathBuf>,
+}
+
+/// Raster that stores its elements in square tiles, for images that are too large to keep in memory in one piece.
+///
+/// With a scratch directory, at most a fixed number of tiles is kept in memory; the least recently used tiles are written to a scratch file when more tiles
+/// are needed. The scratch file is deleted when the raster is dropped. Tiles that have never been modified are not stored at all.
+pub struct TiledRaster<T: Pixel, C: Coordinate = u32> {
+    rectangle: Rectangle<C>,
+    storage: TileStorage,
+    tile_count_x: u32,
+    tile_count_y: u32,
+    tiles: Vec<Tile<T>>,
+    resident_count: usize,
+    clock: u64,
+    scratch: Option<ScratchFile>,
+}
+
+struct Tile<T> {
+    elements: Option<Vec<T>>,
+    offset: Option<u64>,
+    dirty: bool,
+    last_used: u64,
+}
+
+struct ScratchFile {
+    file: File,
+    path: PathBuf,
+    length: u64,
+}
+
+// ===== TileStorage ===========================================================================================================================================
+
+impl TileStorage {
+    /// Storage that keeps all tiles in memory.
+    pub fn in_memory(tile_size: u32) -> TileStorage {
+        assert!(tile_size > 0, "tile size must be greater than zero");
+        TileStorage { tile_size, max_resident_tiles: usize::MAX, scratch_dir: None }
+    }
+
+    /// Storage that keeps at most the given number of tiles in memory and writes other tiles to a scratch file in the given directory.
+    ///
+    /// To avoid reading tiles repeatedly when processing a raster row by row, the maximum should be at least the number of tiles in a row of tiles.
+    pub fn with_scratch_dir<P: Into<PathBuf>>(tile_size: u32, max_resident_tiles: usize, scratch_dir: P) -> TileStorage {
+        assert!(tile_size > 0, "tile size must be greater than zero");
+        assert!(max_resident_tiles > 0, "maximum number of resident tiles must be greater than zero");
+        TileStorage { tile_size, max_resident_tiles, scratch_dir: Some(scratch_dir.into()) }
+    }
+
+    #[inline]
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    #[inline]
+    pub fn max_resident_tiles(&self) -> usize {
+        self.max_resident_tiles
+    }
+
+    #[inline]
+    pub fn scratch_dir(&self) -> Option<&Path> {
+        self.scratch_dir.as_deref()
+    }
+}
+
+// ===== TiledRaster ===========================================================================================================================================
+
+impl<T: Pixel, C: Coordinate> TiledRaster<T, C> {
+    /// Creates a tiled raster in which all elements have the default value. No memory is allocated for the tiles until they are accessed.
+    pub fn new(rectangle: Rectangle<C>, storage: &TileStorage) -> TiledRaster<T, C> {
+        let (tile_count_x, tile_count_y) = rectangle.fixed_tile_count(storage.tile_size, storage.tile_size);
+        let tiles = (0..tile_count_x as usize * tile_count_y as usize)
+            .map(|_| Tile { elements: None, offset: None, dirty: false, last_used: 0 })
+            .collect();
+
+        TiledRaster { rectangle, storage: storage.clone(), tile_count_x, tile_count_y, tiles, resident_count: 0, clock: 0, scratch: None }
+    }
+
+    #[inline]
+    pub fn rectangle(&self) -> &Rectangle<C> {
+        &self.rectangle
+    }
+
+    #[inline]
+    pub fn storage(&self) -> &TileStorage {
+        &self.storage
+    }
+
+    /// Returns the number of tiles horizontally and vertically.
+    #[inline]
+    pub fn tile_count(&self) -> (u32, u32) {
+        (self.tile_count_x, self.tile_count_y)
+    }
+
+    /// Returns the rectangle of the tile with the given tile coordinates; tiles at the right and bottom edges may be smaller than the tile size.
+    pub fn tile_rectangle(&self, tile_x: u32, tile_y: u32) -> Rectangle<C> {
+        self.rectangle.fixed_tile(tile_x, tile_y, self.storage.tile_size, self.storage.tile_size)
+    }
+
+    /// Returns the number of tiles that are currently in memory.
+    #[inline]
+    pub fn resident_tile_count(&self) -> usize {
+        self.resident_count
+    }
+
+    pub fn get(&mut self, x: C, y: C) -> Result<T> {
+        let (index, offset) = self.locate(x, y);
+        Ok(self.load_tile(index, false)?[offset])
+    }
+
+    pub fn set(&mut self, x: C, y: C, value: T) -> Result<()> {
+        let (index, offset) = self.locate(x, y);
+        self.load_tile(index, true)?[offset] = value;
+        Ok(())
+    }
+
+    /// Merges the elements of a raster into this raster like `Raster::merge()`, loading the tiles that overlap the raster.
+    pub fn merge<U: Copy + Default, F: FnMut(T, U) -> T>(&mut self, other: &Raster<U, C>, mut merge_fn: F) -> Result<()> {
+        let intersection = match self.rectangle.intersection(other.rectangle()) {
+            Some(intersection) => intersection,
+            None => return Ok(()),
+        };
+
+        for (tile_x, tile_y) in self.tiles_overlapping(&intersection).index_iter() {
+            let tile_rectangle = self.tile_rectangle(tile_x, tile_y);
+            let part = tile_rectangle.intersection(&intersection).unwrap();
+
+            let elements = self.load_tile((tile_y * self.tile_count_x + tile_x) as usize, true)?;
+            for (x, y) in part.index_iter() {
+                let element = &mut elements[tile_rectangle.linear_index(x, y)];
+                *element = merge_fn(*element, other.get(x, y));
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls a function for each row of this raster, from top to bottom, with the y coordinate and the elements of the row.
+    #[inline]
+    pub fn for_each_row<F: FnMut(C, &[T]) -> Result<()>>(&mut self, row_fn: F) -> Result<()> {
+        self.for_each_row_in(self.rectangle.top..self.rectangle.bottom, row_fn)
+    }
+
+    /// Calls a function for each row in the given range of y coordinates, which must be inside this raster, from top to bottom.
+    pub fn for_each_row_in<F: FnMut(C, &[T]) -> Result<()>>(&mut self, rows: Range<C>, mut row_fn: F) -> Result<()> {
+        assert!(rows.start >= self.rectangle.top && rows.end <= self.rectangle.bottom, "rows {:?} are outside the raster {:?}", rows, self.rectangle);
+
+        let mut row = Vec::with_capacity(self.rectangle.width() as usize);
+        for y in rows.start.to_i64()..rows.end.to_i64() {
+            let y = C::from_i64(y);
+            row.clear();
+
+            let tile_y = (y.to_i64() - self.rectangle.top.to_i64()) as u32 / self.storage.tile_size;
+            for tile_x in 0..self.tile_count_x {
+                let tile_rectangle = self.tile_rectangle(tile_x, tile_y);
+                let start = tile_rectangle.linear_index(tile_rectangle.left, y);
+                let elements = self.load_tile((tile_y * self.tile_count_x + tile_x) as usize, false)?;
+                row.extend_from_slice(&elements[start..start + tile_rectangle.width() as usize]);
+            }
+
+            row_fn(y, &row)?;
+        }
+        Ok(())
+    }
+
+    /// Discards the tiles that are completely above the given y coordinate, so that they no longer take up memory. The elements of these tiles have the
+    /// default value afterwards; space they took up in the scratch file is not reused.
+    pub fn discard_rows_above(&mut self, y: C) {
+        let top = self.rectangle.top;
+        let tile_rows = if y >= self.rectangle.bottom { self.tile_count_y } else { (y.max(top).to_i64() - top.to_i64()) as u32 / self.storage.tile_size };
+
+        for tile in &mut self.tiles[..(tile_rows * self.tile_count_x) as usize] {
+            if tile.elements.take().is_some() {
+                self.resident_count -= 1;
+            }
+            tile.offset = None;
+            tile.dirty = false;
+        }
+    }
+
+    /// Maps all elements of this raster into a new tiled raster with the same rectangle and storage configuration, tile by tile.
+    pub fn map<U: Pixel, F: FnMut(T) -> U>(mut self, mut map_fn: F) -> Result<TiledRaster<U, C>> {
+        let mut result = TiledRaster::new(self.rectangle.clone(), &self.storage);
+        for index in 0..self.tiles.len() {
+            let elements = self.load_tile(index, false)?.iter().map(|&element| map_fn(element)).collect();
+            *result.load_tile(index, true)? = elements;
+        }
+        Ok(result)
+    }
+
+    /// Copies all elements of this raster into a raster in memory.
+    pub fn to_raster(&mut self) -> Result<Raster<T, C>> {
+        let mut elements = Vec::with_capacity(self.rectangle.size());
+        self.for_each_row(|_, row| {
+            elements.extend_from_slice(row);
+            Ok(())
+        })?;
+        Ok(Raster::from_vec(self.rectangle.clone(), elements))
+    }
+
+    /// Returns the index of the tile that contains the given position and the index of the element within the tile.
+    fn locate(&self, x: C, y: C) -> (usize, usize) {
+        assert!(self.rectangle.contains(x, y), "position ({}, {}) is outside the raster {:?}", x, y, self.rectangle);
+
+        let tile_size = self.storage.tile_size;
+        let (tile_x, tile_y) = ((x.to_i64() - self.rectangle.left.to_i64()) as u32 / tile_size, (y.to_i64() - self.rectangle.top.to_i64()) as u32 / tile_size);
+        let tile_rectangle = self.tile_rectangle(tile_x, tile_y);
+        ((tile_y * self.tile_count_x + tile_x) as usize, tile_rectangle.linear_index(x, y))
+    }
+
+    /// Returns the rectangle of tile coordinates of the tiles that overlap the given rectangle, which must be inside this raster.
+    fn tiles_overlapping(&self, rectangle: &Rectangle<C>) -> Rectangle {
+        let tile_size = self.storage.tile_size;
+        let (origin_x, origin_y) = (self.rectangle.left.to_i64(), self.rectangle.top.to_i64());
+        let (left, top) = ((rectangle.left.to_i64() - origin_x) as u32 / tile_size, (rectangle.top.to_i64() - origin_y) as u32 / tile_size);
+        let (right, bottom) = (((rectangle.right.to_i64() - origin_x) as u32).div_ceil(tile_size), ((rectangle.bottom.to_i64() - origin_y) as u32).div_ceil(tile_size));
+        Rectangle::new(left, top, right, bottom)
+    }
+
+    /// Makes sure the tile with the given index is in memory and returns its elements; other tiles are evicted if necessary.
+    fn load_tile(&mut self, index: usize, modify: bool) -> Result<&mut Vec<T>> {
+        self.clock += 1;
+
+        if self.tiles[index].elements.is_none() {
+            if self.resident_count >= self.storage.max_resident_tiles {
+                self.evict_least_recently_used()?;
+            }
+
+            let size = self.tile_rectangle(index as u32 % self.tile_count_x, index as u32 / self.tile_count_x).size();
+            let elements = match (self.tiles[index].offset, &mut self.scratch) {
+                (Some(offset), Some(scratch)) => scratch.read(offset, size)?,
+                _ => vec![T::default(); size],
+            };
+
+            self.tiles[index].elements = Some(elements);
+            self.resident_count += 1;
+        }
+
+        let tile = &mut self.tiles[index];
+        tile.last_used = self.clock;
+        tile.dirty |= modify;
+        Ok(tile.elements.as_mut().unwrap())
+    }
+
+    fn evict_least_recently_used(&mut self) -> Result<()> {
+        let index = match self.tiles.iter().enumerate().filter(|(_, tile)| tile.elements.is_some()).min_by_key(|(_, tile)| tile.last_used) {
+            Some((index, _)) => index,
+            None => return Ok(()),
+        };
+
+        let tile = &mut self.tiles[index];
+        if tile.dirty {
+            let scratch = match &mut self.scratch {
+                Some(scratch) => scratch,
+                None => self.scratch.insert(ScratchFile::create(self.storage.scratch_dir.as_deref().unwrap_or(Path::new(".")))?),
+            };
+            tile.offset = Some(scratch.write(tile.offset, tile.elements.as_ref().unwrap())?);
+            tile.dirty = false;
+        }
+
+        tile.elements = None;
+        self.resident_count -= 1;
+        Ok(())
+    }
+}
+
+// ===== ScratchFile ===========================================================================================================================================
+
+impl ScratchFile {
+    fn create(dir: &Path) -> Result<ScratchFile> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let name = format!("renderbase-{}-{}.tiles", std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed));
+        let path = dir.join(name);
+        let file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+
+        log::debug!("Created scratch file {}", path.display());
+        Ok(ScratchFile { file, path, length: 0 })
+    }
+
+    /// Writes elements at the given offset, or at the end of the file if there is no offset yet, and returns the offset.
+    fn write<T: Pixel>(&mut self, offset: Option<u64>, elements: &[T]) -> Result<u64> {
+        let mut data = Vec::with_capacity(elements.len() * T::CHANNEL_COUNT * 4);
+        for element in elements {
+            for index in 0..T::CHANNEL_COUNT {
+                data.extend_from_slice(&element.channel(index).to_le_bytes());
+            }
+        }
+
+        let offset = offset.unwrap_or_else(|| {
+            let offset = self.length;
+            self.length += data.len() as u64;
+            offset
+        });
+
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(&data)?;
+        Ok(offset)
+    }
+
+    fn read<T: Pixel>(&mut self, offset: u64, size: usize) -> Result<Vec<T>> {
+        let mut data = vec![0u8; size * T::CHANNEL_COUNT * 4];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut data)?;
+
+        let channels: Vec<f32> = data.chunks_exact(4).map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap())).collect();
+        Ok(channels.chunks_exact(T::CHANNEL_COUNT.max(1)).take(size).map(T::from_channels).collect())
+    }
+}
+
+impl Drop for ScratchFile {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            log::warn!("Could not remove scratch file {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use crate::color::Rgb;
+
+    use super::*;
+
+    fn scratch_dir() -> PathBuf {
+        std::env::temp_dir()
+    }
+
+    fn fill<C: Coordinate>(raster: &mut TiledRaster<f32, C>) {
+        for (x, y) in raster.rectangle().clone().index_iter() {
+            raster.set(x, y, (x.to_i64() * 1000 + y.to_i64()) as f32).unwrap();
+        }
+    }
+
+    #[test]
+    fn tiled_raster_tiles() {
+        let raster = TiledRaster::<f32>::new(Rectangle::new(10, 20, 35, 40), &TileStorage::in_memory(8));
+        assert_eq!(raster.tile_count(), (4, 3));
+        assert_eq!(raster.tile_rectangle(0, 0), Rectangle::new(10, 20, 18, 28));
+        assert_eq!(raster.tile_rectangle(3, 2), Rectangle::new(34, 36, 35, 40));
+        assert_eq!(raster.resident_tile_count(), 0);
+    }
+
+    #[test]
+    fn tiled_raster_in_memory() {
+        let mut raster = TiledRaster::new(Rectangle::new(10, 20, 35, 40), &TileStorage::in_memory(8));
+        fill(&mut raster);
+        assert_eq!(raster.resident_tile_count(), 12);
+        assert_eq!(raster.get(34, 39).unwrap(), 34039.0);
+
+        let result = raster.to_raster().unwrap();
+        assert!(result.iter().all(|(x, y, &value)| value == (x * 1000 + y) as f32));
+    }
+
+    #[test]
+    fn tiled_raster_spills_to_scratch_file() {
+        let mut raster = TiledRaster::new(Rectangle::new(10, 20, 35, 40), &TileStorage::with_scratch_dir(8, 2, scratch_dir()));
+        fill(&mut raster);
+        assert_eq!(raster.resident_tile_count(), 2);
+
+        let path = raster.scratch.as_ref().unwrap().path.clone();
+        assert!(path.exists());
+
+        assert_eq!(raster.get(10, 20).unwrap(), 10020.0);
+        let result = raster.to_raster().unwrap();
+        assert!(result.iter().all(|(x, y, &value)| value == (x * 1000 + y) as f32));
+
+        drop(raster);
+        assert!(!path.exists(), "scratch file should be removed");
+    }
+
+    #[test]
+    fn tiled_raster_merge_and_map() {
+        let storage = TileStorage::with_scratch_dir(4, 3, scratch_dir());
+        let mut raster = TiledRaster::<(Rgb, f32)>::new(Rectangle::new(0, 0, 16, 16), &storage);
+
+        let mut tile = Raster::new(Rectangle::new(3, 3, 13, 9));
+        for (_, _, element) in tile.iter_mut() {
+            *element = (Rgb::gray(2.0), 0.5);
+        }
+        for _ in 0..2 {
+            raster.merge(&tile, |(value, weight), (tile_value, tile_weight)| (value + tile_value, weight + tile_weight)).unwrap();
+        }
+
+        let mut result = raster.map(|(value, weight)| if weight != 0.0 { value / weight } else { Rgb::BLACK }).unwrap();
+        assert_eq!(result.get(3, 3).unwrap(), Rgb::gray(4.0));
+        assert_eq!(result.get(12, 8).unwrap(), Rgb::gray(4.0));
+        assert_eq!(result.get(13, 8).unwrap(), Rgb::BLACK);
+        assert_eq!(result.get(2, 3).unwrap(), Rgb::BLACK);
+    }
+
+    #[test]
+    fn tiled_raster_for_each_row() {
+        let mut raster = TiledRaster::new(Rectangle::new(0, 0, 10, 5), &TileStorage::with_scratch_dir(3, 4, scratch_dir()));
+        fill(&mut raster);
+
+        let mut rows = Vec::new();
+        raster.for_each_row(|y, row| {
+            rows.push((y, row.len(), row[9]));
+            Ok(())
+        }).unwrap();
+        assert_eq!(rows, (0..5).map(|y| (y, 10, (9000 + y) as f32)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn tiled_raster_discard_rows_above() {
+        let mut raster = TiledRaster::new(Rectangle::<i32>::new(0, -2, 10, 8), &TileStorage::with_scratch_dir(3, 4, scratch_dir()));
+        fill(&mut raster);
+
+        let mut rows = Vec::new();
+        raster.for_each_row_in(1..4, |y, row| {
+            rows.push((y, row[9]));
+            Ok(())
+        }).unwrap();
+        assert_eq!(rows, vec![(1, 9001.0), (2, 9002.0), (3, 9003.0)]);
+
+        // Only the first row of tiles (rows -2..1) is completely above row 3
+        raster.discard_rows_above(3);
+        assert_eq!(raster.get(9, 0).unwrap(), 0.0);
+        assert_eq!(raster.get(9, 1).unwrap(), 9001.0);
+
+        raster.discard_rows_above(8);
+        assert_eq!(raster.get(9, 7).unwrap(), 0.0);
+    }
+}
@@ -0,0 +1,210 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::raster::{BorderMode, Raster};
+use crate::rectangle::{Coordinate, Rectangle};
+
+// ===== Raster ================================================================================================================================================
+
+impl<T: Copy + Default, C: Coordinate> Raster<T, C> {
+    /// Returns the part of this raster that is inside the given rectangle.
+    ///
+    /// The rectangle of the result is the intersection of the given rectangle and the rectangle of this raster; if they do not overlap, the result is empty.
+    pub fn crop(&self, rectangle: &Rectangle<C>) -> Raster<T, C> {
+        match self.rectangle.intersection(rectangle) {
+            Some(intersection) => {
+                let mut elements = Vec::with_capacity(intersection.size());
+                for y in intersection.top.to_i64()..intersection.bottom.to_i64() {
+                    let start = self.rectangle.linear_index(intersection.left, C::from_i64(y));
+                    elements.extend_from_slice(&self.elements[start..start + intersection.width() as usize]);
+                }
+
+                Raster { rectangle: intersection, elements }
+            }
+            None => Raster::new(Rectangle::new(rectangle.left, rectangle.top, rectangle.left, rectangle.top)),
+        }
+    }
+
+    /// Returns a raster with the given rectangle, which usually contains the rectangle of this raster. Elements outside the rectangle of this raster are
+    /// filled in according to the border mode.
+    pub fn pad(&self, rectangle: &Rectangle<C>, border_mode: BorderMode) -> Raster<T, C> {
+        let mut elements = Vec::with_capacity(rectangle.size());
+        for (x, y) in rectangle.index_iter() {
+            elements.push(self.get_bordered(x.to_i64(), y.to_i64(), border_mode));
+        }
+
+        Raster { rectangle: rectangle.clone(), elements }
+    }
+
+    /// Returns a copy of this raster that is mirrored left to right.
+    pub fn flip_horizontal(&self) -> Raster<T, C> {
+        let width = self.rectangle.width() as usize;
+
+        let mut elements = self.elements.clone();
+        if width > 0 {
+            for row in elements.chunks_exact_mut(width) {
+                row.reverse();
+            }
+        }
+
+        Raster { rectangle: self.rectangle.clone(), elements }
+    }
+
+    /// Returns a copy of this raster that is mirrored top to bottom.
+    pub fn flip_vertical(&self) -> Raster<T, C> {
+        let width = self.rectangle.width() as usize;
+
+        let mut elements = Vec::with_capacity(self.elements.len());
+        if width > 0 {
+            for row in self.elements.chunks_exact(width).rev() {
+                elements.extend_from_slice(row);
+            }
+        }
+
+        Raster { rectangle: self.rectangle.clone(), elements }
+    }
+
+    /// Returns a copy of this raster with rows and columns swapped. The top left corner of the rectangle stays at the same position.
+    pub fn transpose(&self) -> Raster<T, C> {
+        self.rearrange(|x, y| (y, x))
+    }
+
+    /// Returns a copy of this raster that is rotated 90 degrees clockwise. The top left corner of the rectangle stays at the same position.
+    pub fn rotate_90_clockwise(&self) -> Raster<T, C> {
+        let height = self.rectangle.height();
+        self.rearrange(|x, y| (y, height - 1 - x))
+    }
+
+    /// Returns a copy of this raster that is rotated 90 degrees counterclockwise. The top left corner of the rectangle stays at the same position.
+    pub fn rotate_90_counterclockwise(&self) -> Raster<T, C> {
+        let width = self.rectangle.width();
+        self.rearrange(|x, y| (width - 1 - y, x))
+    }
+
+    /// Returns a copy of this raster that is rotated 180 degrees.
+    pub fn rotate_180(&self) -> Raster<T, C> {
+        let mut elements = self.elements.clone();
+        elements.reverse();
+
+        Raster { rectangle: self.rectangle.clone(), elements }
+    }
+
+    /// Creates a raster with width and height swapped, where `source_fn` maps a position relative to the top left corner of the result to a position
+    /// relative to the top left corner of this raster.
+    fn rearrange<F: Fn(u32, u32) -> (u32, u32)>(&self, source_fn: F) -> Raster<T, C> {
+        let (left, top) = (self.rectangle.left, self.rectangle.top);
+        let rectangle = Rectangle::new(left, top, left.offset(self.rectangle.height() as i64), top.offset(self.rectangle.width() as i64));
+
+        let mut elements = Vec::with_capacity(self.elements.len());
+        for (x, y) in rectangle.index_iter() {
+            let (source_x, source_y) = source_fn((x.to_i64() - left.to_i64()) as u32, (y.to_i64() - top.to_i64()) as u32);
+            elements.push(self.get(left.offset(source_x as i64), top.offset(source_y as i64)));
+        }
+
+        Raster { rectangle, elements }
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // 3 x 2 raster at (10, 20) with values:
+    // 1 2 3
+    // 4 5 6
+    fn test_raster() -> Raster<u8> {
+        let mut raster = Raster::new(Rectangle::new(10, 20, 13, 22));
+        for (v, (x, y)) in raster.rectangle.index_iter().enumerate() {
+            raster.set(x, y, v as u8 + 1);
+        }
+        raster
+    }
+
+    #[test]
+    fn raster_crop() {
+        let raster = test_raster().crop(&Rectangle::new(11, 0, 100, 21));
+        assert_eq!(raster.rectangle, Rectangle::new(11, 20, 13, 21));
+        assert_eq!(raster.elements, vec![2, 3]);
+    }
+
+    #[test]
+    fn raster_crop_outside() {
+        let raster = test_raster().crop(&Rectangle::new(50, 60, 70, 80));
+        assert!(raster.rectangle.is_empty());
+        assert!(raster.elements.is_empty());
+    }
+
+    #[test]
+    fn raster_pad() {
+        let raster = test_raster().pad(&Rectangle::new(9, 20, 14, 23), BorderMode::Clamp);
+        assert_eq!(raster.rectangle, Rectangle::new(9, 20, 14, 23));
+        assert_eq!(raster.elements, vec![1, 1, 2, 3, 3, 4, 4, 5, 6, 6, 4, 4, 5, 6, 6]);
+
+        let raster = test_raster().pad(&Rectangle::new(9, 20, 14, 22), BorderMode::Default);
+        assert_eq!(raster.elements, vec![0, 1, 2, 3, 0, 0, 4, 5, 6, 0]);
+    }
+
+    #[test]
+    fn raster_flip_horizontal() {
+        let raster = test_raster().flip_horizontal();
+        assert_eq!(raster.rectangle, Rectangle::new(10, 20, 13, 22));
+        assert_eq!(raster.elements, vec![3, 2, 1, 6, 5, 4]);
+    }
+
+    #[test]
+    fn raster_flip_vertical() {
+        let raster = test_raster().flip_vertical();
+        assert_eq!(raster.rectangle, Rectangle::new(10, 20, 13, 22));
+        assert_eq!(raster.elements, vec![4, 5, 6, 1, 2, 3]);
+    }
+
+    #[test]
+    fn raster_transpose() {
+        let raster = test_raster().transpose();
+        assert_eq!(raster.rectangle, Rectangle::new(10, 20, 12, 23));
+        assert_eq!(raster.elements, vec![1, 4, 2, 5, 3, 6]);
+    }
+
+    #[test]
+    fn raster_rotate_90_clockwise() {
+        let raster = test_raster().rotate_90_clockwise();
+        assert_eq!(raster.rectangle, Rectangle::new(10, 20, 12, 23));
+        assert_eq!(raster.elements, vec![4, 1, 5, 2, 6, 3]);
+    }
+
+    #[test]
+    fn raster_rotate_90_counterclockwise() {
+        let raster = test_raster().rotate_90_counterclockwise();
+        assert_eq!(raster.rectangle, Rectangle::new(10, 20, 12, 23));
+        assert_eq!(raster.elements, vec![3, 6, 2, 5, 1, 4]);
+    }
+
+    #[test]
+    fn raster_rotate_180() {
+        let raster = test_raster().rotate_180();
+        assert_eq!(raster.elements, vec![6, 5, 4, 3, 2, 1]);
+        assert_eq!(raster.rotate_180().elements, test_raster().elements);
+    }
+
+    #[test]
+    fn raster_rotate_four_times() {
+        let raster = test_raster();
+        let rotated = raster.rotate_90_clockwise().rotate_90_clockwise().rotate_90_clockwise().rotate_90_clockwise();
+        assert_eq!(rotated.rectangle, raster.rectangle);
+        assert_eq!(rotated.elements, raster.elements);
+        assert_eq!(raster.rotate_90_clockwise().rotate_90_counterclockwise().elements, raster.elements);
+    }
+}
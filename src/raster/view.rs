@@ -0,0 +1,328 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::raster::Raster;
+use crate::rectangle::{Coordinate, Rectangle};
+
+/// Read-only view of the part of a raster inside a rectangle.
+#[derive(Clone)]
+pub struct RasterView<'a, T: Copy + Default, C: Coordinate = u32> {
+    raster: &'a Raster<T, C>,
+    rectangle: Rectangle<C>,
+}
+
+/// Mutable view of the part of a raster inside a rectangle.
+///
+/// A mutable view can be split into disjoint mutable views, which can be processed on different threads.
+pub struct RasterViewMut<'a, T: Copy + Default, C: Coordinate = u32> {
+    rectangle: Rectangle<C>,
+    rows: Vec<&'a mut [T]>,
+}
+
+// ===== Raster ================================================================================================================================================
+
+impl<T: Copy + Default, C: Coordinate> Raster<T, C> {
+    /// Returns a view of the part of this raster inside the given rectangle, which must be contained in the rectangle of this raster.
+    pub fn view(&self, rectangle: &Rectangle<C>) -> RasterView<'_, T, C> {
+        check_contains(&self.rectangle, rectangle);
+        RasterView { raster: self, rectangle: rectangle.clone() }
+    }
+
+    /// Returns a mutable view of the part of this raster inside the given rectangle, which must be contained in the rectangle of this raster.
+    pub fn view_mut(&mut self, rectangle: &Rectangle<C>) -> RasterViewMut<'_, T, C> {
+        check_contains(&self.rectangle, rectangle);
+
+        let left = self.rectangle.left.to_i64();
+        let (start, end) = ((rectangle.left.to_i64() - left) as usize, (rectangle.right.to_i64() - left) as usize);
+        let rows = self.rows_mut()
+            .filter(|&(y, _)| y >= rectangle.top && y < rectangle.bottom)
+            .map(|(_, row)| &mut row[start..end])
+            .collect();
+
+        RasterViewMut { rectangle: rectangle.clone(), rows }
+    }
+}
+
+#[track_caller]
+fn check_contains<C: Coordinate>(outer: &Rectangle<C>, inner: &Rectangle<C>) {
+    assert!(inner.left >= outer.left && inner.top >= outer.top && inner.right <= outer.right && inner.bottom <= outer.bottom && inner.left <= inner.right &&
+        inner.top <= inner.bottom, "rectangle {:?} is not contained in {:?}", inner, outer);
+}
+
+// ===== RasterView ============================================================================================================================================
+
+impl<'a, T: Copy + Default, C: Coordinate> RasterView<'a, T, C> {
+    #[inline]
+    pub fn rectangle(&self) -> &Rectangle<C> {
+        &self.rectangle
+    }
+
+    #[inline]
+    pub fn get(&self, x: C, y: C) -> T {
+        debug_assert!(self.rectangle.contains(x, y), "position ({}, {}) is outside the view {:?}", x, y, self.rectangle);
+        self.raster.get(x, y)
+    }
+
+    /// Returns the elements of the row with the given y coordinate.
+    #[inline]
+    pub fn row(&self, y: C) -> &'a [T] {
+        let row = self.raster.row(y);
+        let start = (self.rectangle.left.to_i64() - self.raster.rectangle.left.to_i64()) as usize;
+        &row[start..start + self.rectangle.width() as usize]
+    }
+
+    /// Returns an iterator over the rows of this view; each item is the y coordinate and the elements of a row.
+    pub fn rows(&self) -> impl Iterator<Item=(C, &'a [T])> + 'a {
+        let view = self.clone();
+        (self.rectangle.top.to_i64()..self.rectangle.bottom.to_i64()).map(move |y| (C::from_i64(y), view.row(C::from_i64(y))))
+    }
+
+    /// Returns an iterator over the coordinates and elements of this view, in row-major order.
+    pub fn iter(&self) -> impl Iterator<Item=(C, C, &'a T)> + 'a {
+        let left = self.rectangle.left.to_i64();
+        self.rows().flat_map(move |(y, row)| row.iter().enumerate().map(move |(i, element)| (C::from_i64(left + i as i64), y, element)))
+    }
+
+    /// Returns a view of the part of this view inside the given rectangle, which must be contained in the rectangle of this view.
+    pub fn view(&self, rectangle: &Rectangle<C>) -> RasterView<'a, T, C> {
+        check_contains(&self.rectangle, rectangle);
+        RasterView { raster: self.raster, rectangle: rectangle.clone() }
+    }
+
+    /// Copies the elements of this view into a new raster.
+    pub fn to_raster(&self) -> Raster<T, C> {
+        self.raster.crop(&self.rectangle)
+    }
+}
+
+// ===== RasterViewMut =========================================================================================================================================
+
+impl<'a, T: Copy + Default, C: Coordinate> RasterViewMut<'a, T, C> {
+    #[inline]
+    pub fn rectangle(&self) -> &Rectangle<C> {
+        &self.rectangle
+    }
+
+    #[inline]
+    pub fn get(&self, x: C, y: C) -> T {
+        self.row(y)[self.column(x)]
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, x: C, y: C) -> &mut T {
+        let column = self.column(x);
+        &mut self.row_mut(y)[column]
+    }
+
+    #[inline]
+    pub fn set(&mut self, x: C, y: C, value: T) {
+        *self.get_mut(x, y) = value;
+    }
+
+    /// Returns the elements of the row with the given y coordinate.
+    #[inline]
+    pub fn row(&self, y: C) -> &[T] {
+        self.rows[self.row_index(y)]
+    }
+
+    /// Returns the mutable elements of the row with the given y coordinate.
+    #[inline]
+    pub fn row_mut(&mut self, y: C) -> &mut [T] {
+        let index = self.row_index(y);
+        self.rows[index]
+    }
+
+    /// Returns an iterator over the rows of this view; each item is the y coordinate and the elements of a row.
+    pub fn rows(&self) -> impl Iterator<Item=(C, &[T])> + '_ {
+        let top = self.rectangle.top.to_i64();
+        self.rows.iter().enumerate().map(move |(i, row)| (C::from_i64(top + i as i64), &**row))
+    }
+
+    /// Returns an iterator over the mutable rows of this view; each item is the y coordinate and the elements of a row.
+    pub fn rows_mut(&mut self) -> impl Iterator<Item=(C, &mut [T])> + use<'_, 'a, T, C> {
+        let top = self.rectangle.top.to_i64();
+        self.rows.iter_mut().enumerate().map(move |(i, row)| (C::from_i64(top + i as i64), &mut **row))
+    }
+
+    /// Returns an iterator over the coordinates and elements of this view, in row-major order.
+    pub fn iter(&self) -> impl Iterator<Item=(C, C, &T)> + '_ {
+        let left = self.rectangle.left.to_i64();
+        self.rows().flat_map(move |(y, row)| row.iter().enumerate().map(move |(i, element)| (C::from_i64(left + i as i64), y, element)))
+    }
+
+    /// Returns an iterator over the coordinates and mutable elements of this view, in row-major order.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item=(C, C, &mut T)> + use<'_, 'a, T, C> {
+        let left = self.rectangle.left.to_i64();
+        self.rows_mut().flat_map(move |(y, row)| row.iter_mut().enumerate().map(move |(i, element)| (C::from_i64(left + i as i64), y, element)))
+    }
+
+    /// Splits this view into a top part above the given y coordinate and a bottom part.
+    pub fn split_at_y(mut self, y: C) -> (RasterViewMut<'a, T, C>, RasterViewMut<'a, T, C>) {
+        let r = &self.rectangle;
+        assert!(y >= r.top && y <= r.bottom, "y coordinate {} is outside the range {}..={}", y, r.top, r.bottom);
+
+        let bottom_rows = self.rows.split_off((y.to_i64() - r.top.to_i64()) as usize);
+        let top = RasterViewMut { rectangle: Rectangle::new(r.left, r.top, r.right, y), rows: self.rows };
+        let bottom = RasterViewMut { rectangle: Rectangle::new(r.left, y, r.right, r.bottom), rows: bottom_rows };
+        (top, bottom)
+    }
+
+    /// Splits this view into a left part before the given x coordinate and a right part.
+    pub fn split_at_x(self, x: C) -> (RasterViewMut<'a, T, C>, RasterViewMut<'a, T, C>) {
+        let r = self.rectangle;
+        assert!(x >= r.left && x <= r.right, "x coordinate {} is outside the range {}..={}", x, r.left, r.right);
+
+        let (left_rows, right_rows) = self.rows.into_iter().map(|row| row.split_at_mut((x.to_i64() - r.left.to_i64()) as usize)).unzip();
+        let left = RasterViewMut { rectangle: Rectangle::new(r.left, r.top, x, r.bottom), rows: left_rows };
+        let right = RasterViewMut { rectangle: Rectangle::new(x, r.top, r.right, r.bottom), rows: right_rows };
+        (left, right)
+    }
+
+    /// Splits this view into disjoint views for the tiles returned by `Rectangle::tile_iter()`, in the same order.
+    pub fn split_into_tiles(self, tile_count_x: u32, tile_count_y: u32) -> Vec<RasterViewMut<'a, T, C>> {
+        let tiles: Vec<Rectangle<C>> = self.rectangle.tile_iter(tile_count_x, tile_count_y).collect();
+
+        let mut views = Vec::with_capacity(tiles.len());
+        let mut band_rest = Some(self);
+        let mut row_rest = None;
+        for tile in tiles {
+            // Split off the next band of rows when the previous band has been used up
+            if row_rest.is_none() {
+                let (band, rest) = band_rest.take().unwrap().split_at_y(tile.bottom);
+                band_rest = Some(rest);
+                row_rest = Some(band);
+            }
+
+            let (view, rest) = row_rest.take().unwrap().split_at_x(tile.right);
+            views.push(view);
+            if !rest.rectangle.is_empty() {
+                row_rest = Some(rest);
+            }
+        }
+        views
+    }
+
+    /// Reborrows this view as a view with a shorter lifetime.
+    pub fn reborrow(&mut self) -> RasterViewMut<'_, T, C> {
+        RasterViewMut { rectangle: self.rectangle.clone(), rows: self.rows.iter_mut().map(|row| &mut **row).collect() }
+    }
+
+    /// Copies the elements of this view into a new raster.
+    pub fn to_raster(&self) -> Raster<T, C> {
+        let mut elements = Vec::with_capacity(self.rectangle.size());
+        for row in &self.rows {
+            elements.extend_from_slice(row);
+        }
+        Raster::from_vec(self.rectangle.clone(), elements)
+    }
+
+    #[inline]
+    fn row_index(&self, y: C) -> usize {
+        debug_assert!(y >= self.rectangle.top && y < self.rectangle.bottom, "invalid y index: {} (valid range is {}..{})", y, self.rectangle.top, self.rectangle.bottom);
+        (y.to_i64() - self.rectangle.top.to_i64()) as usize
+    }
+
+    #[inline]
+    fn column(&self, x: C) -> usize {
+        debug_assert!(x >= self.rectangle.left && x < self.rectangle.right, "invalid x index: {} (valid range is {}..{})", x, self.rectangle.left, self.rectangle.right);
+        (x.to_i64() - self.rectangle.left.to_i64()) as usize
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use crossbeam_utils::thread;
+
+    use super::*;
+
+    fn raster() -> Raster<u32> {
+        let mut raster = Raster::new(Rectangle::new(10, 20, 20, 28));
+        for (x, y, element) in raster.iter_mut() {
+            *element = x * 100 + y;
+        }
+        raster
+    }
+
+    #[test]
+    fn raster_view() {
+        let raster = raster();
+        let view = raster.view(&Rectangle::new(12, 21, 15, 23));
+        assert_eq!(view.get(13, 22), 1322);
+        assert_eq!(view.row(21), &[1221, 1321, 1421]);
+        assert_eq!(view.iter().count(), 6);
+        assert_eq!(view.iter().last(), Some((14, 22, &1422)));
+        assert_eq!(view.view(&Rectangle::new(14, 22, 15, 23)).get(14, 22), 1422);
+        assert_eq!(view.to_raster().as_slice(), &[1221, 1321, 1421, 1222, 1322, 1422]);
+    }
+
+    #[test]
+    #[should_panic(expected = "not contained")]
+    fn raster_view_outside() {
+        raster().view(&Rectangle::new(5, 21, 15, 23));
+    }
+
+    #[test]
+    fn raster_view_mut() {
+        let mut raster = raster();
+        let mut view = raster.view_mut(&Rectangle::new(12, 21, 15, 23));
+        assert_eq!(view.get(12, 21), 1221);
+        view.set(13, 22, 7);
+        for (_, _, element) in view.iter_mut().filter(|&(x, _, _)| x == 14) {
+            *element = 8;
+        }
+        assert_eq!(view.to_raster().as_slice(), &[1221, 1321, 8, 1222, 7, 8]);
+
+        assert_eq!(raster.get(13, 22), 7);
+        assert_eq!(raster.get(14, 21), 8);
+        assert_eq!(raster.get(15, 21), 1521);
+    }
+
+    #[test]
+    fn raster_view_mut_split() {
+        let mut raster = raster();
+        let view = raster.view_mut(&Rectangle::new(10, 20, 20, 28));
+        let (top, bottom) = view.split_at_y(23);
+        let (left, right) = bottom.split_at_x(15);
+        assert_eq!(*top.rectangle(), Rectangle::new(10, 20, 20, 23));
+        assert_eq!(*left.rectangle(), Rectangle::new(10, 23, 15, 28));
+        assert_eq!(*right.rectangle(), Rectangle::new(15, 23, 20, 28));
+        assert_eq!(right.get(15, 23), 1523);
+    }
+
+    #[test]
+    fn raster_view_mut_split_into_tiles() {
+        let mut raster = raster();
+        let rectangle = raster.rectangle().clone();
+        let views = raster.view_mut(&rectangle).split_into_tiles(3, 2);
+
+        let tiles: Vec<Rectangle> = rectangle.tile_iter(3, 2).collect();
+        assert_eq!(views.iter().map(|view| view.rectangle().clone()).collect::<Vec<_>>(), tiles);
+
+        thread::scope(|scope| {
+            for (index, mut view) in views.into_iter().enumerate() {
+                scope.spawn(move |_| {
+                    for (_, _, element) in view.iter_mut() {
+                        *element = index as u32;
+                    }
+                });
+            }
+        }).unwrap();
+
+        for (index, tile) in tiles.iter().enumerate() {
+            assert!(tile.index_iter().all(|(x, y)| raster.get(x, y) == index as u32));
+        }
+    }
+}
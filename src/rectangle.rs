@@ -13,27 +13,82 @@
 // limitations under the License.
 
 use std::cmp::min;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
 use std::iter::FusedIterator;
 
+/// Integer type of the coordinates of rectangles, rasters and pixel samples.
+///
+/// `u32` is the default, for images with their origin at the top left corner. `i32` also allows coordinates left of and above the origin, for example for
+/// rasters that are padded for filter footprints and for overscan regions around an image.
+pub trait Coordinate: Copy + Ord + Default + Hash + Debug + Display + Send + Sync + 'static {
+    /// Converts this coordinate to `i64`, which holds coordinates of all coordinate types and the differences between them without overflow.
+    fn to_i64(self) -> i64;
+
+    /// Converts an `i64` value to a coordinate, saturating at the minimum and maximum values of this type.
+    fn from_i64(value: i64) -> Self;
+
+    /// Returns this coordinate moved by the given distance, saturating at the minimum and maximum values of this type.
+    #[inline]
+    fn offset(self, distance: i64) -> Self {
+        Self::from_i64(self.to_i64() + distance)
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, Debug)]
-pub struct Rectangle {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RectangleData<C>"))]
+pub struct Rectangle<C: Coordinate = u32> {
+    pub left: C,
+    pub top: C,
+    pub right: C,
+    pub bottom: C,
+}
+
+/// Margins on each side of a rectangle, used to inset or expand it.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub struct Margins {
     pub left: u32,
     pub top: u32,
     pub right: u32,
     pub bottom: u32,
 }
 
+/// Order in which the pixels of a rectangle are visited.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PixelOrder {
+    /// Row by row, from left to right.
+    #[default]
+    Scanline,
+
+    /// Along a Z-order (Morton) curve.
+    Morton,
+
+    /// Along a Hilbert curve.
+    Hilbert,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct RectangleData<C> {
+    left: C,
+    top: C,
+    right: C,
+    bottom: C,
+}
+
 #[derive(Clone, Eq, PartialEq, Debug)]
-pub struct RectangleIndexIterator {
-    rectangle: Rectangle,
+pub struct RectangleIndexIterator<C: Coordinate = u32> {
+    rectangle: Rectangle<C>,
 
-    index_x: u32,
-    index_y: u32,
+    index_x: i64,
+    index_y: i64,
 }
 
 #[derive(Clone, Eq, PartialEq, Debug)]
-pub struct RectangleTileIterator {
-    rectangle: Rectangle,
+pub struct RectangleTileIterator<C: Coordinate = u32> {
+    rectangle: Rectangle<C>,
 
     tile_count_x: u32,
     tile_count_y: u32,
@@ -41,15 +96,98 @@ pub struct RectangleTileIterator {
     tile_index_x: u32,
     tile_index_y: u32,
 
-    tile_left: u32,
-    tile_top: u32,
+    tile_left: i64,
+    tile_top: i64,
+}
+
+/// Iterator over the pixels of a rectangle along a space-filling curve.
+///
+/// The rectangle is covered by a grid of square blocks with the largest power of two size that fits in the rectangle. The strips to the right of and below
+/// the grid that are not covered are in turn covered by smaller blocks, so that every block lies entirely within the rectangle and no positions are skipped.
+/// The blocks of each grid are visited row by row and the pixels within each block along the curve.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct RectangleCurveIterator<C: Coordinate = u32> {
+    rectangle: Rectangle<C>,
+    curve: PixelOrder,
+
+    /// Regions (left, top, width, height relative to the rectangle) that remain to be covered by blocks.
+    regions: Vec<(u32, u32, u32, u32)>,
+
+    region_left: u32,
+    region_top: u32,
+    block_size: u32,
+    block_count_x: u32,
+    block_count: u64,
+    block_index: u64,
+    curve_index: u64,
+
+    remaining: usize,
+}
+
+/// Iterator over the pixels of a rectangle in a selectable pixel order.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum PixelOrderIterator<C: Coordinate = u32> {
+    Scanline(RectangleIndexIterator<C>),
+    Curve(RectangleCurveIterator<C>),
+}
+
+/// Iterator over tiles of a fixed size that cover a rectangle, row by row, with the tile grid coordinates of each tile.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct RectangleFixedTileIterator<C: Coordinate = u32> {
+    rectangle: Rectangle<C>,
+
+    tile_width: u32,
+    tile_height: u32,
+
+    tile_count_x: u32,
+    tile_count_y: u32,
+
+    tile_index_x: u32,
+    tile_index_y: u32,
+}
+
+/// Iterator over the pixels at the edges of a rectangle, row by row.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct RectangleBorderIterator<C: Coordinate = u32> {
+    rectangle: Rectangle<C>,
+
+    index_x: i64,
+    index_y: i64,
+
+    remaining: usize,
+}
+
+// ===== Coordinate ============================================================================================================================================
+
+impl Coordinate for u32 {
+    #[inline]
+    fn to_i64(self) -> i64 {
+        self as i64
+    }
+
+    #[inline]
+    fn from_i64(value: i64) -> u32 {
+        value.clamp(0, u32::MAX as i64) as u32
+    }
+}
+
+impl Coordinate for i32 {
+    #[inline]
+    fn to_i64(self) -> i64 {
+        self as i64
+    }
+
+    #[inline]
+    fn from_i64(value: i64) -> i32 {
+        value.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+    }
 }
 
 // ===== Rectangle =============================================================================================================================================
 
-impl Rectangle {
+impl<C: Coordinate> Rectangle<C> {
     #[inline]
-    pub fn new(left: u32, top: u32, right: u32, bottom: u32) -> Rectangle {
+    pub fn new(left: C, top: C, right: C, bottom: C) -> Rectangle<C> {
         debug_assert!(left <= right, "left must be less than or equal to right but {} > {}", left, right);
         debug_assert!(top <= bottom, "top must be less than or equal to bottom but {} > {}", top, bottom);
 
@@ -58,12 +196,12 @@ impl Rectangle {
 
     #[inline]
     pub fn width(&self) -> u32 {
-        self.right - self.left
+        (self.right.to_i64() - self.left.to_i64()) as u32
     }
 
     #[inline]
     pub fn height(&self) -> u32 {
-        self.bottom - self.top
+        (self.bottom.to_i64() - self.top.to_i64()) as u32
     }
 
     #[inline]
@@ -77,25 +215,25 @@ impl Rectangle {
     }
 
     #[inline]
-    pub fn contains(&self, x: u32, y: u32) -> bool {
+    pub fn contains(&self, x: C, y: C) -> bool {
         x >= self.left && x < self.right && y >= self.top && y < self.bottom
     }
 
-    pub fn union(&self, other: &Rectangle) -> Rectangle {
-        let left = u32::min(self.left, other.left);
-        let top = u32::min(self.top, other.top);
-        let right = u32::max(self.right, other.right);
-        let bottom = u32::max(self.bottom, other.bottom);
+    pub fn union(&self, other: &Rectangle<C>) -> Rectangle<C> {
+        let left = C::min(self.left, other.left);
+        let top = C::min(self.top, other.top);
+        let right = C::max(self.right, other.right);
+        let bottom = C::max(self.bottom, other.bottom);
 
         Rectangle { left, top, right, bottom }
     }
 
-    pub fn intersection(&self, other: &Rectangle) -> Option<Rectangle> {
+    pub fn intersection(&self, other: &Rectangle<C>) -> Option<Rectangle<C>> {
         if self.overlaps(other) {
-            let left = u32::max(self.left, other.left);
-            let top = u32::max(self.top, other.top);
-            let right = u32::min(self.right, other.right);
-            let bottom = u32::min(self.bottom, other.bottom);
+            let left = C::max(self.left, other.left);
+            let top = C::max(self.top, other.top);
+            let right = C::min(self.right, other.right);
+            let bottom = C::min(self.bottom, other.bottom);
 
             Some(Rectangle { left, top, right, bottom })
         } else {
@@ -104,51 +242,195 @@ impl Rectangle {
     }
 
     #[inline]
-    pub fn overlaps(&self, other: &Rectangle) -> bool {
+    pub fn overlaps(&self, other: &Rectangle<C>) -> bool {
         self.left < other.right && self.top < other.bottom && self.right > other.left && self.bottom > other.top
     }
 
+    /// Returns this rectangle shrunk by the given margins; when the margins are larger than the rectangle, the result is empty.
+    pub fn inset(&self, margins: &Margins) -> Rectangle<C> {
+        let left = self.left.offset(margins.left as i64).min(self.right);
+        let top = self.top.offset(margins.top as i64).min(self.bottom);
+        let right = self.right.offset(-(margins.right as i64)).max(left);
+        let bottom = self.bottom.offset(-(margins.bottom as i64)).max(top);
+
+        Rectangle { left, top, right, bottom }
+    }
+
+    /// Returns this rectangle grown by the given margins and clamped against the bounds rectangle.
+    pub fn expand(&self, margins: &Margins, bounds: &Rectangle<C>) -> Rectangle<C> {
+        let expanded = Rectangle {
+            left: self.left.offset(-(margins.left as i64)),
+            top: self.top.offset(-(margins.top as i64)),
+            right: self.right.offset(margins.right as i64),
+            bottom: self.bottom.offset(margins.bottom as i64),
+        };
+
+        expanded.clamp(bounds)
+    }
+
+    /// Returns the part of this rectangle that is inside the bounds rectangle. Unlike `intersection()`, this always returns a rectangle, which is empty and
+    /// lies on the edge of the bounds rectangle when the rectangles do not overlap.
+    pub fn clamp(&self, bounds: &Rectangle<C>) -> Rectangle<C> {
+        let left = self.left.clamp(bounds.left, bounds.right);
+        let top = self.top.clamp(bounds.top, bounds.bottom);
+        let right = self.right.clamp(left, bounds.right);
+        let bottom = self.bottom.clamp(top, bounds.bottom);
+
+        Rectangle { left, top, right, bottom }
+    }
+
+    /// Returns the point inside this rectangle that is nearest to the given point. This rectangle must not be empty.
+    #[inline]
+    pub fn clamp_point(&self, x: C, y: C) -> (C, C) {
+        debug_assert!(!self.is_empty(), "cannot clamp a point to an empty rectangle");
+
+        (x.clamp(self.left, C::from_i64(self.right.to_i64() - 1)), y.clamp(self.top, C::from_i64(self.bottom.to_i64() - 1)))
+    }
+
+    /// Splits this rectangle into the parts left and right of x, which is clamped to the horizontal range of this rectangle.
+    pub fn split_at_x(&self, x: C) -> (Rectangle<C>, Rectangle<C>) {
+        let x = x.clamp(self.left, self.right);
+        (Rectangle { right: x, ..self.clone() }, Rectangle { left: x, ..self.clone() })
+    }
+
+    /// Splits this rectangle into the parts above and below y, which is clamped to the vertical range of this rectangle.
+    pub fn split_at_y(&self, y: C) -> (Rectangle<C>, Rectangle<C>) {
+        let y = y.clamp(self.top, self.bottom);
+        (Rectangle { bottom: y, ..self.clone() }, Rectangle { top: y, ..self.clone() })
+    }
+
+    /// Returns the parts of this rectangle that are not covered by the other rectangle, as at most four non-overlapping rectangles: the full-width parts
+    /// above and below the other rectangle, followed by the parts to the left and right of it.
+    pub fn subtract(&self, other: &Rectangle<C>) -> Vec<Rectangle<C>> {
+        let intersection = match self.intersection(other) {
+            Some(intersection) => intersection,
+            None => return if self.is_empty() { Vec::new() } else { vec![self.clone()] },
+        };
+
+        let parts = [
+            Rectangle { bottom: intersection.top, ..self.clone() },
+            Rectangle { top: intersection.bottom, ..self.clone() },
+            Rectangle { left: self.left, right: intersection.left, ..intersection.clone() },
+            Rectangle { left: intersection.right, right: self.right, ..intersection.clone() },
+        ];
+
+        parts.into_iter().filter(|part| !part.is_empty()).collect()
+    }
+
+    /// Returns the smallest rectangle that contains all of the given rectangles, ignoring empty rectangles, or `None` if there are no non-empty rectangles.
+    pub fn bounding_box<'a, I: IntoIterator<Item=&'a Rectangle<C>>>(rectangles: I) -> Option<Rectangle<C>> {
+        rectangles.into_iter().filter(|rectangle| !rectangle.is_empty()).fold(None, |bounds, rectangle| match bounds {
+            Some(bounds) => Some(rectangle.union(&bounds)),
+            None => Some(rectangle.clone()),
+        })
+    }
+
     #[inline]
-    pub fn index_iter(&self) -> RectangleIndexIterator {
+    pub fn index_iter(&self) -> RectangleIndexIterator<C> {
         RectangleIndexIterator::new(self.clone())
     }
 
     #[inline]
-    pub fn tile_iter(&self, tile_count_x: u32, tile_count_y: u32) -> RectangleTileIterator {
+    pub fn tile_iter(&self, tile_count_x: u32, tile_count_y: u32) -> RectangleTileIterator<C> {
         RectangleTileIterator::new(self.clone(), tile_count_x, tile_count_y)
     }
 
+    /// Returns the number of tiles horizontally and vertically when this rectangle is divided into tiles of the given size.
     #[inline]
-    pub fn linear_index(&self, x: u32, y: u32) -> usize {
+    pub fn fixed_tile_count(&self, tile_width: u32, tile_height: u32) -> (u32, u32) {
+        debug_assert!(tile_width > 0 && tile_height > 0, "tile size must be greater than zero but is {}x{}", tile_width, tile_height);
+
+        (self.width().div_ceil(tile_width), self.height().div_ceil(tile_height))
+    }
+
+    /// Returns the tile with the given tile grid coordinates when this rectangle is divided into tiles of the given size; tiles at the right and bottom
+    /// edges may be smaller than the tile size.
+    pub fn fixed_tile(&self, tile_x: u32, tile_y: u32, tile_width: u32, tile_height: u32) -> Rectangle<C> {
+        let left = self.left.to_i64() + (tile_x as i64 * tile_width as i64).min(self.width() as i64);
+        let top = self.top.to_i64() + (tile_y as i64 * tile_height as i64).min(self.height() as i64);
+        let right = (left + tile_width as i64).min(self.right.to_i64());
+        let bottom = (top + tile_height as i64).min(self.bottom.to_i64());
+
+        Rectangle { left: C::from_i64(left), top: C::from_i64(top), right: C::from_i64(right), bottom: C::from_i64(bottom) }
+    }
+
+    /// Returns an iterator over the pixels of this rectangle along a Z-order (Morton) curve.
+    #[inline]
+    pub fn morton_iter(&self) -> RectangleCurveIterator<C> {
+        RectangleCurveIterator::new(self.clone(), PixelOrder::Morton)
+    }
+
+    /// Returns an iterator over the pixels of this rectangle along a Hilbert curve.
+    #[inline]
+    pub fn hilbert_iter(&self) -> RectangleCurveIterator<C> {
+        RectangleCurveIterator::new(self.clone(), PixelOrder::Hilbert)
+    }
+
+    /// Returns an iterator over the pixels of this rectangle in the given order.
+    pub fn pixel_order_iter(&self, pixel_order: PixelOrder) -> PixelOrderIterator<C> {
+        match pixel_order {
+            PixelOrder::Scanline => PixelOrderIterator::Scanline(self.index_iter()),
+            PixelOrder::Morton | PixelOrder::Hilbert => PixelOrderIterator::Curve(RectangleCurveIterator::new(self.clone(), pixel_order)),
+        }
+    }
+
+    /// Returns an iterator over tiles of the given size that cover this rectangle, row by row. Each item contains the tile grid coordinates and the
+    /// rectangle of the tile; tiles at the right and bottom edges may be smaller than the tile size.
+    #[inline]
+    pub fn fixed_tile_iter(&self, tile_width: u32, tile_height: u32) -> RectangleFixedTileIterator<C> {
+        RectangleFixedTileIterator::new(self.clone(), tile_width, tile_height)
+    }
+
+    /// Returns an iterator over the pixels at the edges of this rectangle, row by row.
+    #[inline]
+    pub fn border_iter(&self) -> RectangleBorderIterator<C> {
+        RectangleBorderIterator::new(self.clone())
+    }
+
+    #[inline]
+    pub fn linear_index(&self, x: C, y: C) -> usize {
         debug_assert!(x >= self.left && x < self.right, "invalid x index: {} (valid range is {}..{})", x, self.left, self.right);
         debug_assert!(y >= self.top && y < self.bottom, "invalid y index: {} (valid range is {}..{})", y, self.top, self.bottom);
 
-        (y - self.top) as usize * self.width() as usize + (x - self.left) as usize
+        (y.to_i64() - self.top.to_i64()) as usize * self.width() as usize + (x.to_i64() - self.left.to_i64()) as usize
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<C: Coordinate> TryFrom<RectangleData<C>> for Rectangle<C> {
+    type Error = String;
+
+    fn try_from(data: RectangleData<C>) -> Result<Rectangle<C>, String> {
+        if data.left <= data.right && data.top <= data.bottom {
+            Ok(Rectangle { left: data.left, top: data.top, right: data.right, bottom: data.bottom })
+        } else {
+            Err(format!("invalid rectangle: left {} and top {} must not be greater than right {} and bottom {}", data.left, data.top, data.right, data.bottom))
+        }
     }
 }
 
 // ===== RectangleIndexIterator ================================================================================================================================
 
-impl RectangleIndexIterator {
-    fn new(rectangle: Rectangle) -> RectangleIndexIterator {
-        let index_x = rectangle.left;
-        let index_y = if rectangle.right > rectangle.left { rectangle.top } else { rectangle.bottom };
+impl<C: Coordinate> RectangleIndexIterator<C> {
+    fn new(rectangle: Rectangle<C>) -> RectangleIndexIterator<C> {
+        let index_x = rectangle.left.to_i64();
+        let index_y = if rectangle.right > rectangle.left { rectangle.top.to_i64() } else { rectangle.bottom.to_i64() };
 
         RectangleIndexIterator { rectangle, index_x, index_y }
     }
 }
 
-impl Iterator for RectangleIndexIterator {
-    type Item = (u32, u32);
+impl<C: Coordinate> Iterator for RectangleIndexIterator<C> {
+    type Item = (C, C);
 
-    fn next(&mut self) -> Option<(u32, u32)> {
-        if self.index_y < self.rectangle.bottom {
-            let indices = (self.index_x, self.index_y);
+    fn next(&mut self) -> Option<(C, C)> {
+        if self.index_y < self.rectangle.bottom.to_i64() {
+            let indices = (C::from_i64(self.index_x), C::from_i64(self.index_y));
 
             // Advance indices
             self.index_x += 1;
-            if self.index_x >= self.rectangle.right {
-                self.index_x = self.rectangle.left;
+            if self.index_x >= self.rectangle.right.to_i64() {
+                self.index_x = self.rectangle.left.to_i64();
                 self.index_y += 1;
             }
 
@@ -159,9 +441,9 @@ impl Iterator for RectangleIndexIterator {
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        if self.index_y < self.rectangle.bottom {
-            let remaining_y = (self.rectangle.bottom - self.index_y) as usize;
-            let remaining = remaining_y + (self.rectangle.right - self.index_x) as usize;
+        if self.index_y < self.rectangle.bottom.to_i64() {
+            let remaining_y = (self.rectangle.bottom.to_i64() - self.index_y) as usize;
+            let remaining = (remaining_y - 1) * self.rectangle.width() as usize + (self.rectangle.right.to_i64() - self.index_x) as usize;
             (remaining, Some(remaining))
         } else {
             (0, Some(0))
@@ -169,15 +451,15 @@ impl Iterator for RectangleIndexIterator {
     }
 }
 
-impl ExactSizeIterator for RectangleIndexIterator {}
+impl<C: Coordinate> ExactSizeIterator for RectangleIndexIterator<C> {}
 
-impl FusedIterator for RectangleIndexIterator {}
+impl<C: Coordinate> FusedIterator for RectangleIndexIterator<C> {}
 
 // ===== RectangleTileIterator =================================================================================================================================
 
-impl RectangleTileIterator {
+impl<C: Coordinate> RectangleTileIterator<C> {
     #[inline]
-    fn new(rectangle: Rectangle, tile_count_x: u32, tile_count_y: u32) -> RectangleTileIterator {
+    fn new(rectangle: Rectangle<C>, tile_count_x: u32, tile_count_y: u32) -> RectangleTileIterator<C> {
         debug_assert!(tile_count_x > 0, "tile_count_x must be greater than zero but {} < 0", tile_count_x);
         debug_assert!(tile_count_y > 0, "tile_count_y must be greater than zero but {} < 0", tile_count_y);
 
@@ -185,21 +467,22 @@ impl RectangleTileIterator {
         let tile_count_x = min(tile_count_x, rectangle.width());
         let tile_count_y = min(tile_count_y, rectangle.height());
 
-        let (tile_left, tile_top) = (rectangle.left, rectangle.top);
+        let (tile_left, tile_top) = (rectangle.left.to_i64(), rectangle.top.to_i64());
 
         RectangleTileIterator { rectangle, tile_count_x, tile_count_y, tile_index_x: 0, tile_index_y: 0, tile_left, tile_top }
     }
 }
 
-impl Iterator for RectangleTileIterator {
-    type Item = Rectangle;
+impl<C: Coordinate> Iterator for RectangleTileIterator<C> {
+    type Item = Rectangle<C>;
 
-    fn next(&mut self) -> Option<Rectangle> {
+    fn next(&mut self) -> Option<Rectangle<C>> {
         if self.tile_index_y < self.tile_count_y {
-            let tile_right = min(self.tile_left + (self.rectangle.right - self.tile_left) / (self.tile_count_x - self.tile_index_x), self.rectangle.right);
-            let tile_bottom = min(self.tile_top + (self.rectangle.bottom - self.tile_top) / (self.tile_count_y - self.tile_index_y), self.rectangle.bottom);
+            let (right, bottom) = (self.rectangle.right.to_i64(), self.rectangle.bottom.to_i64());
+            let tile_right = min(self.tile_left + (right - self.tile_left) / (self.tile_count_x - self.tile_index_x) as i64, right);
+            let tile_bottom = min(self.tile_top + (bottom - self.tile_top) / (self.tile_count_y - self.tile_index_y) as i64, bottom);
 
-            let tile = Rectangle::new(self.tile_left, self.tile_top, tile_right, tile_bottom);
+            let tile = Rectangle::new(C::from_i64(self.tile_left), C::from_i64(self.tile_top), C::from_i64(tile_right), C::from_i64(tile_bottom));
 
             // Advance indices
             self.tile_index_x += 1;
@@ -208,7 +491,7 @@ impl Iterator for RectangleTileIterator {
                 self.tile_index_x = 0;
                 self.tile_index_y += 1;
                 self.tile_top = tile_bottom;
-                self.tile_left = self.rectangle.left;
+                self.tile_left = self.rectangle.left.to_i64();
             }
 
             Some(tile)
@@ -220,7 +503,208 @@ impl Iterator for RectangleTileIterator {
     fn size_hint(&self) -> (usize, Option<usize>) {
         if self.tile_index_y < self.tile_count_y {
             let remaining_y = (self.tile_count_y - self.tile_index_y) as usize;
-            let remaining = remaining_y + (self.tile_count_x - self.tile_index_x) as usize;
+            let remaining = (remaining_y - 1) * self.tile_count_x as usize + (self.tile_count_x - self.tile_index_x) as usize;
+            (remaining, Some(remaining))
+        } else {
+            (0, Some(0))
+        }
+    }
+}
+
+impl<C: Coordinate> ExactSizeIterator for RectangleTileIterator<C> {}
+
+impl<C: Coordinate> FusedIterator for RectangleTileIterator<C> {}
+
+// ===== RectangleCurveIterator ================================================================================================================================
+
+impl<C: Coordinate> RectangleCurveIterator<C> {
+    fn new(rectangle: Rectangle<C>, curve: PixelOrder) -> RectangleCurveIterator<C> {
+        let regions = if rectangle.is_empty() { Vec::new() } else { vec![(0, 0, rectangle.width(), rectangle.height())] };
+        let remaining = rectangle.size();
+
+        RectangleCurveIterator {
+            rectangle, curve, regions,
+            region_left: 0, region_top: 0, block_size: 1, block_count_x: 0, block_count: 0, block_index: 0, curve_index: 0,
+            remaining,
+        }
+    }
+
+    /// Starts covering the next remaining region with a grid of blocks, and adds the strips to the right of and below the grid to the remaining regions.
+    fn next_region(&mut self) {
+        if let Some((left, top, width, height)) = self.regions.pop() {
+            let (block_size, block_count_x, block_count_y) = curve_blocks(width, height);
+            let (grid_width, grid_height) = (block_size * block_count_x, block_size * block_count_y);
+
+            // The strip to the right is covered before the strip below
+            if grid_height < height {
+                self.regions.push((left, top + grid_height, width, height - grid_height));
+            }
+            if grid_width < width {
+                self.regions.push((left + grid_width, top, width - grid_width, grid_height));
+            }
+
+            (self.region_left, self.region_top) = (left, top);
+            (self.block_size, self.block_count_x) = (block_size, block_count_x);
+            self.block_count = block_count_x as u64 * block_count_y as u64;
+            self.block_index = 0;
+            self.curve_index = 0;
+        }
+    }
+}
+
+impl<C: Coordinate> Iterator for RectangleCurveIterator<C> {
+    type Item = (C, C);
+
+    fn next(&mut self) -> Option<(C, C)> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        if self.curve_index >= self.block_size as u64 * self.block_size as u64 {
+            self.curve_index = 0;
+            self.block_index += 1;
+        }
+        if self.block_index >= self.block_count {
+            self.next_region();
+        }
+
+        let (dx, dy) = match self.curve {
+            PixelOrder::Hilbert => hilbert_position(self.curve_index, self.block_size),
+            _ => morton_position(self.curve_index),
+        };
+        self.curve_index += 1;
+
+        let (block_x, block_y) = (self.block_index % self.block_count_x as u64, self.block_index / self.block_count_x as u64);
+        let x = self.rectangle.left.to_i64() + self.region_left as i64 + (block_x * self.block_size as u64) as i64 + dx as i64;
+        let y = self.rectangle.top.to_i64() + self.region_top as i64 + (block_y * self.block_size as u64) as i64 + dy as i64;
+        self.remaining -= 1;
+
+        Some((C::from_i64(x), C::from_i64(y)))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<C: Coordinate> ExactSizeIterator for RectangleCurveIterator<C> {}
+
+impl<C: Coordinate> FusedIterator for RectangleCurveIterator<C> {}
+
+/// Returns the size of the largest power of two square block that fits in a region of the given size, and the number of blocks horizontally and vertically
+/// in the grid of such blocks that covers as much of the region as possible. The width and height must not be zero.
+fn curve_blocks(width: u32, height: u32) -> (u32, u32, u32) {
+    let block_size = 1 << width.min(height).ilog2();
+    (block_size, width / block_size, height / block_size)
+}
+
+/// Returns the position of the point with the given index on a Z-order curve, by separating the even and odd bits of the index.
+fn morton_position(index: u64) -> (u32, u32) {
+    let compact = |mut v: u64| {
+        v &= 0x5555_5555_5555_5555;
+        v = (v | (v >> 1)) & 0x3333_3333_3333_3333;
+        v = (v | (v >> 2)) & 0x0f0f_0f0f_0f0f_0f0f;
+        v = (v | (v >> 4)) & 0x00ff_00ff_00ff_00ff;
+        v = (v | (v >> 8)) & 0x0000_ffff_0000_ffff;
+        v = (v | (v >> 16)) & 0x0000_0000_ffff_ffff;
+        v as u32
+    };
+
+    (compact(index), compact(index >> 1))
+}
+
+/// Returns the position of the point with the given index on a Hilbert curve that fills a square of the given size, which must be a power of two.
+fn hilbert_position(index: u64, size: u32) -> (u32, u32) {
+    let (mut x, mut y) = (0, 0);
+    let mut t = index;
+
+    let mut s = 1;
+    while s < size {
+        let rx = (1 & (t / 2)) as u32;
+        let ry = (1 & (t ^ rx as u64)) as u32;
+
+        // Rotate the quadrant
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            (x, y) = (y, x);
+        }
+
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+
+    (x, y)
+}
+
+// ===== PixelOrderIterator ====================================================================================================================================
+
+impl<C: Coordinate> Iterator for PixelOrderIterator<C> {
+    type Item = (C, C);
+
+    #[inline]
+    fn next(&mut self) -> Option<(C, C)> {
+        match self {
+            PixelOrderIterator::Scanline(iter) => iter.next(),
+            PixelOrderIterator::Curve(iter) => iter.next(),
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            PixelOrderIterator::Scanline(iter) => iter.size_hint(),
+            PixelOrderIterator::Curve(iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl<C: Coordinate> ExactSizeIterator for PixelOrderIterator<C> {}
+
+impl<C: Coordinate> FusedIterator for PixelOrderIterator<C> {}
+
+// ===== RectangleFixedTileIterator ============================================================================================================================
+
+impl<C: Coordinate> RectangleFixedTileIterator<C> {
+    #[inline]
+    fn new(rectangle: Rectangle<C>, tile_width: u32, tile_height: u32) -> RectangleFixedTileIterator<C> {
+        let (tile_count_x, tile_count_y) = rectangle.fixed_tile_count(tile_width, tile_height);
+        let tile_index_y = if tile_count_x > 0 { 0 } else { tile_count_y };
+
+        RectangleFixedTileIterator { rectangle, tile_width, tile_height, tile_count_x, tile_count_y, tile_index_x: 0, tile_index_y }
+    }
+}
+
+impl<C: Coordinate> Iterator for RectangleFixedTileIterator<C> {
+    type Item = (u32, u32, Rectangle<C>);
+
+    fn next(&mut self) -> Option<(u32, u32, Rectangle<C>)> {
+        if self.tile_index_y < self.tile_count_y {
+            let (tile_x, tile_y) = (self.tile_index_x, self.tile_index_y);
+            let tile = self.rectangle.fixed_tile(tile_x, tile_y, self.tile_width, self.tile_height);
+
+            // Advance indices
+            self.tile_index_x += 1;
+            if self.tile_index_x >= self.tile_count_x {
+                self.tile_index_x = 0;
+                self.tile_index_y += 1;
+            }
+
+            Some((tile_x, tile_y, tile))
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.tile_index_y < self.tile_count_y {
+            let remaining_y = (self.tile_count_y - self.tile_index_y) as usize;
+            let remaining = (remaining_y - 1) * self.tile_count_x as usize + (self.tile_count_x - self.tile_index_x) as usize;
             (remaining, Some(remaining))
         } else {
             (0, Some(0))
@@ -228,9 +712,54 @@ impl Iterator for RectangleTileIterator {
     }
 }
 
-impl ExactSizeIterator for RectangleTileIterator {}
+impl<C: Coordinate> ExactSizeIterator for RectangleFixedTileIterator<C> {}
+
+impl<C: Coordinate> FusedIterator for RectangleFixedTileIterator<C> {}
+
+// ===== RectangleBorderIterator ===============================================================================================================================
+
+impl<C: Coordinate> RectangleBorderIterator<C> {
+    fn new(rectangle: Rectangle<C>) -> RectangleBorderIterator<C> {
+        let (width, height) = (rectangle.width() as usize, rectangle.height() as usize);
+        let remaining = if width <= 2 || height <= 2 { width * height } else { 2 * width + 2 * (height - 2) };
+        let (index_x, index_y) = (rectangle.left.to_i64(), rectangle.top.to_i64());
+
+        RectangleBorderIterator { rectangle, index_x, index_y, remaining }
+    }
+}
+
+impl<C: Coordinate> Iterator for RectangleBorderIterator<C> {
+    type Item = (C, C);
+
+    fn next(&mut self) -> Option<(C, C)> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let indices = (C::from_i64(self.index_x), C::from_i64(self.index_y));
+        self.remaining -= 1;
+
+        // Advance indices; in rows between the top and bottom rows, skip from the left edge to the right edge
+        let (left, top, right, bottom) = (self.rectangle.left.to_i64(), self.rectangle.top.to_i64(), self.rectangle.right.to_i64(), self.rectangle.bottom.to_i64());
+        let inner_row = self.index_y > top && self.index_y < bottom - 1;
+        self.index_x = if inner_row && self.index_x == left { right - 1 } else { self.index_x + 1 };
+        if self.index_x >= right || self.index_x <= left {
+            self.index_x = left;
+            self.index_y += 1;
+        }
+
+        Some(indices)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<C: Coordinate> ExactSizeIterator for RectangleBorderIterator<C> {}
 
-impl FusedIterator for RectangleTileIterator {}
+impl<C: Coordinate> FusedIterator for RectangleBorderIterator<C> {}
 
 // ===== Tests =================================================================================================================================================
 
@@ -284,6 +813,18 @@ mod test {
         assert!(!rect.contains(100, 220));
     }
 
+    #[test]
+    fn rectangle_negative_coordinates() {
+        let rect = Rectangle::new(-4, -3, 4, 3);
+        assert_eq!(rect.width(), 8);
+        assert_eq!(rect.height(), 6);
+        assert!(rect.contains(-4, -3));
+        assert!(!rect.contains(-5, 0));
+        assert_eq!(rect.linear_index(-3, -2), 9);
+        assert_eq!(rect.index_iter().next(), Some((-4, -3)));
+        assert_eq!(rect.index_iter().last(), Some((3, 2)));
+    }
+
     #[test]
     fn rectangle_union() {
         let rect1 = Rectangle::new(10, 20, 100, 220);
@@ -347,6 +888,71 @@ mod test {
         assert!(rect1.overlaps(&rect2));
     }
 
+    #[test]
+    fn rectangle_inset() {
+        let rect = Rectangle::new(10, 20, 100, 220);
+        assert_eq!(rect.inset(&Margins { left: 1, top: 2, right: 3, bottom: 4 }), Rectangle::new(11, 22, 97, 216));
+        assert!(rect.inset(&Margins { left: 50, top: 0, right: 50, bottom: 0 }).is_empty());
+        assert_eq!(rect.inset(&Margins::default()), rect);
+    }
+
+    #[test]
+    fn rectangle_expand() {
+        let bounds = Rectangle::new(-10, -10, 50, 50);
+        let rect = Rectangle::new(-8, 0, 20, 30);
+        assert_eq!(rect.expand(&Margins { left: 4, top: 4, right: 4, bottom: 4 }, &bounds), Rectangle::new(-10, -4, 24, 34));
+        assert_eq!(rect.expand(&Margins { left: 0, top: 0, right: 100, bottom: 0 }, &bounds), Rectangle::new(-8, 0, 50, 30));
+    }
+
+    #[test]
+    fn rectangle_clamp() {
+        let bounds = Rectangle::new(0, 0, 10, 10);
+        assert_eq!(Rectangle::new(-5, 5, 5, 15).clamp(&bounds), Rectangle::new(0, 5, 5, 10));
+        assert!(Rectangle::new(20, 20, 30, 30).clamp(&bounds).is_empty());
+        assert_eq!(bounds.clamp_point(-3, 12), (0, 9));
+        assert_eq!(bounds.clamp_point(4, 5), (4, 5));
+    }
+
+    #[test]
+    fn rectangle_split() {
+        let rect = Rectangle::new(10, 20, 100, 220);
+        assert_eq!(rect.split_at_x(40), (Rectangle::new(10, 20, 40, 220), Rectangle::new(40, 20, 100, 220)));
+        assert_eq!(rect.split_at_y(300), (rect.clone(), Rectangle::new(10, 220, 100, 220)));
+    }
+
+    #[test]
+    fn rectangle_subtract() {
+        let rect = Rectangle::new(0, 0, 10, 10);
+
+        let parts = rect.subtract(&Rectangle::new(2, 3, 5, 7));
+        assert_eq!(parts, vec![Rectangle::new(0, 0, 10, 3), Rectangle::new(0, 7, 10, 10), Rectangle::new(0, 3, 2, 7), Rectangle::new(5, 3, 10, 7)]);
+        assert_eq!(parts.iter().map(Rectangle::size).sum::<usize>(), 100 - 3 * 4);
+
+        assert_eq!(rect.subtract(&Rectangle::new(-5, -5, 5, 20)), vec![Rectangle::new(5, 0, 10, 10)]);
+        assert_eq!(rect.subtract(&Rectangle::new(20, 20, 30, 30)), vec![rect.clone()]);
+        assert!(rect.subtract(&Rectangle::new(-1, -1, 11, 11)).is_empty());
+    }
+
+    #[test]
+    fn rectangle_bounding_box() {
+        let rects = [Rectangle::new(0, 5, 10, 10), Rectangle::new(-5, 20, -5, 30), Rectangle::new(3, -2, 4, 8)];
+        assert_eq!(Rectangle::bounding_box(&rects), Some(Rectangle::new(0, -2, 10, 10)));
+        assert_eq!(Rectangle::bounding_box(&rects[1..2]), None);
+    }
+
+    #[test]
+    fn rectangle_border_iter() {
+        let border: Vec<_> = Rectangle::new(-1, 0, 2, 4).border_iter().collect();
+        assert_eq!(border, vec![(-1, 0), (0, 0), (1, 0), (-1, 1), (1, 1), (-1, 2), (1, 2), (-1, 3), (0, 3), (1, 3)]);
+
+        for (width, height) in [(1, 1), (1, 5), (5, 1), (2, 2), (2, 6), (7, 3), (0, 4)] {
+            let rect = Rectangle::new(0, 0, width, height);
+            let expected: Vec<_> = rect.index_iter().filter(|&(x, y)| x == 0 || y == 0 || x == width - 1 || y == height - 1).collect();
+            assert_eq!(rect.border_iter().len(), expected.len());
+            assert_eq!(rect.border_iter().collect::<Vec<_>>(), expected, "wrong border for {}x{}", width, height);
+        }
+    }
+
     #[test]
     fn rectangle_index_iter() {
         let rect = Rectangle::new(10, 20, 100, 220);
@@ -366,6 +972,15 @@ mod test {
         assert_eq!(count, 90 * 200);
     }
 
+    #[test]
+    fn rectangle_index_iter_len() {
+        let mut iter = Rectangle::new(10, 20, 14, 23).index_iter();
+        for remaining in (0..=12).rev() {
+            assert_eq!(iter.len(), remaining);
+            iter.next();
+        }
+    }
+
     #[test]
     fn rectangle_tile_iter_horizontal() {
         for width in 8..122 {
@@ -400,6 +1015,112 @@ mod test {
         }
     }
 
+    #[test]
+    fn rectangle_tile_iter_len() {
+        let mut iter = Rectangle::new(0, 0, 100, 100).tile_iter(4, 3);
+        for remaining in (0..=12).rev() {
+            assert_eq!(iter.len(), remaining);
+            iter.next();
+        }
+    }
+
+    fn assert_visits_all_pixels<C: Coordinate, I: ExactSizeIterator<Item=(C, C)>>(rect: &Rectangle<C>, iter: I) {
+        assert_eq!(iter.len(), rect.size());
+
+        let mut visited = vec![false; rect.size()];
+        for (x, y) in iter {
+            let index = rect.linear_index(x, y);
+            assert!(!visited[index], "pixel ({}, {}) visited twice", x, y);
+            visited[index] = true;
+        }
+        assert!(visited.iter().all(|&v| v), "not all pixels visited in {:?}", rect);
+    }
+
+    #[test]
+    fn rectangle_morton_iter() {
+        let order: Vec<_> = Rectangle::new(0, 0, 4, 4).morton_iter().take(8).collect();
+        assert_eq!(order, vec![(0, 0), (1, 0), (0, 1), (1, 1), (2, 0), (3, 0), (2, 1), (3, 1)]);
+
+        for rect in [Rectangle::new(-5, 3, 11, 19), Rectangle::new(0, 0, 13, 7), Rectangle::new(2, 2, 100, 3), Rectangle::new(0, 0, 0, 5)] {
+            assert_visits_all_pixels(&rect, rect.morton_iter());
+        }
+    }
+
+    #[test]
+    fn rectangle_hilbert_iter() {
+        // Consecutive pixels along a Hilbert curve over a power of two square are neighbours
+        let pixels: Vec<_> = Rectangle::<i32>::new(-8, -8, 8, 8).hilbert_iter().collect();
+        assert!(pixels.windows(2).all(|w| (w[0].0 - w[1].0).abs() + (w[0].1 - w[1].1).abs() == 1));
+        assert_eq!(pixels[0], (-8, -8));
+        assert_eq!(pixels[255], (7, -8));
+
+        for rect in [Rectangle::new(-5, 3, 11, 19), Rectangle::new(0, 0, 13, 7), Rectangle::new(2, 2, 3, 100), Rectangle::new(0, 0, 5, 0)] {
+            assert_visits_all_pixels(&rect, rect.hilbert_iter());
+        }
+    }
+
+    #[test]
+    fn rectangle_curve_iter_walk_cost() {
+        // Every block lies within the rectangle, so that walking the curve costs one step per pixel also for sizes that are not a power of two
+        for (width, height) in [(65, 65), (127, 129), (100, 37), (1, 1000), (1000, 3)] {
+            let rect = Rectangle::new(10, 20, 10 + width, 20 + height);
+            for mut iter in [rect.morton_iter(), rect.hilbert_iter()] {
+                let mut walked = 0;
+                let mut block_area = 0;
+                while iter.next().is_some() {
+                    walked += 1;
+                    if iter.block_index == 0 && iter.curve_index == 1 {
+                        block_area += iter.block_count * iter.block_size as u64 * iter.block_size as u64;
+                    }
+
+                    let (block_x, block_y) = (iter.block_index % iter.block_count_x as u64, iter.block_index / iter.block_count_x as u64);
+                    assert!(iter.region_left as u64 + (block_x + 1) * iter.block_size as u64 <= width as u64, "block outside of {:?}", rect);
+                    assert!(iter.region_top as u64 + (block_y + 1) * iter.block_size as u64 <= height as u64, "block outside of {:?}", rect);
+                }
+
+                assert_eq!(walked, rect.size());
+                assert_eq!(block_area, rect.size() as u64, "blocks do not exactly cover {:?}", rect);
+            }
+        }
+    }
+
+    #[test]
+    fn rectangle_pixel_order_iter() {
+        let rect = Rectangle::new(1, 2, 7, 5);
+        assert_eq!(rect.pixel_order_iter(PixelOrder::Scanline).collect::<Vec<_>>(), rect.index_iter().collect::<Vec<_>>());
+        assert_eq!(rect.pixel_order_iter(PixelOrder::Morton).collect::<Vec<_>>(), rect.morton_iter().collect::<Vec<_>>());
+        assert_eq!(rect.pixel_order_iter(PixelOrder::Hilbert).len(), 18);
+    }
+
+    #[test]
+    fn rectangle_fixed_tile_iter() {
+        let rect = Rectangle::new(-10, 5, 90, 55);
+        assert_eq!(rect.fixed_tile_count(32, 16), (4, 4));
+
+        let tiles: Vec<_> = rect.fixed_tile_iter(32, 16).collect();
+        assert_eq!(tiles.len(), 16);
+        assert_eq!(tiles[0], (0, 0, Rectangle::new(-10, 5, 22, 21)));
+        assert_eq!(tiles[5], (1, 1, Rectangle::new(22, 21, 54, 37)));
+        assert_eq!(tiles[15], (3, 3, Rectangle::new(86, 53, 90, 55)));
+        assert_eq!(tiles.iter().map(|(_, _, tile)| tile.size()).sum::<usize>(), rect.size());
+
+        for (tile_x, tile_y, tile) in &tiles {
+            assert_eq!(rect.fixed_tile(*tile_x, *tile_y, 32, 16), *tile);
+        }
+
+        let mut iter = rect.fixed_tile_iter(32, 16);
+        for remaining in (0..=16).rev() {
+            assert_eq!(iter.len(), remaining);
+            iter.next();
+        }
+    }
+
+    #[test]
+    fn rectangle_fixed_tile_iter_empty() {
+        assert_eq!(Rectangle::new(10, 10, 10, 50).fixed_tile_iter(8, 8).count(), 0);
+        assert_eq!(Rectangle::new(10, 10, 50, 10).fixed_tile_iter(8, 8).len(), 0);
+    }
+
     #[test]
     fn rectangle_linear_index() {
         let rect = Rectangle::new(10, 20, 100, 220);
@@ -409,4 +1130,48 @@ mod test {
         assert_eq!(rect.linear_index(10, 22), 180);
         assert_eq!(rect.linear_index(99, 219), 90 * 200 - 1);
     }
+
+    #[test]
+    fn rectangle_full_range() {
+        let rect = Rectangle::<i32>::new(i32::MIN, -1, i32::MAX, 1);
+        assert_eq!(rect.width(), u32::MAX);
+        assert_eq!(rect.linear_index(i32::MAX - 1, 0), 2 * u32::MAX as usize - 1);
+
+        let tiles: Vec<_> = rect.tile_iter(3, 2).collect();
+        assert_eq!(tiles.len(), 6);
+        assert_eq!((tiles[0].left, tiles[0].top), (i32::MIN, -1));
+        assert_eq!((tiles[5].right, tiles[5].bottom), (i32::MAX, 1));
+        assert_eq!(tiles.iter().map(|tile| tile.width() as u64).sum::<u64>(), 2 * u32::MAX as u64);
+
+        let rect = Rectangle::new(0, 0, u32::MAX, 1);
+        let tiles: Vec<_> = rect.tile_iter(2, 1).collect();
+        assert_eq!(tiles, vec![Rectangle::new(0, 0, u32::MAX / 2, 1), Rectangle::new(u32::MAX / 2, 0, u32::MAX, 1)]);
+        assert_eq!(rect.linear_index(u32::MAX - 1, 0), u32::MAX as usize - 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn rectangle_serde() {
+        let rect = Rectangle::new(10, 20, 100, 220);
+        let data = bincode::serialize(&rect).unwrap();
+
+        let result: Rectangle = bincode::deserialize(&data).unwrap();
+        assert_eq!(result, rect);
+
+        let rect = Rectangle::new(-10, -20, 100, 220);
+        let data = bincode::serialize(&rect).unwrap();
+
+        let result: Rectangle<i32> = bincode::deserialize(&data).unwrap();
+        assert_eq!(result, rect);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn rectangle_serde_invalid() {
+        let data = bincode::serialize(&(10u32, 20u32, 5u32, 220u32)).unwrap();
+        assert!(bincode::deserialize::<Rectangle>(&data).is_err());
+
+        let data = bincode::serialize(&(-10i32, 20i32, -100i32, 220i32)).unwrap();
+        assert!(bincode::deserialize::<Rectangle<i32>>(&data).is_err());
+    }
 }
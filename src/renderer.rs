@@ -17,19 +17,449 @@ use std::ops::{Add, AddAssign, Div, Mul};
 pub use multithreaded::*;
 pub use simple::*;
 
+use crate::color::{Rgb, Rgba, Spectrum};
 use crate::filter::Filter;
 use crate::raster::Raster;
+use crate::rectangle::Coordinate;
 use crate::sampler::{PixelSample, Sampler};
 
 mod multithreaded;
 mod simple;
 
-pub trait RenderFunction: Send + Sync {
+pub trait RenderFunction<C: Coordinate = u32>: Send + Sync {
     type Value: Copy + Default + Add<Output=Self::Value> + AddAssign + Mul<f32, Output=Self::Value> + Div<f32, Output=Self::Value> + Send + Sync;
 
-    fn evaluate(&self, sample: &PixelSample) -> Self::Value;
+    fn evaluate(&self, sample: &PixelSample<C>) -> Self::Value;
+}
+
+/// Value returned by a render function, which can be checked for NaN and infinite values and clamped. Render options can only be applied to render functions
+/// with values that implement this trait.
+pub trait SampleValue {
+    /// Checks if all components of this value are finite.
+    fn is_finite(&self) -> bool;
+
+    /// Returns the largest component of this value.
+    fn max_component(&self) -> f32;
 }
 
 pub trait Renderer {
-    fn render<S: Sampler, R: RenderFunction, F: Filter>(&self, sampler: &S, render_fn: &R, filter: &F) -> Raster<R::Value>;
+    /// Renders the samples of the sampler, adding the values returned by the render function to the raster as they are.
+    fn render<C: Coordinate, S: Sampler<C>, R: RenderFunction<C>, F: Filter>(&self, sampler: &S, render_fn: &R, filter: &F) -> Raster<R::Value, C>;
+
+    /// Renders like `render()`, applying the given options to the sample values, and also returns statistics about the samples that were processed.
+    fn render_with_statistics<C, S, R, F>(&self, sampler: &S, render_fn: &R, filter: &F, options: &RenderOptions) -> (Raster<R::Value, C>, RenderStatistics)
+        where
+            C: Coordinate,
+            S: Sampler<C>,
+            R: RenderFunction<C>,
+            F: Filter,
+            R::Value: SampleValue;
+}
+
+/// Options for processing the sample values returned by the render function.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct RenderOptions {
+    non_finite_samples: NonFiniteSamples,
+    max_sample_value: Option<f32>,
+}
+
+/// How sample values with NaN or infinite components are handled.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum NonFiniteSamples {
+    /// Sample values are not checked; a NaN or infinite value poisons the pixel it contributes to.
+    Ignore,
+    /// Non-finite sample values are logged and counted, and still added to the pixel.
+    Report,
+    /// Non-finite sample values are logged and counted, and not added to the pixel.
+    Discard,
+}
+
+/// Statistics about the samples processed by a renderer.
+#[derive(Copy, Clone, Default, Eq, PartialEq, Debug)]
+pub struct RenderStatistics {
+    sample_count: usize,
+    non_finite_sample_count: usize,
+    discarded_sample_count: usize,
+    clamped_sample_count: usize,
+}
+
+// ===== SampleValue ===========================================================================================================================================
+
+impl SampleValue for f32 {
+    #[inline]
+    fn is_finite(&self) -> bool {
+        f32::is_finite(*self)
+    }
+
+    #[inline]
+    fn max_component(&self) -> f32 {
+        *self
+    }
+}
+
+impl SampleValue for Rgb {
+    #[inline]
+    fn is_finite(&self) -> bool {
+        self.r.is_finite() && self.g.is_finite() && self.b.is_finite()
+    }
+
+    #[inline]
+    fn max_component(&self) -> f32 {
+        Rgb::max_component(self)
+    }
+}
+
+impl SampleValue for Rgba {
+    #[inline]
+    fn is_finite(&self) -> bool {
+        self.r.is_finite() && self.g.is_finite() && self.b.is_finite() && self.a.is_finite()
+    }
+
+    /// Returns the largest color component, not including alpha.
+    #[inline]
+    fn max_component(&self) -> f32 {
+        f32::max(self.r, f32::max(self.g, self.b))
+    }
+}
+
+impl SampleValue for Spectrum {
+    #[inline]
+    fn is_finite(&self) -> bool {
+        self.values().iter().all(|value| value.is_finite())
+    }
+
+    #[inline]
+    fn max_component(&self) -> f32 {
+        self.values().iter().copied().fold(f32::NEG_INFINITY, f32::max)
+    }
+}
+
+// ===== RenderOptions =========================================================================================================================================
+
+impl RenderOptions {
+    /// Maximum number of non-finite sample values that are logged per renderer thread; further values are only counted.
+    const MAX_LOGGED_NON_FINITE_SAMPLES: usize = 10;
+
+    /// Creates render options that report non-finite sample values and do not clamp sample values.
+    #[inline]
+    pub fn new() -> RenderOptions {
+        RenderOptions { non_finite_samples: NonFiniteSamples::Report, max_sample_value: None }
+    }
+
+    /// Returns these options with the given handling of non-finite sample values.
+    #[inline]
+    pub fn with_non_finite_samples(self, non_finite_samples: NonFiniteSamples) -> RenderOptions {
+        RenderOptions { non_finite_samples, ..self }
+    }
+
+    /// Returns these options with a maximum sample value to suppress fireflies. Sample values with a component greater than the maximum are scaled down so
+    /// that their largest component is equal to the maximum, which preserves their hue.
+    #[inline]
+    pub fn with_max_sample_value(self, max_sample_value: f32) -> RenderOptions {
+        RenderOptions { max_sample_value: Some(max_sample_value), ..self }
+    }
+
+    #[inline]
+    pub fn non_finite_samples(&self) -> NonFiniteSamples {
+        self.non_finite_samples
+    }
+
+    #[inline]
+    pub fn max_sample_value(&self) -> Option<f32> {
+        self.max_sample_value
+    }
+
+    /// Evaluates the render function for a sample, checks and clamps the value according to these options and adds the filtered value to the raster, which
+    /// contains weighted sums and weights.
+    pub(crate) fn add_sample<C: Coordinate, R: RenderFunction<C>, F: Filter>(&self, render_fn: &R, filter: &F, sample: &PixelSample<C>,
+                                                                             raster: &mut Raster<(R::Value, f32), C>, statistics: &mut RenderStatistics)
+        where
+            R::Value: SampleValue
+    {
+        statistics.sample_count += 1;
+
+        // Evaluate render function
+        let mut value = render_fn.evaluate(sample);
+
+        let (pixel_x, pixel_y) = sample.pixel();
+        let (sample_x, sample_y) = sample.sample();
+
+        if self.non_finite_samples != NonFiniteSamples::Ignore && !value.is_finite() {
+            statistics.non_finite_sample_count += 1;
+            if statistics.non_finite_sample_count <= RenderOptions::MAX_LOGGED_NON_FINITE_SAMPLES {
+                log::warn!("Non-finite sample value at pixel ({}, {}), sample position ({}, {})", pixel_x, pixel_y, sample_x, sample_y);
+            }
+
+            if self.non_finite_samples == NonFiniteSamples::Discard {
+                statistics.discarded_sample_count += 1;
+                return;
+            }
+        }
+
+        if let Some(max_sample_value) = self.max_sample_value {
+            let max_component = value.max_component();
+            if max_component > max_sample_value {
+                value = value * (max_sample_value / max_component);
+                statistics.clamped_sample_count += 1;
+            }
+        }
+
+        add_filtered_value(filter, sample, value, raster);
+    }
+}
+
+impl Default for RenderOptions {
+    #[inline]
+    fn default() -> RenderOptions {
+        RenderOptions::new()
+    }
+}
+
+// ===== RenderStatistics ======================================================================================================================================
+
+impl RenderStatistics {
+    /// Returns the number of samples for which the render function was evaluated.
+    #[inline]
+    pub fn sample_count(&self) -> usize {
+        self.sample_count
+    }
+
+    /// Returns the number of samples for which the render function returned a NaN or infinite value.
+    #[inline]
+    pub fn non_finite_sample_count(&self) -> usize {
+        self.non_finite_sample_count
+    }
+
+    /// Returns the number of samples that were discarded because their value was not finite.
+    #[inline]
+    pub fn discarded_sample_count(&self) -> usize {
+        self.discarded_sample_count
+    }
+
+    /// Returns the number of samples whose value was clamped to the maximum sample value.
+    #[inline]
+    pub fn clamped_sample_count(&self) -> usize {
+        self.clamped_sample_count
+    }
+}
+
+impl AddAssign for RenderStatistics {
+    #[inline]
+    fn add_assign(&mut self, rhs: RenderStatistics) {
+        self.sample_count += rhs.sample_count;
+        self.non_finite_sample_count += rhs.non_finite_sample_count;
+        self.discarded_sample_count += rhs.discarded_sample_count;
+        self.clamped_sample_count += rhs.clamped_sample_count;
+    }
+}
+
+/// Evaluates the render function for a sample and adds the filtered value to the raster as it is, without applying render options.
+pub(crate) fn add_sample<C: Coordinate, R: RenderFunction<C>, F: Filter>(render_fn: &R, filter: &F, sample: &PixelSample<C>,
+                                                                         raster: &mut Raster<(R::Value, f32), C>, statistics: &mut RenderStatistics) {
+    statistics.sample_count += 1;
+
+    // Evaluate render function
+    let value = render_fn.evaluate(sample);
+
+    add_filtered_value(filter, sample, value, raster);
+}
+
+/// Adds a sample value weighted by the filter to the pixel of the sample in the raster, which contains weighted sums and weights.
+fn add_filtered_value<C: Coordinate, V: Copy + Default + AddAssign + Mul<f32, Output=V>, F: Filter>(filter: &F, sample: &PixelSample<C>, value: V,
+                                                                                                    raster: &mut Raster<(V, f32), C>) {
+    let (pixel_x, pixel_y) = sample.pixel();
+    let (sample_x, sample_y) = sample.sample();
+
+    // Evaluate filter at this pixel's center
+    let (pixel_center_x, pixel_center_y) = (pixel_x.to_i64() as f32 + 0.5, pixel_y.to_i64() as f32 + 0.5);
+    let weight = filter.evaluate(pixel_center_x - sample_x, pixel_center_y - sample_y);
+
+    // Update pixel with weighted value and weight
+    let element = raster.get_mut(pixel_x, pixel_y);
+    element.0 += value * weight;
+    element.1 += weight;
+}
+
+fn log_non_finite_samples(statistics: &RenderStatistics) {
+    if statistics.non_finite_sample_count > 0 {
+        log::warn!("{} of {} samples had non-finite values; {} samples were discarded",
+            statistics.non_finite_sample_count, statistics.sample_count, statistics.discarded_sample_count);
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use crate::filter::BoxFilter;
+    use crate::raster::TileStorage;
+    use crate::rectangle::Rectangle;
+    use crate::sampler::StratifiedSampler;
+
+    use super::*;
+
+    struct TestRenderFunction;
+
+    impl<C: Coordinate> RenderFunction<C> for TestRenderFunction {
+        type Value = f32;
+
+        fn evaluate(&self, sample: &PixelSample<C>) -> f32 {
+            let (x, y) = sample.pixel();
+            match ((x.to_i64(), y.to_i64()), sample.sample_offset()) {
+                ((1, 1), (offset_x, _)) if offset_x < 0.5 => f32::NAN,
+                ((0, 0), _) => 100.0,
+                _ => 1.0,
+            }
+        }
+    }
+
+    fn render<T: Renderer>(renderer: &T, options: &RenderOptions) -> (Raster<f32>, RenderStatistics) {
+        let sampler = StratifiedSampler::new(Rectangle::new(0, 0, 4, 3), 2, false);
+        renderer.render_with_statistics(&sampler, &TestRenderFunction, &BoxFilter::with_defaults(), options)
+    }
+
+    #[test]
+    fn render_options_report_non_finite_samples() {
+        let options = RenderOptions::new();
+        for (raster, statistics) in [render(&SimpleRenderer::new(), &options), render(&MultiThreadedRenderer::new(2, 2), &options)] {
+            assert!(raster.get(1, 1).is_nan());
+            assert_eq!(raster.get(2, 1), 1.0);
+            assert_eq!(statistics.sample_count(), 48);
+            assert_eq!(statistics.non_finite_sample_count(), 2);
+            assert_eq!(statistics.discarded_sample_count(), 0);
+        }
+    }
+
+    #[test]
+    fn render_options_discard_non_finite_samples() {
+        let options = RenderOptions::new().with_non_finite_samples(NonFiniteSamples::Discard);
+        for (raster, statistics) in [render(&SimpleRenderer::new(), &options), render(&MultiThreadedRenderer::new(2, 2), &options)] {
+            assert_eq!(raster.get(1, 1), 1.0);
+            assert_eq!(statistics.non_finite_sample_count(), 2);
+            assert_eq!(statistics.discarded_sample_count(), 2);
+        }
+    }
+
+    #[test]
+    fn render_options_ignore_non_finite_samples() {
+        let options = RenderOptions::new().with_non_finite_samples(NonFiniteSamples::Ignore);
+        let (raster, statistics) = render(&SimpleRenderer::new(), &options);
+        assert!(raster.get(1, 1).is_nan());
+        assert_eq!(statistics.non_finite_sample_count(), 0);
+    }
+
+    #[test]
+    fn render_options_max_sample_value() {
+        let (raster, statistics) = render(&SimpleRenderer::new(), &RenderOptions::new());
+        assert_eq!(raster.get(0, 0), 100.0);
+        assert_eq!(statistics.clamped_sample_count(), 0);
+
+        let options = RenderOptions::new().with_max_sample_value(4.0);
+        let (raster, statistics) = render(&MultiThreadedRenderer::new(2, 2), &options);
+        assert_eq!(raster.get(0, 0), 4.0);
+        assert_eq!(raster.get(3, 2), 1.0);
+        assert_eq!(statistics.clamped_sample_count(), 4);
+    }
+
+    #[test]
+    fn render_sample_values_as_they_are() {
+        // Without options, non-finite values are not discarded and large values are not clamped
+        let sampler = StratifiedSampler::new(Rectangle::new(0, 0, 4, 3), 2, false);
+
+        for raster in [SimpleRenderer::new().render(&sampler, &TestRenderFunction, &BoxFilter::with_defaults()),
+            MultiThreadedRenderer::new(2, 2).render(&sampler, &TestRenderFunction, &BoxFilter::with_defaults())] {
+            assert!(raster.get(1, 1).is_nan());
+            assert_eq!(raster.get(0, 0), 100.0);
+        }
+    }
+
+    #[test]
+    fn render_value_without_sample_value() {
+        // Render functions with values that do not implement SampleValue can be rendered without options
+        #[derive(Copy, Clone, Default, PartialEq, Debug)]
+        struct Value(f32);
+
+        impl Add for Value {
+            type Output = Value;
+
+            fn add(self, rhs: Value) -> Value {
+                Value(self.0 + rhs.0)
+            }
+        }
+
+        impl AddAssign for Value {
+            fn add_assign(&mut self, rhs: Value) {
+                self.0 += rhs.0;
+            }
+        }
+
+        impl Mul<f32> for Value {
+            type Output = Value;
+
+            fn mul(self, rhs: f32) -> Value {
+                Value(self.0 * rhs)
+            }
+        }
+
+        impl Div<f32> for Value {
+            type Output = Value;
+
+            fn div(self, rhs: f32) -> Value {
+                Value(self.0 / rhs)
+            }
+        }
+
+        struct ValueRenderFunction;
+
+        impl RenderFunction for ValueRenderFunction {
+            type Value = Value;
+
+            fn evaluate(&self, _: &PixelSample) -> Value {
+                Value(2.0)
+            }
+        }
+
+        let sampler = StratifiedSampler::new(Rectangle::new(0, 0, 4, 3), 2, false);
+        let raster = SimpleRenderer::new().render(&sampler, &ValueRenderFunction, &BoxFilter::with_defaults());
+        assert_eq!(raster.get(3, 2), Value(2.0));
+    }
+
+    #[test]
+    fn multi_threaded_renderer_render_tiled() {
+        let sampler = StratifiedSampler::new(Rectangle::new(0, 0, 40, 30), 2, true);
+        let options = RenderOptions::new().with_non_finite_samples(NonFiniteSamples::Discard);
+        let renderer = MultiThreadedRenderer::new(2, 4);
+        let storage = TileStorage::with_scratch_dir(8, 3, std::env::temp_dir());
+
+        let (expected, _) = renderer.render_with_statistics(&sampler, &TestRenderFunction, &BoxFilter::with_defaults(), &options);
+        let (mut raster, statistics) = renderer.render_tiled(&sampler, &TestRenderFunction, &BoxFilter::with_defaults(), &options, &storage).unwrap();
+        assert_eq!(statistics.sample_count(), 4800);
+        assert_eq!(raster.to_raster().unwrap().as_slice(), expected.as_slice());
+    }
+
+    #[test]
+    fn multi_threaded_renderer_render_rows() {
+        let sampler = StratifiedSampler::new(Rectangle::<i32>::new(-5, 3, 35, 33), 2, true);
+        let renderer = MultiThreadedRenderer::new(3, 4);
+        let storage = TileStorage::with_scratch_dir(8, 5, std::env::temp_dir());
+
+        let options = RenderOptions::new();
+
+        let (expected, _) = renderer.render_with_statistics(&sampler, &TestRenderFunction, &BoxFilter::with_defaults(), &options);
+        let mut rows = Vec::new();
+        let statistics = renderer.render_rows(&sampler, &TestRenderFunction, &BoxFilter::with_defaults(), &options, &storage, |y, row| {
+            assert_eq!(row, expected.row(y));
+            rows.push(y);
+            Ok(())
+        }).unwrap();
+        assert_eq!(statistics.sample_count(), 40 * 30 * 4);
+        assert_eq!(rows, (3..33).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sample_value_rgb() {
+        assert!(Rgb::new(1.0, 2.0, 3.0).is_finite());
+        assert!(!SampleValue::is_finite(&Rgb::new(1.0, f32::INFINITY, 3.0)));
+        assert_eq!(SampleValue::max_component(&Rgb::new(1.0, 5.0, 3.0)), 5.0);
+    }
 }
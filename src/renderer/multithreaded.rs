@@ -12,16 +12,19 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::io;
 use std::time::Instant;
 
 use crossbeam_channel::{Receiver, Sender};
 use crossbeam_utils::thread;
-use crossbeam_utils::thread::Scope;
+use crossbeam_utils::thread::{Scope, ScopedJoinHandle};
 
 use crate::filter::Filter;
-use crate::raster::Raster;
-use crate::renderer::{Renderer, RenderFunction};
-use crate::sampler::{Sampler, SamplerTile};
+use crate::io::Pixel;
+use crate::raster::{Raster, TiledRaster, TileStorage};
+use crate::rectangle::Coordinate;
+use crate::renderer::{add_sample, log_non_finite_samples, Renderer, RenderFunction, RenderOptions, RenderStatistics, SampleValue};
+use crate::sampler::{PixelSample, Sampler, SamplerTile};
 
 pub struct MultiThreadedRenderer {
     worker_count: usize,
@@ -41,7 +44,184 @@ impl MultiThreadedRenderer {
         MultiThreadedRenderer::new(num_cpus::get(), MultiThreadedRenderer::DEFAULT_TILES_PER_WORKER)
     }
 
-    fn start_sample_generator<'a, S: Sampler>(&self, scope: &Scope<'a>, sampler: &'a S, tile_count_x: u32, tile_count_y: u32, sender: &Sender<S::Tile>) {
+    /// Renders like `render_with_statistics()`, but returns the result as a tiled raster with the given storage, so that images that do not fit in memory can
+    /// be rendered. Use `render_rows()` to write the result to disk without keeping it in a tiled raster.
+    pub fn render_tiled<C, S, R, F>(&self, sampler: &S, render_fn: &R, filter: &F, options: &RenderOptions, storage: &TileStorage)
+                                    -> io::Result<(TiledRaster<R::Value, C>, RenderStatistics)>
+        where
+            C: Coordinate,
+            S: Sampler<C>,
+            R: RenderFunction<C>,
+            F: Filter,
+            R::Value: Pixel + SampleValue
+    {
+        let mut raster = TiledRaster::new(sampler.rectangle().clone(), storage);
+        let statistics = self.render_rows(sampler, render_fn, filter, options, storage, |y, row| {
+            let left = raster.rectangle().left;
+            for (i, &value) in row.iter().enumerate() {
+                raster.set(left.offset(i as i64), y, value)?;
+            }
+            Ok(())
+        })?;
+
+        Ok((raster, statistics))
+    }
+
+    /// Renders like `render_with_statistics()` and calls the row function for each row of the result, from top to bottom, as soon as all samples that
+    /// contribute to the row have been processed; for example, rows can be written directly to disk with `PnmWriter`.
+    ///
+    /// Sample values are accumulated in a tiled raster with the given storage. The sampler tiles have about the same size as the storage tiles, and storage
+    /// tiles are discarded as soon as their rows have been passed to the row function, so that only a few rows of tiles are kept at a time.
+    pub fn render_rows<C, S, R, F, W>(&self, sampler: &S, render_fn: &R, filter: &F, options: &RenderOptions, storage: &TileStorage, mut row_fn: W)
+                                      -> io::Result<RenderStatistics>
+        where
+            C: Coordinate,
+            S: Sampler<C>,
+            R: RenderFunction<C>,
+            F: Filter,
+            R::Value: Pixel + SampleValue,
+            W: FnMut(C, &[R::Value]) -> io::Result<()>
+    {
+        let start_time = Instant::now();
+
+        let rectangle = sampler.rectangle().clone();
+        let tile_size = storage.tile_size();
+        let (tile_count_x, tile_count_y) = rectangle.fixed_tile_count(tile_size, tile_size);
+
+        // Number of pixels in each row of storage tiles for which the samples have not been received yet
+        let mut remaining_pixels: Vec<usize> = (0..tile_count_y).map(|tile_y| rectangle.fixed_tile(0, tile_y, rectangle.width(), tile_size).size()).collect();
+        let mut finished_tile_rows = 0;
+
+        let mut raster = TiledRaster::new(rectangle.clone(), storage);
+        let mut row = Vec::with_capacity(rectangle.width() as usize);
+
+        let add_sample_fn = |sample: &PixelSample<C>, raster: &mut Raster<(R::Value, f32), C>, statistics: &mut RenderStatistics| {
+            options.add_sample(render_fn, filter, sample, raster, statistics);
+        };
+
+        // Receive rendered tile rasters from workers and aggregate into the tiled raster; rows of tiles for which all tile rasters have been received are
+        // converted and passed to the row function in order. After an error, the remaining tiles are received but ignored.
+        let mut result: io::Result<()> = Ok(());
+        let output_channel_capacity = 2 * self.worker_count;
+        let statistics = self.render_tiles::<C, S, R, _, _>(sampler, (tile_count_x, tile_count_y), output_channel_capacity, &add_sample_fn, |tile_raster| {
+            if result.is_err() {
+                return;
+            }
+
+            result = (|| {
+                raster.merge(tile_raster, |(raster_value, raster_weight): (R::Value, f32), (tile_value, tile_weight): (R::Value, f32)| {
+                    (raster_value + tile_value, raster_weight + tile_weight)
+                })?;
+
+                for (tile_y, remaining) in remaining_pixels.iter_mut().enumerate() {
+                    let tile_row_rectangle = rectangle.fixed_tile(0, tile_y as u32, rectangle.width(), tile_size);
+                    if let Some(intersection) = tile_row_rectangle.intersection(tile_raster.rectangle()) {
+                        *remaining -= intersection.size();
+                    }
+                }
+
+                while finished_tile_rows < remaining_pixels.len() && remaining_pixels[finished_tile_rows] == 0 {
+                    let tile_row_rectangle = rectangle.fixed_tile(0, finished_tile_rows as u32, rectangle.width(), tile_size);
+
+                    // Convert weighted rows to final result
+                    raster.for_each_row_in(tile_row_rectangle.top..tile_row_rectangle.bottom, |y, weighted_row| {
+                        row.clear();
+                        row.extend(weighted_row.iter().map(|&(value, weight)| if weight != 0.0 { value / weight } else { R::Value::default() }));
+                        row_fn(y, &row)
+                    })?;
+
+                    raster.discard_rows_above(tile_row_rectangle.bottom);
+                    finished_tile_rows += 1;
+                }
+
+                Ok(())
+            })();
+        });
+        result?;
+
+        let duration = Instant::now().duration_since(start_time).as_millis();
+        log::info!("Rendering finished, run time: {} ms", duration);
+
+        Ok(statistics)
+    }
+
+    /// Renders the tiles of the sampler and aggregates them into a raster in memory.
+    fn render_in_memory<C, S, R, P>(&self, sampler: &S, add_sample_fn: &P) -> (Raster<R::Value, C>, RenderStatistics)
+        where
+            C: Coordinate,
+            S: Sampler<C>,
+            R: RenderFunction<C>,
+            P: Fn(&PixelSample<C>, &mut Raster<(R::Value, f32), C>, &mut RenderStatistics) + Sync
+    {
+        let start_time = Instant::now();
+
+        let tile_count = self.worker_count * self.tiles_per_worker;
+        let tile_count_dim = (tile_count as f32).sqrt().round() as u32;
+
+        // Receive rendered tile rasters from workers and aggregate into output raster
+        const OUTPUT_CHANNEL_CAPACITY: usize = 2048;
+        let mut raster = Raster::new(sampler.rectangle().clone());
+        let statistics = self.render_tiles::<C, S, R, _, _>(sampler, (tile_count_dim, tile_count_dim), OUTPUT_CHANNEL_CAPACITY, add_sample_fn, |tile_raster| {
+            raster.merge(tile_raster, |(raster_value, raster_weight): (R::Value, f32), (tile_value, tile_weight): (R::Value, f32)| {
+                (raster_value + tile_value, raster_weight + tile_weight)
+            });
+        });
+
+        // Convert weighted raster to final result
+        log::info!("Converting raster");
+        let raster = raster.map_parallel(|(value, weight): (R::Value, f32)| { if weight != 0.0 { value / weight } else { R::Value::default() } });
+
+        let duration = Instant::now().duration_since(start_time).as_millis();
+        log::info!("Rendering finished, run time: {} ms", duration);
+
+        (raster, statistics)
+    }
+
+    /// Renders the tiles of the sampler on the worker threads and calls the aggregate function on the main thread for each rendered tile raster, which
+    /// contains weighted sums of sample values and weights. The workers call the add sample function to evaluate each sample and add it to the tile raster.
+    ///
+    /// The output channel capacity limits the number of rendered tile rasters that are waiting to be aggregated.
+    fn render_tiles<C, S, R, P, A>(&self, sampler: &S, (tile_count_x, tile_count_y): (u32, u32), output_channel_capacity: usize, add_sample_fn: &P,
+                                   mut aggregate_fn: A) -> RenderStatistics
+        where
+            C: Coordinate,
+            S: Sampler<C>,
+            R: RenderFunction<C>,
+            P: Fn(&PixelSample<C>, &mut Raster<(R::Value, f32), C>, &mut RenderStatistics) + Sync,
+            A: FnMut(&Raster<(R::Value, f32), C>)
+    {
+        // Create channels
+        const INPUT_CHANNEL_CAPACITY: usize = 2048;
+        let (input_snd, input_rcv) = crossbeam_channel::bounded(INPUT_CHANNEL_CAPACITY);
+        let (output_snd, output_rcv) = crossbeam_channel::bounded(output_channel_capacity);
+
+        thread::scope(|scope| {
+            // Start sample generator and worker threads
+            self.start_sample_generator::<C, S>(scope, sampler, tile_count_x, tile_count_y, &input_snd);
+            let workers = self.start_workers::<C, S, R, P>(scope, add_sample_fn, &input_rcv, &output_snd);
+
+            // Disconnect channels used by sample generator and worker threads from the main thread
+            drop(input_snd);
+            drop(input_rcv);
+            drop(output_snd);
+
+            log::info!("Aggregating results");
+            for tile_raster in output_rcv {
+                aggregate_fn(&tile_raster);
+            }
+
+            let mut statistics = RenderStatistics::default();
+            for worker in workers {
+                statistics += worker.join().unwrap();
+            }
+            log_non_finite_samples(&statistics);
+
+            statistics
+        }).unwrap()
+    }
+
+    fn start_sample_generator<'a, C: Coordinate, S: Sampler<C>>(&self, scope: &Scope<'a>, sampler: &'a S, tile_count_x: u32, tile_count_y: u32,
+                                                                sender: &Sender<S::Tile>) {
         let sender = sender.clone();
 
         scope.spawn(move |_| {
@@ -59,13 +239,18 @@ impl MultiThreadedRenderer {
         });
     }
 
-    fn start_workers<'a, S: Sampler, R: RenderFunction, F: Filter>(
-        &self, scope: &Scope<'a>, render_fn: &'a R, filter: &'a F, receiver: &Receiver<S::Tile>, sender: &Sender<Raster<(R::Value, f32)>>)
+    fn start_workers<'s, 'a, C, S, R, P>(&self, scope: &'s Scope<'a>, add_sample_fn: &'a P, receiver: &Receiver<S::Tile>,
+                                         sender: &Sender<Raster<(R::Value, f32), C>>) -> Vec<ScopedJoinHandle<'s, RenderStatistics>>
         where
-            <S as Sampler>::Tile: 'a
+            C: Coordinate,
+            S: Sampler<C>,
+            R: RenderFunction<C>,
+            P: Fn(&PixelSample<C>, &mut Raster<(R::Value, f32), C>, &mut RenderStatistics) + Sync,
+            <S as Sampler<C>>::Tile: 'a,
+            R::Value: 'a
     {
         log::info!("Starting {} worker threads", self.worker_count);
-        for id in 1..=self.worker_count {
+        (1..=self.worker_count).map(|id| {
             let receiver = receiver.clone();
             let sender = sender.clone();
 
@@ -74,82 +259,48 @@ impl MultiThreadedRenderer {
                 let start_time = Instant::now();
 
                 let mut tile_count = 0;
-                let mut sample_count = 0usize;
+                let mut statistics = RenderStatistics::default();
                 for tile in receiver {
                     tile_count += 1;
 
-                    let mut tile_raster = Raster::<(R::Value, f32)>::new(tile.rectangle().clone());
+                    let mut tile_raster = Raster::<(R::Value, f32), C>::new(tile.rectangle().clone());
 
                     // For all samples in this tile, render and update the raster using the filter
                     for sample in tile {
-                        sample_count += 1;
-
-                        // Evaluate render function
-                        let value = render_fn.evaluate(&sample);
-
-                        let (pixel_x, pixel_y) = sample.pixel();
-                        let (sample_x, sample_y) = sample.sample();
-
-                        // Evaluate filter at this pixel's center
-                        let (pixel_center_x, pixel_center_y) = (pixel_x as f32 + 0.5, pixel_y as f32 + 0.5);
-                        let weight = filter.evaluate(pixel_center_x - sample_x, pixel_center_y - sample_y);
-
-                        // Update pixel with weighted value and weight
-                        let element = tile_raster.get_mut(pixel_x, pixel_y);
-                        element.0 += value * weight;
-                        element.1 += weight;
+                        add_sample_fn(&sample, &mut tile_raster, &mut statistics);
                     }
 
                     sender.send(tile_raster).unwrap();
                 }
 
                 let duration = Instant::now().duration_since(start_time).as_millis();
-                log::info!("[{:02}] Worker thread finished, processed {} tiles; {} samples, run time: {} ms", id, tile_count, sample_count, duration);
-            });
-        }
+                log::info!("[{:02}] Worker thread finished, processed {} tiles; {} samples, run time: {} ms", id, tile_count, statistics.sample_count(), duration);
+
+                statistics
+            })
+        }).collect()
     }
 }
 
 impl Renderer for MultiThreadedRenderer {
-    fn render<S: Sampler, R: RenderFunction, F: Filter>(&self, sampler: &S, render_fn: &R, filter: &F) -> Raster<R::Value> {
-        // Create channels
-        const INPUT_CHANNEL_CAPACITY: usize = 2048;
-        const OUTPUT_CHANNEL_CAPACITY: usize = 2048;
-        let (input_snd, input_rcv) = crossbeam_channel::bounded(INPUT_CHANNEL_CAPACITY);
-        let (output_snd, output_rcv) = crossbeam_channel::bounded(OUTPUT_CHANNEL_CAPACITY);
-
-        thread::scope(|scope| {
-            let start_time = Instant::now();
-
-            let tile_count = self.worker_count * self.tiles_per_worker;
-            let tile_count_dim = (tile_count as f32).sqrt().round() as u32;
-
-            // Start sample generator and worker threads
-            self.start_sample_generator(scope, sampler, tile_count_dim, tile_count_dim, &input_snd);
-            self.start_workers::<S, R, F>(scope, render_fn, filter, &input_rcv, &output_snd);
-
-            // Disconnect channels used by sample generator and worker threads from the main thread
-            drop(input_snd);
-            drop(input_rcv);
-            drop(output_snd);
-
-            // Receive rendered tile rasters from workers and aggregate into output raster
-            log::info!("Aggregating results");
-            let mut raster = Raster::new(sampler.rectangle().clone());
-            for tile_raster in output_rcv {
-                raster.merge(&tile_raster, |(raster_value, raster_weight): (R::Value, f32), (tile_value, tile_weight): (R::Value, f32)| {
-                    (raster_value + tile_value, raster_weight + tile_weight)
-                });
-            }
-
-            // Convert weighted raster to final result
-            log::info!("Converting raster");
-            let raster = raster.map(|(value, weight): (R::Value, f32)| { if weight != 0.0 { value / weight } else { R::Value::default() } });
-
-            let duration = Instant::now().duration_since(start_time).as_millis();
-            log::info!("Rendering finished, run time: {} ms", duration);
+    fn render<C: Coordinate, S: Sampler<C>, R: RenderFunction<C>, F: Filter>(&self, sampler: &S, render_fn: &R, filter: &F) -> Raster<R::Value, C> {
+        let add_sample_fn = |sample: &PixelSample<C>, raster: &mut Raster<(R::Value, f32), C>, statistics: &mut RenderStatistics| {
+            add_sample(render_fn, filter, sample, raster, statistics);
+        };
+        self.render_in_memory::<C, S, R, _>(sampler, &add_sample_fn).0
+    }
 
-            raster
-        }).unwrap()
+    fn render_with_statistics<C, S, R, F>(&self, sampler: &S, render_fn: &R, filter: &F, options: &RenderOptions) -> (Raster<R::Value, C>, RenderStatistics)
+        where
+            C: Coordinate,
+            S: Sampler<C>,
+            R: RenderFunction<C>,
+            F: Filter,
+            R::Value: SampleValue
+    {
+        let add_sample_fn = |sample: &PixelSample<C>, raster: &mut Raster<(R::Value, f32), C>, statistics: &mut RenderStatistics| {
+            options.add_sample(render_fn, filter, sample, raster, statistics);
+        };
+        self.render_in_memory::<C, S, R, _>(sampler, &add_sample_fn)
     }
 }
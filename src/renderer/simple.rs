@@ -16,8 +16,9 @@ use std::time::Instant;
 
 use crate::filter::Filter;
 use crate::raster::Raster;
-use crate::renderer::{Renderer, RenderFunction};
-use crate::sampler::Sampler;
+use crate::rectangle::Coordinate;
+use crate::renderer::{add_sample, log_non_finite_samples, Renderer, RenderFunction, RenderOptions, RenderStatistics, SampleValue};
+use crate::sampler::{PixelSample, Sampler};
 
 pub struct SimpleRenderer {}
 
@@ -30,44 +31,61 @@ impl SimpleRenderer {
     }
 }
 
-impl Renderer for SimpleRenderer {
-    fn render<S: Sampler, R: RenderFunction, F: Filter>(&self, sampler: &S, render_fn: &R, filter: &F) -> Raster<R::Value> {
-        let mut raster = Raster::<(R::Value, f32)>::new(sampler.rectangle().clone());
-
-        log::info!("Start rendering");
-        let start_time = Instant::now();
-
-        let mut sample_count = 0usize;
-        for tile in sampler.tiles(1, 1) {
-            for sample in tile {
-                sample_count += 1;
-
-                let value = render_fn.evaluate(&sample);
+impl Default for SimpleRenderer {
+    #[inline]
+    fn default() -> SimpleRenderer {
+        SimpleRenderer::new()
+    }
+}
 
-                let (pixel_x, pixel_y) = sample.pixel();
-                let (sample_x, sample_y) = sample.sample();
+impl Renderer for SimpleRenderer {
+    fn render<C: Coordinate, S: Sampler<C>, R: RenderFunction<C>, F: Filter>(&self, sampler: &S, render_fn: &R, filter: &F) -> Raster<R::Value, C> {
+        render_samples::<C, S, R, _>(sampler, |sample, raster, statistics| add_sample(render_fn, filter, sample, raster, statistics)).0
+    }
 
-                // Evaluate filter at this pixel's center
-                let (pixel_center_x, pixel_center_y) = (pixel_x as f32 + 0.5, pixel_y as f32 + 0.5);
-                let weight = filter.evaluate(pixel_center_x - sample_x, pixel_center_y - sample_y);
+    fn render_with_statistics<C, S, R, F>(&self, sampler: &S, render_fn: &R, filter: &F, options: &RenderOptions) -> (Raster<R::Value, C>, RenderStatistics)
+        where
+            C: Coordinate,
+            S: Sampler<C>,
+            R: RenderFunction<C>,
+            F: Filter,
+            R::Value: SampleValue
+    {
+        render_samples::<C, S, R, _>(sampler, |sample, raster, statistics| options.add_sample(render_fn, filter, sample, raster, statistics))
+    }
+}
 
-                // Update pixel with weighted value and weight
-                let element = raster.get_mut(pixel_x, pixel_y);
-                element.0 += value * weight;
-                element.1 += weight;
-            }
+/// Renders all samples of the sampler, calling the add sample function to evaluate each sample and add it to the raster, which contains weighted sums of
+/// sample values and weights.
+fn render_samples<C, S, R, A>(sampler: &S, mut add_sample_fn: A) -> (Raster<R::Value, C>, RenderStatistics)
+    where
+        C: Coordinate,
+        S: Sampler<C>,
+        R: RenderFunction<C>,
+        A: FnMut(&PixelSample<C>, &mut Raster<(R::Value, f32), C>, &mut RenderStatistics)
+{
+    let mut raster = Raster::<(R::Value, f32), C>::new(sampler.rectangle().clone());
+
+    log::info!("Start rendering");
+    let start_time = Instant::now();
+
+    let mut statistics = RenderStatistics::default();
+    for tile in sampler.tiles(1, 1) {
+        for sample in tile {
+            add_sample_fn(&sample, &mut raster, &mut statistics);
         }
+    }
 
-        let duration = Instant::now().duration_since(start_time).as_millis();
-        log::info!("Rendering finished, processed {} samples; run time: {} ms", sample_count, duration);
+    let duration = Instant::now().duration_since(start_time).as_millis();
+    log::info!("Rendering finished, processed {} samples; run time: {} ms", statistics.sample_count(), duration);
+    log_non_finite_samples(&statistics);
 
-        // Convert weighted raster to final result
-        log::info!("Converting raster");
-        let raster = raster.map(|(value, weight): (R::Value, f32)| { if weight != 0.0 { value / weight } else { R::Value::default() } });
+    // Convert weighted raster to final result
+    log::info!("Converting raster");
+    let raster = raster.map(|(value, weight): (R::Value, f32)| { if weight != 0.0 { value / weight } else { R::Value::default() } });
 
-        let duration = Instant::now().duration_since(start_time).as_millis();
-        log::info!("Rendering finished, run time: {} ms", duration);
+    let duration = Instant::now().duration_since(start_time).as_millis();
+    log::info!("Rendering finished, run time: {} ms", duration);
 
-        raster
-    }
+    (raster, statistics)
 }
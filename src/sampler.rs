@@ -12,45 +12,64 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub use blue_noise::*;
+pub use cmj::*;
 pub use independent::*;
+pub use pmj02::*;
+pub use sobol::*;
 pub use stratified::*;
 
-use crate::rectangle::Rectangle;
+use crate::rectangle::{Coordinate, Rectangle};
 
+mod blue_noise;
+mod cmj;
 mod independent;
+mod pmj02;
+mod sobol;
 mod stratified;
 
 #[derive(Clone, PartialEq, Debug)]
-pub struct PixelSample {
-    pixel_x: u32,
-    pixel_y: u32,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PixelSample<C: Coordinate = u32> {
+    pixel_x: C,
+    pixel_y: C,
     sample_offset_x: f32,
     sample_offset_y: f32,
+    wavelength_sample: f32,
 }
 
-pub trait Sampler: Send + Sync {
-    type Tile: SamplerTile;
+/// Largest `f32` value that is less than one, to keep sample offsets in the range 0..1.
+pub(crate) const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+pub trait Sampler<C: Coordinate = u32>: Send + Sync {
+    type Tile: SamplerTile<C>;
     type TileIter: Iterator<Item=Self::Tile>;
 
-    fn rectangle(&self) -> &Rectangle;
+    fn rectangle(&self) -> &Rectangle<C>;
 
     fn tiles(&self, tile_count_x: u32, tile_count_y: u32) -> Self::TileIter;
 }
 
-pub trait SamplerTile: Iterator<Item=PixelSample> + Send + Sync {
-    fn rectangle(&self) -> &Rectangle;
+pub trait SamplerTile<C: Coordinate = u32>: Iterator<Item=PixelSample<C>> + Send + Sync {
+    fn rectangle(&self) -> &Rectangle<C>;
 }
 
 // ===== PixelSample ===========================================================================================================================================
 
-impl PixelSample {
+impl<C: Coordinate> PixelSample<C> {
+    #[inline]
+    pub fn new(pixel_x: C, pixel_y: C, sample_offset_x: f32, sample_offset_y: f32) -> PixelSample<C> {
+        PixelSample { pixel_x, pixel_y, sample_offset_x, sample_offset_y, wavelength_sample: 0.5 }
+    }
+
+    /// Returns this pixel sample with the given sample value in the range 0..1 for selecting wavelengths.
     #[inline]
-    pub fn new(pixel_x: u32, pixel_y: u32, sample_offset_x: f32, sample_offset_y: f32) -> PixelSample {
-        PixelSample { pixel_x, pixel_y, sample_offset_x, sample_offset_y }
+    pub fn with_wavelength_sample(self, wavelength_sample: f32) -> PixelSample<C> {
+        PixelSample { wavelength_sample, ..self }
     }
 
     #[inline]
-    pub fn pixel(&self) -> (u32, u32) {
+    pub fn pixel(&self) -> (C, C) {
         (self.pixel_x, self.pixel_y)
     }
 
@@ -61,8 +80,82 @@ impl PixelSample {
 
     #[inline]
     pub fn sample(&self) -> (f32, f32) {
-        (self.pixel_x as f32 + self.sample_offset_x, self.pixel_y as f32 + self.sample_offset_y)
+        (self.pixel_x.to_i64() as f32 + self.sample_offset_x, self.pixel_y.to_i64() as f32 + self.sample_offset_y)
+    }
+
+    /// Returns the sample value in the range 0..1 for selecting wavelengths, for example with `SampledWavelengths::sample()`.
+    #[inline]
+    pub fn wavelength_sample(&self) -> f32 {
+        self.wavelength_sample
+    }
+}
+
+/// Returns a hash of a pixel position, used to select a different sample pattern for each pixel.
+pub(crate) fn hash_pixel<C: Coordinate>(x: C, y: C) -> u32 {
+    let mut h = (x.to_i64() as u32).wrapping_mul(0x8da6_b343) ^ (y.to_i64() as u32).wrapping_mul(0xd816_3841);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb_352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846c_a68b);
+    h ^ (h >> 16)
+}
+
+/// Returns the element at `index` of a random permutation of 0..`length`, selected by `pattern`, computed with a hash function that is a bijection on the
+/// smallest power of two that is not less than the length, by cycle walking.
+pub(crate) fn permute(index: u32, length: u32, pattern: u32) -> u32 {
+    assert!(length > 0, "length of permutation must be greater than zero");
+
+    let mut w = length - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    let mut i = index;
+    loop {
+        i ^= pattern;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= pattern >> 16;
+        i ^= (i & w) >> 4;
+        i ^= pattern >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= pattern >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | pattern >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+
+        if i < length {
+            break;
+        }
     }
+
+    i.wrapping_add(pattern) % length
+}
+
+/// Returns a random value in the range 0..1 for the given index, selected by `pattern`.
+pub(crate) fn random_float(index: u32, pattern: u32) -> f32 {
+    let mut i = index;
+    i ^= pattern;
+    i ^= i >> 17;
+    i ^= i >> 10;
+    i = i.wrapping_mul(0xb365_34e5);
+    i ^= i >> 12;
+    i ^= i >> 21;
+    i = i.wrapping_mul(0x93fc_4795);
+    i ^= 0xdf6e_307f;
+    i ^= i >> 17;
+    i = i.wrapping_mul(1 | pattern >> 18);
+
+    i as f32 * (1.0 / 4_294_967_808.0)
 }
 
 // ===== Tests =================================================================================================================================================
@@ -71,5 +164,61 @@ impl PixelSample {
 mod test {
     use super::*;
 
-// TODO: Write tests.
+    #[test]
+    fn pixel_sample_pixel() {
+        let sample = PixelSample::new(10, 20, 0.25, 0.75);
+        assert_eq!(sample.pixel(), (10, 20));
+    }
+
+    #[test]
+    fn pixel_sample_sample_offset() {
+        let sample = PixelSample::new(10, 20, 0.25, 0.75);
+        assert_eq!(sample.sample_offset(), (0.25, 0.75));
+    }
+
+    #[test]
+    fn pixel_sample_sample() {
+        let sample = PixelSample::new(10, 20, 0.25, 0.75);
+        assert_eq!(sample.sample(), (10.25, 20.75));
+    }
+
+    #[test]
+    fn pixel_sample_wavelength_sample() {
+        let sample = PixelSample::new(10, 20, 0.25, 0.75);
+        assert_eq!(sample.wavelength_sample(), 0.5);
+
+        let sample = sample.with_wavelength_sample(0.125);
+        assert_eq!(sample.wavelength_sample(), 0.125);
+        assert_eq!(sample.pixel(), (10, 20));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn pixel_sample_serde() {
+        let sample = PixelSample::new(10, 20, 0.25, 0.75).with_wavelength_sample(0.125);
+        let data = bincode::serialize(&sample).unwrap();
+        assert_eq!(bincode::deserialize::<PixelSample>(&data).unwrap(), sample);
+    }
+
+    #[test]
+    fn permute_is_permutation() {
+        for length in [1, 3, 8, 100] {
+            let mut values: Vec<u32> = (0..length).map(|i| permute(i, length, 0x1234_5678)).collect();
+            values.sort_unstable();
+            assert_eq!(values, (0..length).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn permute_single_element() {
+        for pattern in [0, 1, 0x1234_5678, u32::MAX] {
+            assert_eq!(permute(0, 1, pattern), 0);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "length of permutation")]
+    fn permute_zero_length() {
+        permute(0, 0, 0x1234_5678);
+    }
 }
@@ -0,0 +1,417 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::iter::FusedIterator;
+use std::sync::OnceLock;
+
+use rand::Rng;
+use rand_xoshiro::rand_core::SeedableRng;
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+use crate::rectangle::{Coordinate, PixelOrder, PixelOrderIterator, Rectangle, RectangleTileIterator};
+use crate::sampler::{ONE_MINUS_EPSILON, PixelSample, Sampler, SamplerTile};
+
+/// Sampler that distributes the sample offsets of neighbouring pixels as blue noise, which looks less noisy than white noise at low sample counts.
+///
+/// Each pixel has a random rotation taken from tileable blue noise masks, which is applied to a low-discrepancy sequence (the R2 sequence) that places the
+/// samples within the pixel. The masks repeat every 64 pixels.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "BlueNoiseSamplerData<C>"))]
+pub struct BlueNoiseSampler<C: Coordinate = u32> {
+    rectangle: Rectangle<C>,
+    samples_per_pixel: u32,
+    pixel_order: PixelOrder,
+}
+
+#[derive(Clone, Debug)]
+pub struct BlueNoiseSamplerTileIterator<C: Coordinate = u32> {
+    rect_iter: RectangleTileIterator<C>,
+    samples_per_pixel: u32,
+    pixel_order: PixelOrder,
+}
+
+#[derive(Clone, Debug)]
+pub struct BlueNoiseSamplerTile<C: Coordinate = u32> {
+    tile_rect: Rectangle<C>,
+    tile_rect_iter: PixelOrderIterator<C>,
+    samples_per_pixel: u32,
+
+    pixel_sample_count: u32,
+    pixel_x: C,
+    pixel_y: C,
+    rotation: [f64; 3],
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct BlueNoiseSamplerData<C: Coordinate> {
+    rectangle: Rectangle<C>,
+    samples_per_pixel: u32,
+    pixel_order: PixelOrder,
+}
+
+/// Size of the blue noise masks.
+pub(crate) const BLUE_NOISE_SIZE: u32 = 64;
+
+// Generators of the R2 sequence and the golden ratio sequence
+const R2_ALPHA_X: f64 = 0.754_877_666_246_692_7;
+const R2_ALPHA_Y: f64 = 0.569_840_290_998_053_2;
+const GOLDEN_ALPHA: f64 = 0.618_033_988_749_894_8;
+
+// ===== BlueNoiseSampler ======================================================================================================================================
+
+impl<C: Coordinate> BlueNoiseSampler<C> {
+    #[inline]
+    pub fn new(rectangle: Rectangle<C>, samples_per_pixel: u32) -> BlueNoiseSampler<C> {
+        assert!(samples_per_pixel > 0, "samples per pixel must be greater than zero");
+        BlueNoiseSampler { rectangle, samples_per_pixel, pixel_order: PixelOrder::Scanline }
+    }
+
+    /// Returns this sampler with the given order in which the pixels within each tile are visited.
+    #[inline]
+    pub fn with_pixel_order(self, pixel_order: PixelOrder) -> BlueNoiseSampler<C> {
+        BlueNoiseSampler { pixel_order, ..self }
+    }
+
+    #[inline]
+    pub fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    #[inline]
+    pub fn pixel_order(&self) -> PixelOrder {
+        self.pixel_order
+    }
+}
+
+impl<C: Coordinate> Sampler<C> for BlueNoiseSampler<C> {
+    type Tile = BlueNoiseSamplerTile<C>;
+    type TileIter = BlueNoiseSamplerTileIterator<C>;
+
+    #[inline]
+    fn rectangle(&self) -> &Rectangle<C> {
+        &self.rectangle
+    }
+
+    #[inline]
+    fn tiles(&self, tile_count_x: u32, tile_count_y: u32) -> BlueNoiseSamplerTileIterator<C> {
+        BlueNoiseSamplerTileIterator::new(self.rectangle(), self.samples_per_pixel, tile_count_x, tile_count_y, self.pixel_order)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<C: Coordinate> TryFrom<BlueNoiseSamplerData<C>> for BlueNoiseSampler<C> {
+    type Error = String;
+
+    fn try_from(data: BlueNoiseSamplerData<C>) -> Result<BlueNoiseSampler<C>, String> {
+        if data.samples_per_pixel > 0 {
+            Ok(BlueNoiseSampler { rectangle: data.rectangle, samples_per_pixel: data.samples_per_pixel, pixel_order: data.pixel_order })
+        } else {
+            Err("samples per pixel must be greater than zero".to_string())
+        }
+    }
+}
+
+// ===== BlueNoiseSamplerTileIterator ==========================================================================================================================
+
+impl<C: Coordinate> BlueNoiseSamplerTileIterator<C> {
+    #[inline]
+    fn new(sampler_rect: &Rectangle<C>, samples_per_pixel: u32, tile_count_x: u32, tile_count_y: u32, pixel_order: PixelOrder) -> BlueNoiseSamplerTileIterator<C> {
+        BlueNoiseSamplerTileIterator { rect_iter: sampler_rect.tile_iter(tile_count_x, tile_count_y), samples_per_pixel, pixel_order }
+    }
+}
+
+impl<C: Coordinate> Iterator for BlueNoiseSamplerTileIterator<C> {
+    type Item = BlueNoiseSamplerTile<C>;
+
+    fn next(&mut self) -> Option<BlueNoiseSamplerTile<C>> {
+        self.rect_iter.next().map(|tile| BlueNoiseSamplerTile::new(tile, self.samples_per_pixel, self.pixel_order))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rect_iter.size_hint()
+    }
+}
+
+impl<C: Coordinate> ExactSizeIterator for BlueNoiseSamplerTileIterator<C> {}
+
+impl<C: Coordinate> FusedIterator for BlueNoiseSamplerTileIterator<C> {}
+
+// ===== BlueNoiseSamplerTile ==================================================================================================================================
+
+impl<C: Coordinate> BlueNoiseSamplerTile<C> {
+    fn new(tile_rect: Rectangle<C>, samples_per_pixel: u32, pixel_order: PixelOrder) -> BlueNoiseSamplerTile<C> {
+        let tile_rect_iter = tile_rect.pixel_order_iter(pixel_order);
+        let (pixel_x, pixel_y) = (tile_rect.left, tile_rect.top);
+
+        BlueNoiseSamplerTile {
+            tile_rect,
+            tile_rect_iter,
+            samples_per_pixel,
+
+            pixel_sample_count: samples_per_pixel, // So that the first time, we advance to the first pixel
+            pixel_x,
+            pixel_y,
+            rotation: [0.0; 3],
+        }
+    }
+}
+
+impl<C: Coordinate> SamplerTile<C> for BlueNoiseSamplerTile<C> {
+    #[inline]
+    fn rectangle(&self) -> &Rectangle<C> {
+        &self.tile_rect
+    }
+}
+
+impl<C: Coordinate> Iterator for BlueNoiseSamplerTile<C> {
+    type Item = PixelSample<C>;
+
+    fn next(&mut self) -> Option<PixelSample<C>> {
+        if self.pixel_sample_count >= self.samples_per_pixel {
+            if let Some((px, py)) = self.tile_rect_iter.next() {
+                // Advance to the next pixel in the tile and look up its rotation in the blue noise masks
+                self.pixel_sample_count = 0;
+                self.pixel_x = px;
+                self.pixel_y = py;
+
+                let size = BLUE_NOISE_SIZE as i64;
+                let index = (py.to_i64().rem_euclid(size) * size + px.to_i64().rem_euclid(size)) as usize;
+                self.rotation = [0, 1, 2].map(|mask| blue_noise_mask(mask)[index] as f64);
+            } else {
+                // No more pixels
+                return None;
+            }
+        }
+
+        // Generate the next sample for the current pixel
+        let index = self.pixel_sample_count as f64;
+        self.pixel_sample_count += 1;
+
+        let rotate = |value: f64, rotation: f64| ((value + rotation).fract() as f32).min(ONE_MINUS_EPSILON);
+        let sample_offset_x = rotate(0.5 + index * R2_ALPHA_X, self.rotation[0]);
+        let sample_offset_y = rotate(0.5 + index * R2_ALPHA_Y, self.rotation[1]);
+        let wavelength_sample = rotate(index * GOLDEN_ALPHA, self.rotation[2]);
+
+        Some(PixelSample::new(self.pixel_x, self.pixel_y, sample_offset_x, sample_offset_y).with_wavelength_sample(wavelength_sample))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (pixels_remaining, _) = self.tile_rect_iter.size_hint();
+        let remaining = pixels_remaining * self.samples_per_pixel as usize + (self.samples_per_pixel - self.pixel_sample_count) as usize;
+
+        (remaining, Some(remaining))
+    }
+}
+
+impl<C: Coordinate> ExactSizeIterator for BlueNoiseSamplerTile<C> {}
+
+impl<C: Coordinate> FusedIterator for BlueNoiseSamplerTile<C> {}
+
+// ===== Blue noise masks ======================================================================================================================================
+
+/// Returns one of three independent, tileable blue noise masks of `BLUE_NOISE_SIZE` x `BLUE_NOISE_SIZE` values in the range 0..1, generated with the
+/// void-and-cluster method by Ulichney (1993). Each value occurs exactly once in a mask.
+pub(crate) fn blue_noise_mask(mask: usize) -> &'static [f32] {
+    const SEEDS: [u64; 3] = [0x5eed, 0x5eed_0001, 0x5eed_0002];
+    static MASKS: [OnceLock<Vec<f32>>; 3] = [OnceLock::new(), OnceLock::new(), OnceLock::new()];
+
+    MASKS[mask].get_or_init(|| {
+        let ranks = void_and_cluster(BLUE_NOISE_SIZE as usize, 1.5, SEEDS[mask]);
+        let count = ranks.len() as f32;
+        ranks.into_iter().map(|rank| (rank as f32 + 0.5) / count).collect()
+    })
+}
+
+/// Computes a blue noise dither array of `size` x `size` ranks with the void-and-cluster method.
+fn void_and_cluster(size: usize, sigma: f32, seed: u64) -> Vec<usize> {
+    let count = size * size;
+
+    // Energy contribution of a point to every other point on a torus
+    let kernel: Vec<f32> = (0..count).map(|index| {
+        let (x, y) = (index % size, index / size);
+        let (dx, dy) = (x.min(size - x) as f32, y.min(size - y) as f32);
+        f32::exp(-(dx * dx + dy * dy) / (2.0 * sigma * sigma))
+    }).collect();
+
+    let update = |energy: &mut [f32], index: usize, sign: f32| {
+        let (px, py) = (index % size, index / size);
+        for (target, e) in energy.iter_mut().enumerate() {
+            let (dx, dy) = ((target % size + size - px) % size, (target / size + size - py) % size);
+            *e += sign * kernel[dy * size + dx];
+        }
+    };
+
+    let tightest_cluster = |pattern: &[bool], energy: &[f32], value: bool| -> usize {
+        (0..count).filter(|&i| pattern[i] == value).max_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+    let largest_void = |pattern: &[bool], energy: &[f32], value: bool| -> usize {
+        (0..count).filter(|&i| pattern[i] != value).min_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+
+    // Initial binary pattern: random points, redistributed until the tightest cluster and the largest void coincide
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(seed);
+    let mut pattern = vec![false; count];
+    let mut energy = vec![0.0; count];
+    let initial_count = count / 10;
+    let mut placed = 0;
+    while placed < initial_count {
+        let index = rng.gen_range(0..count);
+        if !pattern[index] {
+            pattern[index] = true;
+            update(&mut energy, index, 1.0);
+            placed += 1;
+        }
+    }
+    loop {
+        let cluster = tightest_cluster(&pattern, &energy, true);
+        pattern[cluster] = false;
+        update(&mut energy, cluster, -1.0);
+
+        let void = largest_void(&pattern, &energy, true);
+        pattern[void] = true;
+        update(&mut energy, void, 1.0);
+
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0; count];
+
+    // Phase 1: rank the initial points by removing the tightest clusters
+    let (mut phase_pattern, mut phase_energy) = (pattern.clone(), energy.clone());
+    for rank in (0..initial_count).rev() {
+        let cluster = tightest_cluster(&phase_pattern, &phase_energy, true);
+        phase_pattern[cluster] = false;
+        update(&mut phase_energy, cluster, -1.0);
+        ranks[cluster] = rank;
+    }
+
+    // Phase 2: fill the largest voids up to half of the points
+    for rank in initial_count..count / 2 {
+        let void = largest_void(&pattern, &energy, true);
+        pattern[void] = true;
+        update(&mut energy, void, 1.0);
+        ranks[void] = rank;
+    }
+
+    // Phase 3: the remaining empty positions are the minority; fill their tightest clusters, measured by the energy of the empty positions
+    let mut empty_energy = vec![0.0; count];
+    for index in (0..count).filter(|&i| !pattern[i]) {
+        update(&mut empty_energy, index, 1.0);
+    }
+    for rank in count / 2..count {
+        let cluster = tightest_cluster(&pattern, &empty_energy, false);
+        pattern[cluster] = true;
+        update(&mut empty_energy, cluster, -1.0);
+        ranks[cluster] = rank;
+    }
+
+    ranks
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn blue_noise_sampler() {
+        let rect = Rectangle::new(-6, 20, 24, 30);
+        let sampler = BlueNoiseSampler::new(rect.clone(), 3);
+
+        let mut tile_count = 0;
+        for tile in sampler.tiles(3, 2) {
+            tile_count += 1;
+
+            let sample_count = tile.len();
+            for sample in tile {
+                let (pixel_x, pixel_y) = sample.pixel();
+                assert!(rect.contains(pixel_x, pixel_y), "pixel ({}, {}) outside of rectangle", pixel_x, pixel_y);
+
+                let (offset_x, offset_y) = sample.sample_offset();
+                assert!((0.0..1.0).contains(&offset_x) && (0.0..1.0).contains(&offset_y), "sample offset out of range: {:?}", sample);
+                assert!((0.0..1.0).contains(&sample.wavelength_sample()));
+            }
+
+            // Total rect size is 30 * 10, 3 samples per pixel, divided by 6 tiles
+            assert_eq!(sample_count, 30 * 10 * 3 / 6, "wrong number of samples in tile");
+        }
+
+        assert_eq!(tile_count, 6, "wrong number of tiles");
+    }
+
+    #[test]
+    fn blue_noise_sampler_is_deterministic_and_tileable() {
+        let sampler = BlueNoiseSampler::new(Rectangle::new(0, 0, 128, 2), 2);
+        let samples: Vec<_> = sampler.tiles(1, 1).flatten().collect();
+        assert_eq!(samples, sampler.tiles(1, 1).flatten().collect::<Vec<_>>());
+
+        // The masks repeat every 64 pixels
+        assert_eq!(samples[3].sample_offset(), samples[64 * 2 + 3].sample_offset());
+        assert_ne!(samples[3].sample_offset(), samples[2 * 2 + 3].sample_offset());
+    }
+
+    #[test]
+    fn blue_noise_sampler_offsets_are_blue_noise() {
+        // With one sample per pixel, horizontally neighbouring pixels have more different offsets than with white noise, for which the expected mean
+        // absolute difference between two uniform random values is 1/3
+        let sampler = BlueNoiseSampler::new(Rectangle::new(0, 0, 64, 64), 1);
+        let offsets: Vec<f32> = sampler.tiles(1, 1).flatten().map(|sample| sample.sample_offset().0).collect();
+
+        let differences: Vec<f32> = offsets.chunks_exact(64).flat_map(|row| row.windows(2).map(|w| (w[0] - w[1]).abs())).collect();
+        let mean_difference = differences.iter().sum::<f32>() / differences.len() as f32;
+        assert!(mean_difference > 0.37, "mean difference between neighbours is {}", mean_difference);
+    }
+
+    #[test]
+    fn blue_noise_mask_is_permutation() {
+        for index in 0..3 {
+            let mask = blue_noise_mask(index);
+            let mut ranks: Vec<usize> = mask.iter().map(|&t| (t * mask.len() as f32 - 0.5).round() as usize).collect();
+            ranks.sort_unstable();
+            assert_eq!(ranks, (0..mask.len()).collect::<Vec<_>>());
+        }
+        assert_ne!(blue_noise_mask(0), blue_noise_mask(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "samples per pixel")]
+    fn blue_noise_sampler_zero_samples_per_pixel() {
+        BlueNoiseSampler::new(Rectangle::new(0, 0, 4, 4), 0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn blue_noise_sampler_serde() {
+        let sampler = BlueNoiseSampler::new(Rectangle::new(10, 20, 22, 30), 4).with_pixel_order(PixelOrder::Hilbert);
+        let data = bincode::serialize(&sampler).unwrap();
+
+        let result: BlueNoiseSampler = bincode::deserialize(&data).unwrap();
+        assert_eq!(*result.rectangle(), *sampler.rectangle());
+        assert_eq!(result.samples_per_pixel(), 4);
+        assert_eq!(result.pixel_order(), PixelOrder::Hilbert);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn blue_noise_sampler_serde_zero_samples_per_pixel() {
+        let data = bincode::serialize(&(Rectangle::new(0, 0, 4, 4), 0u32, PixelOrder::Scanline)).unwrap();
+        assert!(bincode::deserialize::<BlueNoiseSampler>(&data).is_err());
+    }
+}
@@ -0,0 +1,303 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::iter::FusedIterator;
+
+use crate::rectangle::{Coordinate, PixelOrder, PixelOrderIterator, Rectangle, RectangleTileIterator};
+use crate::sampler::{hash_pixel, ONE_MINUS_EPSILON, permute, PixelSample, random_float, Sampler, SamplerTile};
+
+/// Correlated multi-jittered sampler by Kensler (2013), which supports any number of samples per pixel.
+///
+/// The samples of a pixel are stratified in a grid of about √N x √N cells and their projections on each axis are stratified in N strata. Each pixel uses a
+/// different pattern, derived from a hash of the pixel position.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "CmjSamplerData<C>"))]
+pub struct CmjSampler<C: Coordinate = u32> {
+    rectangle: Rectangle<C>,
+    samples_per_pixel: u32,
+    pixel_order: PixelOrder,
+}
+
+#[derive(Clone, Debug)]
+pub struct CmjSamplerTileIterator<C: Coordinate = u32> {
+    rect_iter: RectangleTileIterator<C>,
+    samples_per_pixel: u32,
+    pixel_order: PixelOrder,
+}
+
+#[derive(Clone, Debug)]
+pub struct CmjSamplerTile<C: Coordinate = u32> {
+    tile_rect: Rectangle<C>,
+    tile_rect_iter: PixelOrderIterator<C>,
+    samples_per_pixel: u32,
+
+    pixel_sample_count: u32,
+    pixel_x: C,
+    pixel_y: C,
+    pixel_seed: u32,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct CmjSamplerData<C: Coordinate> {
+    rectangle: Rectangle<C>,
+    samples_per_pixel: u32,
+    pixel_order: PixelOrder,
+}
+
+// ===== CmjSampler ============================================================================================================================================
+
+impl<C: Coordinate> CmjSampler<C> {
+    #[inline]
+    pub fn new(rectangle: Rectangle<C>, samples_per_pixel: u32) -> CmjSampler<C> {
+        assert!(samples_per_pixel > 0, "samples per pixel must be greater than zero");
+        CmjSampler { rectangle, samples_per_pixel, pixel_order: PixelOrder::Scanline }
+    }
+
+    /// Returns this sampler with the given order in which the pixels within each tile are visited.
+    #[inline]
+    pub fn with_pixel_order(self, pixel_order: PixelOrder) -> CmjSampler<C> {
+        CmjSampler { pixel_order, ..self }
+    }
+
+    #[inline]
+    pub fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    #[inline]
+    pub fn pixel_order(&self) -> PixelOrder {
+        self.pixel_order
+    }
+}
+
+impl<C: Coordinate> Sampler<C> for CmjSampler<C> {
+    type Tile = CmjSamplerTile<C>;
+    type TileIter = CmjSamplerTileIterator<C>;
+
+    #[inline]
+    fn rectangle(&self) -> &Rectangle<C> {
+        &self.rectangle
+    }
+
+    #[inline]
+    fn tiles(&self, tile_count_x: u32, tile_count_y: u32) -> CmjSamplerTileIterator<C> {
+        CmjSamplerTileIterator::new(self.rectangle(), self.samples_per_pixel, tile_count_x, tile_count_y, self.pixel_order)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<C: Coordinate> TryFrom<CmjSamplerData<C>> for CmjSampler<C> {
+    type Error = String;
+
+    fn try_from(data: CmjSamplerData<C>) -> Result<CmjSampler<C>, String> {
+        if data.samples_per_pixel > 0 {
+            Ok(CmjSampler { rectangle: data.rectangle, samples_per_pixel: data.samples_per_pixel, pixel_order: data.pixel_order })
+        } else {
+            Err("samples per pixel must be greater than zero".to_string())
+        }
+    }
+}
+
+// ===== CmjSamplerTileIterator ================================================================================================================================
+
+impl<C: Coordinate> CmjSamplerTileIterator<C> {
+    #[inline]
+    fn new(sampler_rect: &Rectangle<C>, samples_per_pixel: u32, tile_count_x: u32, tile_count_y: u32, pixel_order: PixelOrder) -> CmjSamplerTileIterator<C> {
+        CmjSamplerTileIterator { rect_iter: sampler_rect.tile_iter(tile_count_x, tile_count_y), samples_per_pixel, pixel_order }
+    }
+}
+
+impl<C: Coordinate> Iterator for CmjSamplerTileIterator<C> {
+    type Item = CmjSamplerTile<C>;
+
+    fn next(&mut self) -> Option<CmjSamplerTile<C>> {
+        self.rect_iter.next().map(|tile| CmjSamplerTile::new(tile, self.samples_per_pixel, self.pixel_order))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rect_iter.size_hint()
+    }
+}
+
+impl<C: Coordinate> ExactSizeIterator for CmjSamplerTileIterator<C> {}
+
+impl<C: Coordinate> FusedIterator for CmjSamplerTileIterator<C> {}
+
+// ===== CmjSamplerTile ========================================================================================================================================
+
+impl<C: Coordinate> CmjSamplerTile<C> {
+    fn new(tile_rect: Rectangle<C>, samples_per_pixel: u32, pixel_order: PixelOrder) -> CmjSamplerTile<C> {
+        let tile_rect_iter = tile_rect.pixel_order_iter(pixel_order);
+        let (pixel_x, pixel_y) = (tile_rect.left, tile_rect.top);
+
+        CmjSamplerTile {
+            tile_rect,
+            tile_rect_iter,
+            samples_per_pixel,
+
+            pixel_sample_count: samples_per_pixel, // So that the first time, we advance to the first pixel
+            pixel_x,
+            pixel_y,
+            pixel_seed: 0,
+        }
+    }
+}
+
+impl<C: Coordinate> SamplerTile<C> for CmjSamplerTile<C> {
+    #[inline]
+    fn rectangle(&self) -> &Rectangle<C> {
+        &self.tile_rect
+    }
+}
+
+impl<C: Coordinate> Iterator for CmjSamplerTile<C> {
+    type Item = PixelSample<C>;
+
+    fn next(&mut self) -> Option<PixelSample<C>> {
+        if self.pixel_sample_count >= self.samples_per_pixel {
+            if let Some((px, py)) = self.tile_rect_iter.next() {
+                // Advance to the next pixel in the tile
+                self.pixel_sample_count = 0;
+                self.pixel_x = px;
+                self.pixel_y = py;
+                self.pixel_seed = hash_pixel(px, py);
+            } else {
+                // No more pixels
+                return None;
+            }
+        }
+
+        // Generate the next sample for the current pixel
+        let index = self.pixel_sample_count;
+        self.pixel_sample_count += 1;
+        let (sample_offset_x, sample_offset_y) = cmj(index, self.samples_per_pixel, self.pixel_seed);
+        let wavelength_sample = (permute(index, self.samples_per_pixel, self.pixel_seed.wrapping_mul(0x5b8a_4b3d)) as f32
+            + random_float(index, self.pixel_seed.wrapping_mul(0x2f4a_2b11))) / self.samples_per_pixel as f32;
+
+        Some(PixelSample::new(self.pixel_x, self.pixel_y, sample_offset_x.min(ONE_MINUS_EPSILON), sample_offset_y.min(ONE_MINUS_EPSILON))
+            .with_wavelength_sample(wavelength_sample.min(ONE_MINUS_EPSILON)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (pixels_remaining, _) = self.tile_rect_iter.size_hint();
+        let remaining = pixels_remaining * self.samples_per_pixel as usize + (self.samples_per_pixel - self.pixel_sample_count) as usize;
+
+        (remaining, Some(remaining))
+    }
+}
+
+impl<C: Coordinate> ExactSizeIterator for CmjSamplerTile<C> {}
+
+impl<C: Coordinate> FusedIterator for CmjSamplerTile<C> {}
+
+// ===== Correlated multi-jittered patterns ====================================================================================================================
+
+/// Returns sample number `index` of the correlated multi-jittered pattern of `count` samples with the given pattern number (Kensler, 2013, listing 5).
+fn cmj(index: u32, count: u32, pattern: u32) -> (f32, f32) {
+    let m = (count as f32).sqrt() as u32;
+    let n = count.div_ceil(m);
+
+    let s = permute(index, count, pattern.wrapping_mul(0x5163_3e2d));
+    let sx = permute(s % m, m, pattern.wrapping_mul(0x68bc_21eb));
+    let sy = permute(s / m, n, pattern.wrapping_mul(0x02e5_be93));
+    let jx = random_float(s, pattern.wrapping_mul(0x967a_889b));
+    let jy = random_float(s, pattern.wrapping_mul(0x368c_c8b7));
+
+    ((sx as f32 + (sy as f32 + jx) / n as f32) / m as f32, (s as f32 + jy) / count as f32)
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn samples(samples_per_pixel: u32) -> Vec<(f32, f32)> {
+        let sampler = CmjSampler::new(Rectangle::new(-3, 7, -2, 8), samples_per_pixel);
+        sampler.tiles(1, 1).flatten().map(|sample| sample.sample_offset()).collect()
+    }
+
+    #[test]
+    fn cmj_sampler() {
+        let rect = Rectangle::new(10, 20, 22, 30);
+        let sampler = CmjSampler::new(rect.clone(), 5);
+
+        let mut tile_count = 0;
+        for tile in sampler.tiles(3, 2) {
+            tile_count += 1;
+
+            // Total rect size is 12 * 10, 5 samples per pixel, divided by 6 tiles
+            assert_eq!(tile.len(), 12 * 10 * 5 / 6, "wrong number of samples in tile");
+            for sample in tile {
+                assert!(rect.contains(sample.pixel().0, sample.pixel().1));
+                assert!((0.0..1.0).contains(&sample.wavelength_sample()));
+            }
+        }
+
+        assert_eq!(tile_count, 6, "wrong number of tiles");
+    }
+
+    #[test]
+    fn cmj_sampler_stratification() {
+        for count in [1, 2, 5, 7, 12, 16, 30] {
+            let samples = samples(count);
+            let (m, n) = ((count as f32).sqrt() as u32, count.div_ceil((count as f32).sqrt() as u32));
+
+            // One sample in each of the horizontal strata and at most one sample in each vertical stratum; when the sample count fills the m x n grid,
+            // there is also one sample in each cell of the grid
+            let mut rows = vec![0; count as usize];
+            let mut columns = vec![0; (m * n) as usize];
+            let mut cells = vec![0; (m * n) as usize];
+            for &(x, y) in &samples {
+                assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+                rows[(y * count as f32) as usize] += 1;
+                columns[(x * (m * n) as f32) as usize] += 1;
+                cells[(y * n as f32) as usize * m as usize + (x * m as f32) as usize] += 1;
+            }
+            assert!(rows.iter().all(|&c| c == 1), "{} samples: rows {:?}", count, rows);
+            assert!(columns.iter().all(|&c| c <= 1), "{} samples: columns {:?}", count, columns);
+            if count == m * n {
+                assert!(cells.iter().all(|&c| c == 1), "{} samples: cells {:?}", count, cells);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "samples per pixel")]
+    fn cmj_sampler_zero_samples_per_pixel() {
+        CmjSampler::new(Rectangle::new(0, 0, 4, 4), 0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn cmj_sampler_serde() {
+        let sampler = CmjSampler::new(Rectangle::new(10, 20, 22, 30), 6).with_pixel_order(PixelOrder::Morton);
+        let data = bincode::serialize(&sampler).unwrap();
+
+        let result: CmjSampler = bincode::deserialize(&data).unwrap();
+        assert_eq!(*result.rectangle(), *sampler.rectangle());
+        assert_eq!(result.samples_per_pixel(), 6);
+        assert_eq!(result.pixel_order(), PixelOrder::Morton);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn cmj_sampler_serde_zero_samples_per_pixel() {
+        let data = bincode::serialize(&(Rectangle::new(0, 0, 4, 4), 0u32, PixelOrder::Scanline)).unwrap();
+        assert!(bincode::deserialize::<CmjSampler>(&data).is_err());
+    }
+}
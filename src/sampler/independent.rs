@@ -18,76 +18,115 @@ use rand::Rng;
 use rand_xoshiro::rand_core::SeedableRng;
 use rand_xoshiro::Xoshiro256PlusPlus;
 
-use crate::rectangle::{Rectangle, RectangleIndexIterator, RectangleTileIterator};
-use crate::sampler::{PixelSample, Sampler, SamplerTile};
+use crate::rectangle::{Coordinate, PixelOrder, PixelOrderIterator, Rectangle, RectangleTileIterator};
+use crate::sampler::{hash_pixel, ONE_MINUS_EPSILON, permute, PixelSample, Sampler, SamplerTile};
 
 #[derive(Clone, Debug)]
-pub struct IndependentSampler {
-    rectangle: Rectangle,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "IndependentSamplerData<C>"))]
+pub struct IndependentSampler<C: Coordinate = u32> {
+    rectangle: Rectangle<C>,
     samples_per_pixel: u32,
     jitter: bool,
+    pixel_order: PixelOrder,
 }
 
 #[derive(Clone, Debug)]
-pub struct IndependentSamplerTileIterator {
-    rect_iter: RectangleTileIterator,
+pub struct IndependentSamplerTileIterator<C: Coordinate = u32> {
+    rect_iter: RectangleTileIterator<C>,
     samples_per_pixel: u32,
     jitter: bool,
+    pixel_order: PixelOrder,
 }
 
 #[derive(Clone, Debug)]
-pub struct IndependentSamplerTile {
-    tile_rect: Rectangle,
-    tile_rect_iter: RectangleIndexIterator,
+pub struct IndependentSamplerTile<C: Coordinate = u32> {
+    tile_rect: Rectangle<C>,
+    tile_rect_iter: PixelOrderIterator<C>,
     samples_per_pixel: u32,
 
     pixel_sample_count: u32,
-    pixel_x: u32,
-    pixel_y: u32,
+    pixel_x: C,
+    pixel_y: C,
+    wavelength_pattern: u32,
 
     jitter: bool,
     rng: Xoshiro256PlusPlus,
 }
 
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct IndependentSamplerData<C: Coordinate> {
+    rectangle: Rectangle<C>,
+    samples_per_pixel: u32,
+    jitter: bool,
+    pixel_order: PixelOrder,
+}
+
 // ===== IndependentSampler ====================================================================================================================================
 
-impl IndependentSampler {
+impl<C: Coordinate> IndependentSampler<C> {
+    #[inline]
+    pub fn new(rectangle: Rectangle<C>, samples_per_pixel: u32, jitter: bool) -> IndependentSampler<C> {
+        assert!(samples_per_pixel > 0, "samples per pixel must be greater than zero");
+        IndependentSampler { rectangle, samples_per_pixel, jitter, pixel_order: PixelOrder::Scanline }
+    }
+
+    /// Returns this sampler with the given order in which the pixels within each tile are visited.
+    #[inline]
+    pub fn with_pixel_order(self, pixel_order: PixelOrder) -> IndependentSampler<C> {
+        IndependentSampler { pixel_order, ..self }
+    }
+
     #[inline]
-    pub fn new(rectangle: Rectangle, samples_per_pixel: u32, jitter: bool) -> IndependentSampler {
-        IndependentSampler { rectangle, samples_per_pixel, jitter }
+    pub fn pixel_order(&self) -> PixelOrder {
+        self.pixel_order
     }
 }
 
-impl Sampler for IndependentSampler {
-    type Tile = IndependentSamplerTile;
-    type TileIter = IndependentSamplerTileIterator;
+impl<C: Coordinate> Sampler<C> for IndependentSampler<C> {
+    type Tile = IndependentSamplerTile<C>;
+    type TileIter = IndependentSamplerTileIterator<C>;
 
     #[inline]
-    fn rectangle(&self) -> &Rectangle {
+    fn rectangle(&self) -> &Rectangle<C> {
         &self.rectangle
     }
 
     #[inline]
-    fn tiles(&self, tile_count_x: u32, tile_count_y: u32) -> IndependentSamplerTileIterator {
-        IndependentSamplerTileIterator::new(self.rectangle(), self.samples_per_pixel, tile_count_x, tile_count_y, self.jitter)
+    fn tiles(&self, tile_count_x: u32, tile_count_y: u32) -> IndependentSamplerTileIterator<C> {
+        IndependentSamplerTileIterator::new(self.rectangle(), self.samples_per_pixel, tile_count_x, tile_count_y, self.jitter, self.pixel_order)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<C: Coordinate> TryFrom<IndependentSamplerData<C>> for IndependentSampler<C> {
+    type Error = String;
+
+    fn try_from(data: IndependentSamplerData<C>) -> Result<IndependentSampler<C>, String> {
+        if data.samples_per_pixel > 0 {
+            Ok(IndependentSampler { rectangle: data.rectangle, samples_per_pixel: data.samples_per_pixel, jitter: data.jitter, pixel_order: data.pixel_order })
+        } else {
+            Err("samples per pixel must be greater than zero".to_string())
+        }
     }
 }
 
 // ===== IndependentSamplerTileIterator ========================================================================================================================
 
-impl IndependentSamplerTileIterator {
+impl<C: Coordinate> IndependentSamplerTileIterator<C> {
     #[inline]
-    fn new(sampler_rect: &Rectangle, samples_per_pixel: u32, tile_count_x: u32, tile_count_y: u32, jitter: bool) -> IndependentSamplerTileIterator {
-        IndependentSamplerTileIterator { rect_iter: sampler_rect.tile_iter(tile_count_x, tile_count_y), samples_per_pixel, jitter }
+    fn new(sampler_rect: &Rectangle<C>, samples_per_pixel: u32, tile_count_x: u32, tile_count_y: u32, jitter: bool, pixel_order: PixelOrder) -> IndependentSamplerTileIterator<C> {
+        IndependentSamplerTileIterator { rect_iter: sampler_rect.tile_iter(tile_count_x, tile_count_y), samples_per_pixel, jitter, pixel_order }
     }
 }
 
-impl Iterator for IndependentSamplerTileIterator {
-    type Item = IndependentSamplerTile;
+impl<C: Coordinate> Iterator for IndependentSamplerTileIterator<C> {
+    type Item = IndependentSamplerTile<C>;
 
-    fn next(&mut self) -> Option<IndependentSamplerTile> {
+    fn next(&mut self) -> Option<IndependentSamplerTile<C>> {
         self.rect_iter.next().map(|tile| {
-            IndependentSamplerTile::new(tile, self.samples_per_pixel, self.jitter)
+            IndependentSamplerTile::new(tile, self.samples_per_pixel, self.jitter, self.pixel_order)
         })
     }
 
@@ -97,15 +136,15 @@ impl Iterator for IndependentSamplerTileIterator {
     }
 }
 
-impl ExactSizeIterator for IndependentSamplerTileIterator {}
+impl<C: Coordinate> ExactSizeIterator for IndependentSamplerTileIterator<C> {}
 
-impl FusedIterator for IndependentSamplerTileIterator {}
+impl<C: Coordinate> FusedIterator for IndependentSamplerTileIterator<C> {}
 
 // ===== IndependentSamplerTile ================================================================================================================================
 
-impl IndependentSamplerTile {
-    fn new(tile_rect: Rectangle, samples_per_pixel: u32, jitter: bool) -> IndependentSamplerTile {
-        let tile_rect_iter = tile_rect.index_iter();
+impl<C: Coordinate> IndependentSamplerTile<C> {
+    fn new(tile_rect: Rectangle<C>, samples_per_pixel: u32, jitter: bool, pixel_order: PixelOrder) -> IndependentSamplerTile<C> {
+        let tile_rect_iter = tile_rect.pixel_order_iter(pixel_order);
         let (pixel_x, pixel_y) = (tile_rect.left, tile_rect.top);
 
         IndependentSamplerTile {
@@ -116,6 +155,7 @@ impl IndependentSamplerTile {
             pixel_sample_count: samples_per_pixel, // So that the first time, we advance to the first pixel
             pixel_x,
             pixel_y,
+            wavelength_pattern: 0,
 
             jitter,
             rng: Xoshiro256PlusPlus::from_entropy(),
@@ -123,23 +163,24 @@ impl IndependentSamplerTile {
     }
 }
 
-impl SamplerTile for IndependentSamplerTile {
+impl<C: Coordinate> SamplerTile<C> for IndependentSamplerTile<C> {
     #[inline]
-    fn rectangle(&self) -> &Rectangle {
+    fn rectangle(&self) -> &Rectangle<C> {
         &self.tile_rect
     }
 }
 
-impl Iterator for IndependentSamplerTile {
-    type Item = PixelSample;
+impl<C: Coordinate> Iterator for IndependentSamplerTile<C> {
+    type Item = PixelSample<C>;
 
-    fn next(&mut self) -> Option<PixelSample> {
+    fn next(&mut self) -> Option<PixelSample<C>> {
         if self.pixel_sample_count >= self.samples_per_pixel {
             if let Some((px, py)) = self.tile_rect_iter.next() {
                 // Advance to the next pixel in the tile
                 self.pixel_sample_count = 0;
                 self.pixel_x = px;
                 self.pixel_y = py;
+                self.wavelength_pattern = if self.jitter { self.rng.gen() } else { hash_pixel(px, py) };
             } else {
                 // No more pixels
                 return None;
@@ -147,9 +188,13 @@ impl Iterator for IndependentSamplerTile {
         }
 
         // Generate the next sample for the current pixel
+        let (sample_offset_x, sample_offset_y, jitter_wavelength) = if self.jitter { self.rng.gen() } else { (0.5, 0.5, 0.5) };
+
+        // The wavelength samples of a pixel are stratified, in a shuffled order so that they are not correlated between pixels
+        let wavelength_stratum = permute(self.pixel_sample_count, self.samples_per_pixel, self.wavelength_pattern);
+        let wavelength_sample = ((wavelength_stratum as f32 + jitter_wavelength) / self.samples_per_pixel as f32).min(ONE_MINUS_EPSILON);
         self.pixel_sample_count += 1;
-        let (sample_offset_x, sample_offset_y) = if self.jitter { self.rng.gen() } else { (0.5, 0.5) };
-        Some(PixelSample::new(self.pixel_x, self.pixel_y, sample_offset_x, sample_offset_y))
+        Some(PixelSample::new(self.pixel_x, self.pixel_y, sample_offset_x, sample_offset_y).with_wavelength_sample(wavelength_sample))
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
@@ -160,15 +205,16 @@ impl Iterator for IndependentSamplerTile {
     }
 }
 
-impl ExactSizeIterator for IndependentSamplerTile {}
+impl<C: Coordinate> ExactSizeIterator for IndependentSamplerTile<C> {}
 
-impl FusedIterator for IndependentSamplerTile {}
+impl<C: Coordinate> FusedIterator for IndependentSamplerTile<C> {}
 
 // ===== Tests =================================================================================================================================================
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::color::{ColorSpace, SampledWavelengths, Spectrum};
 
     #[test]
     fn independent_sampler() {
@@ -177,14 +223,9 @@ mod test {
 
         let mut tile_count = 0;
         for tile in sampler.tiles(3, 2) {
-            // println!("***** tile: {:?}", tile.tile_rect);
             tile_count += 1;
 
-            let mut sample_count = 0;
-            for sample in tile {
-                // println!("  --- sample: {:?}", sample);
-                sample_count += 1;
-            }
+            let sample_count = tile.count();
 
             // Total rect size is 12 * 10, 2 samples per pixel, divided by 6 tiles
             assert_eq!(sample_count, 12 * 10 * 2 / 6, "wrong number of samples in tile");
@@ -192,4 +233,62 @@ mod test {
 
         assert_eq!(tile_count, 6, "wrong number of tiles");
     }
+
+    #[test]
+    fn independent_sampler_negative_coordinates() {
+        let rect = Rectangle::new(-8, -6, 8, 6);
+        let sampler = IndependentSampler::new(rect.clone(), 2, true);
+
+        let mut sample_count = 0;
+        for sample in sampler.tiles(2, 2).flatten() {
+            let (pixel_x, pixel_y) = sample.pixel();
+            assert!(rect.contains(pixel_x, pixel_y), "pixel ({}, {}) outside of rectangle", pixel_x, pixel_y);
+
+            let (sample_x, sample_y) = sample.sample();
+            assert!(sample_x >= pixel_x as f32 && sample_x < (pixel_x + 1) as f32);
+            assert!(sample_y >= pixel_y as f32 && sample_y < (pixel_y + 1) as f32);
+            sample_count += 1;
+        }
+        assert_eq!(sample_count, 16 * 12 * 2);
+    }
+
+    #[test]
+    fn independent_sampler_wavelengths() {
+        // With jitter off, the wavelength samples of a pixel should still cover the visible range evenly, so a constant spectrum comes out with the color of the constant spectrum
+        let sampler = IndependentSampler::new(Rectangle::new(0, 0, 2, 2), 40, false);
+        let expected = Spectrum::constant(1.0).to_rgb(&ColorSpace::LINEAR_SRGB);
+
+        let samples: Vec<_> = sampler.tiles(1, 1).flatten().collect();
+        for pixel_samples in samples.chunks(40) {
+            let mut spectrum = Spectrum::default();
+            for sample in pixel_samples {
+                spectrum += Spectrum::from_wavelength_samples(&SampledWavelengths::sample(sample.wavelength_sample()), [1.0; SampledWavelengths::COUNT]);
+            }
+
+            let rgb = (spectrum / pixel_samples.len() as f32).to_rgb(&ColorSpace::LINEAR_SRGB);
+            assert!((rgb.r - expected.r).abs() < 1e-3 && (rgb.g - expected.g).abs() < 1e-3 && (rgb.b - expected.b).abs() < 1e-3, "wrong color: {:?}", rgb);
+        }
+    }
+
+    #[test]
+    fn independent_sampler_pixel_order() {
+        let rect = Rectangle::new(0, 0, 8, 8);
+        let sampler = IndependentSampler::new(rect.clone(), 3, true).with_pixel_order(PixelOrder::Morton);
+
+        let pixels: Vec<_> = sampler.tiles(1, 1).flatten().step_by(3).map(|sample| sample.pixel()).collect();
+        assert_eq!(pixels, rect.morton_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[should_panic(expected = "samples per pixel")]
+    fn independent_sampler_zero_samples_per_pixel() {
+        IndependentSampler::new(Rectangle::new(0, 0, 4, 4), 0, true);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn independent_sampler_serde_zero_samples_per_pixel() {
+        let data = bincode::serialize(&(Rectangle::new(0, 0, 4, 4), 0u32, true, PixelOrder::Scanline)).unwrap();
+        assert!(bincode::deserialize::<IndependentSampler>(&data).is_err());
+    }
 }
@@ -0,0 +1,467 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::iter::FusedIterator;
+use std::sync::OnceLock;
+
+use rand::Rng;
+use rand_xoshiro::rand_core::SeedableRng;
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+use crate::rectangle::{Coordinate, PixelOrder, PixelOrderIterator, Rectangle, RectangleTileIterator};
+use crate::sampler::{hash_pixel, ONE_MINUS_EPSILON, permute, PixelSample, random_float, Sampler, SamplerTile};
+
+/// Progressive multi-jittered (0,2) sampler by Christensen, Kensler and Kilpatrick (2018).
+///
+/// Every prefix of 2^k samples of a pixel has one sample in each base-2 elementary interval of area 1/2^k, so each prefix is well distributed and any number
+/// of samples per pixel can be used. The samples are taken from a set of precomputed pmj02 sequences of 1024 samples; each pixel uses one of these sequences,
+/// selected and scrambled by a hash of the pixel position. With more than 1024 samples per pixel, each following block of 1024 samples uses another
+/// sequence.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "Pmj02SamplerData<C>"))]
+pub struct Pmj02Sampler<C: Coordinate = u32> {
+    rectangle: Rectangle<C>,
+    samples_per_pixel: u32,
+    pixel_order: PixelOrder,
+}
+
+#[derive(Clone, Debug)]
+pub struct Pmj02SamplerTileIterator<C: Coordinate = u32> {
+    rect_iter: RectangleTileIterator<C>,
+    samples_per_pixel: u32,
+    pixel_order: PixelOrder,
+}
+
+#[derive(Clone, Debug)]
+pub struct Pmj02SamplerTile<C: Coordinate = u32> {
+    tile_rect: Rectangle<C>,
+    tile_rect_iter: PixelOrderIterator<C>,
+    samples_per_pixel: u32,
+
+    pixel_sample_count: u32,
+    pixel_x: C,
+    pixel_y: C,
+    pixel_seed: u32,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct Pmj02SamplerData<C: Coordinate> {
+    rectangle: Rectangle<C>,
+    samples_per_pixel: u32,
+    pixel_order: PixelOrder,
+}
+
+// ===== Pmj02Sampler ==========================================================================================================================================
+
+impl<C: Coordinate> Pmj02Sampler<C> {
+    #[inline]
+    pub fn new(rectangle: Rectangle<C>, samples_per_pixel: u32) -> Pmj02Sampler<C> {
+        assert!(samples_per_pixel > 0, "samples per pixel must be greater than zero");
+        Pmj02Sampler { rectangle, samples_per_pixel, pixel_order: PixelOrder::Scanline }
+    }
+
+    /// Returns this sampler with the given order in which the pixels within each tile are visited.
+    #[inline]
+    pub fn with_pixel_order(self, pixel_order: PixelOrder) -> Pmj02Sampler<C> {
+        Pmj02Sampler { pixel_order, ..self }
+    }
+
+    #[inline]
+    pub fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    #[inline]
+    pub fn pixel_order(&self) -> PixelOrder {
+        self.pixel_order
+    }
+}
+
+impl<C: Coordinate> Sampler<C> for Pmj02Sampler<C> {
+    type Tile = Pmj02SamplerTile<C>;
+    type TileIter = Pmj02SamplerTileIterator<C>;
+
+    #[inline]
+    fn rectangle(&self) -> &Rectangle<C> {
+        &self.rectangle
+    }
+
+    #[inline]
+    fn tiles(&self, tile_count_x: u32, tile_count_y: u32) -> Pmj02SamplerTileIterator<C> {
+        Pmj02SamplerTileIterator::new(self.rectangle(), self.samples_per_pixel, tile_count_x, tile_count_y, self.pixel_order)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<C: Coordinate> TryFrom<Pmj02SamplerData<C>> for Pmj02Sampler<C> {
+    type Error = String;
+
+    fn try_from(data: Pmj02SamplerData<C>) -> Result<Pmj02Sampler<C>, String> {
+        if data.samples_per_pixel > 0 {
+            Ok(Pmj02Sampler { rectangle: data.rectangle, samples_per_pixel: data.samples_per_pixel, pixel_order: data.pixel_order })
+        } else {
+            Err("samples per pixel must be greater than zero".to_string())
+        }
+    }
+}
+
+// ===== Pmj02SamplerTileIterator ==============================================================================================================================
+
+impl<C: Coordinate> Pmj02SamplerTileIterator<C> {
+    #[inline]
+    fn new(sampler_rect: &Rectangle<C>, samples_per_pixel: u32, tile_count_x: u32, tile_count_y: u32, pixel_order: PixelOrder) -> Pmj02SamplerTileIterator<C> {
+        Pmj02SamplerTileIterator { rect_iter: sampler_rect.tile_iter(tile_count_x, tile_count_y), samples_per_pixel, pixel_order }
+    }
+}
+
+impl<C: Coordinate> Iterator for Pmj02SamplerTileIterator<C> {
+    type Item = Pmj02SamplerTile<C>;
+
+    fn next(&mut self) -> Option<Pmj02SamplerTile<C>> {
+        self.rect_iter.next().map(|tile| Pmj02SamplerTile::new(tile, self.samples_per_pixel, self.pixel_order))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rect_iter.size_hint()
+    }
+}
+
+impl<C: Coordinate> ExactSizeIterator for Pmj02SamplerTileIterator<C> {}
+
+impl<C: Coordinate> FusedIterator for Pmj02SamplerTileIterator<C> {}
+
+// ===== Pmj02SamplerTile ======================================================================================================================================
+
+impl<C: Coordinate> Pmj02SamplerTile<C> {
+    fn new(tile_rect: Rectangle<C>, samples_per_pixel: u32, pixel_order: PixelOrder) -> Pmj02SamplerTile<C> {
+        let tile_rect_iter = tile_rect.pixel_order_iter(pixel_order);
+        let (pixel_x, pixel_y) = (tile_rect.left, tile_rect.top);
+
+        Pmj02SamplerTile {
+            tile_rect,
+            tile_rect_iter,
+            samples_per_pixel,
+
+            pixel_sample_count: samples_per_pixel, // So that the first time, we advance to the first pixel
+            pixel_x,
+            pixel_y,
+            pixel_seed: 0,
+        }
+    }
+}
+
+impl<C: Coordinate> SamplerTile<C> for Pmj02SamplerTile<C> {
+    #[inline]
+    fn rectangle(&self) -> &Rectangle<C> {
+        &self.tile_rect
+    }
+}
+
+impl<C: Coordinate> Iterator for Pmj02SamplerTile<C> {
+    type Item = PixelSample<C>;
+
+    fn next(&mut self) -> Option<PixelSample<C>> {
+        if self.pixel_sample_count >= self.samples_per_pixel {
+            if let Some((px, py)) = self.tile_rect_iter.next() {
+                // Advance to the next pixel in the tile
+                self.pixel_sample_count = 0;
+                self.pixel_x = px;
+                self.pixel_y = py;
+                self.pixel_seed = hash_pixel(px, py);
+            } else {
+                // No more pixels
+                return None;
+            }
+        }
+
+        // Generate the next sample for the current pixel, from the sequence for this pixel and block of samples
+        let index = self.pixel_sample_count;
+        self.pixel_sample_count += 1;
+
+        let block = index / SEQUENCE_LENGTH as u32;
+        let pattern = (self.pixel_seed ^ block.wrapping_mul(0x9e37_79b9)).wrapping_mul(0x85eb_ca6b);
+        let (x, y) = pmj02_sequence((pattern >> 28) as usize % SEQUENCE_COUNT)[index as usize % SEQUENCE_LENGTH];
+
+        // Flipping the same bits of all samples keeps the stratification in elementary intervals
+        let sample_offset_x = to_float(x ^ pattern.wrapping_mul(0xc2b2_ae35));
+        let sample_offset_y = to_float(y ^ pattern.wrapping_mul(0x27d4_eb2f));
+        let wavelength_sample = (permute(index, self.samples_per_pixel, pattern.wrapping_mul(0x5b8a_4b3d)) as f32
+            + random_float(index, pattern.wrapping_mul(0x2f4a_2b11))) / self.samples_per_pixel as f32;
+
+        Some(PixelSample::new(self.pixel_x, self.pixel_y, sample_offset_x.min(ONE_MINUS_EPSILON), sample_offset_y.min(ONE_MINUS_EPSILON))
+            .with_wavelength_sample(wavelength_sample.min(ONE_MINUS_EPSILON)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (pixels_remaining, _) = self.tile_rect_iter.size_hint();
+        let remaining = pixels_remaining * self.samples_per_pixel as usize + (self.samples_per_pixel - self.pixel_sample_count) as usize;
+
+        (remaining, Some(remaining))
+    }
+}
+
+impl<C: Coordinate> ExactSizeIterator for Pmj02SamplerTile<C> {}
+
+impl<C: Coordinate> FusedIterator for Pmj02SamplerTile<C> {}
+
+// ===== Progressive multi-jittered (0,2) sequences ============================================================================================================
+
+/// Number of precomputed pmj02 sequences.
+const SEQUENCE_COUNT: usize = 16;
+
+/// Number of samples in each precomputed pmj02 sequence; must be a power of two.
+const SEQUENCE_LENGTH: usize = 1024;
+
+/// Returns one of the precomputed pmj02 sequences, with coordinates in 0.32 fixed point format.
+fn pmj02_sequence(index: usize) -> &'static [(u32, u32)] {
+    static SEQUENCES: [OnceLock<Vec<(u32, u32)>>; SEQUENCE_COUNT] = [const { OnceLock::new() }; SEQUENCE_COUNT];
+
+    SEQUENCES[index].get_or_init(|| generate_pmj02(SEQUENCE_LENGTH, 0x9e37_79b9_7f4a_7c15 ^ index as u64))
+}
+
+/// Generates a progressive multi-jittered (0,2) sequence. The sequence is doubled repeatedly by placing a new sample for each existing sample in an empty
+/// quadrant of the grid cell that contains it, at a random position that is in an empty elementary interval of every shape.
+fn generate_pmj02(length: usize, seed: u64) -> Vec<(u32, u32)> {
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(seed);
+
+    // When no valid position is left for a sample, which is rare, start over
+    loop {
+        if let Some(samples) = try_generate_pmj02(length, &mut rng) {
+            return samples;
+        }
+    }
+}
+
+fn try_generate_pmj02(length: usize, rng: &mut Xoshiro256PlusPlus) -> Option<Vec<(u32, u32)>> {
+    let mut samples: Vec<(u32, u32)> = Vec::with_capacity(length);
+    samples.push((rng.gen(), rng.gen()));
+
+    while samples.len() < length {
+        let count = samples.len();
+        let count_bits = count.trailing_zeros();
+        let grid_bits = count_bits / 2;
+        let mut intervals = ElementaryIntervals::new(count_bits + 1, &samples);
+
+        if count_bits.is_multiple_of(2) {
+            // Each cell of the grid contains one sample; add a sample in the diagonally opposite quadrant
+            for index in 0..count {
+                let (x, y) = samples[index];
+                let quadrant = (quadrant(x, grid_bits) ^ 1, quadrant(y, grid_bits) ^ 1);
+                samples.push(intervals.sample_in_quadrant((x, y), quadrant, grid_bits, rng)?);
+            }
+        } else {
+            // Each cell of the grid contains two samples in diagonally opposite quadrants; add a sample in one of the other quadrants, chosen at random, and
+            // then a sample in the last quadrant
+            let mut quadrants = Vec::with_capacity(count / 2);
+            for index in 0..count / 2 {
+                let (x, y) = samples[index];
+                let (quadrant_x, quadrant_y) = (quadrant(x, grid_bits), quadrant(y, grid_bits));
+                let quadrant = if rng.gen() { (quadrant_x ^ 1, quadrant_y) } else { (quadrant_x, quadrant_y ^ 1) };
+                quadrants.push(quadrant);
+                samples.push(intervals.sample_in_quadrant((x, y), quadrant, grid_bits, rng)?);
+            }
+
+            for (index, (quadrant_x, quadrant_y)) in quadrants.into_iter().enumerate() {
+                samples.push(intervals.sample_in_quadrant(samples[index], (quadrant_x ^ 1, quadrant_y ^ 1), grid_bits, rng)?);
+            }
+        }
+    }
+
+    Some(samples)
+}
+
+/// Returns the quadrant (0 or 1) of a coordinate within its cell, when the unit interval is divided into 2^grid_bits cells.
+#[inline]
+fn quadrant(value: u32, grid_bits: u32) -> u32 {
+    (value >> (31 - grid_bits)) & 1
+}
+
+/// Returns the most significant bits of a value.
+#[inline]
+fn top_bits(value: u32, bits: u32) -> u32 {
+    if bits == 0 { 0 } else { value >> (32 - bits) }
+}
+
+/// Occupied base-2 elementary intervals of area 1/2^bits: for each shape of 2^k x 2^(bits - k) cells, which cells contain a sample.
+struct ElementaryIntervals {
+    bits: u32,
+    occupied: Vec<Vec<bool>>,
+}
+
+impl ElementaryIntervals {
+    fn new(bits: u32, samples: &[(u32, u32)]) -> ElementaryIntervals {
+        let mut intervals = ElementaryIntervals { bits, occupied: vec![vec![false; 1 << bits]; bits as usize + 1] };
+        for &sample in samples {
+            intervals.insert(sample);
+        }
+        intervals
+    }
+
+    /// Returns the index of the cell that contains a sample, for the shape with 2^column_bits columns.
+    #[inline]
+    fn cell(&self, (x, y): (u32, u32), column_bits: u32) -> usize {
+        ((top_bits(y, self.bits - column_bits) << column_bits) | top_bits(x, column_bits)) as usize
+    }
+
+    fn is_free(&self, sample: (u32, u32)) -> bool {
+        (0..=self.bits).all(|column_bits| !self.occupied[column_bits as usize][self.cell(sample, column_bits)])
+    }
+
+    fn insert(&mut self, sample: (u32, u32)) {
+        for column_bits in 0..=self.bits {
+            let cell = self.cell(sample, column_bits);
+            self.occupied[column_bits as usize][cell] = true;
+        }
+    }
+
+    /// Adds and returns a random sample in the given quadrant of the cell that contains a sample, when the unit square is divided into a grid of
+    /// 2^grid_bits x 2^grid_bits cells. The new sample is in an empty elementary interval of every shape; if there is no such position, returns `None`.
+    fn sample_in_quadrant(&mut self, (x, y): (u32, u32), (quadrant_x, quadrant_y): (u32, u32), grid_bits: u32, rng: &mut Xoshiro256PlusPlus)
+                          -> Option<(u32, u32)> {
+        // The quadrant is divided into strata of the width and height of the smallest elementary intervals
+        let strata_bits = self.bits - grid_bits - 1;
+        let first_x = ((top_bits(x, grid_bits) << 1) | quadrant_x) << strata_bits;
+        let first_y = ((top_bits(y, grid_bits) << 1) | quadrant_y) << strata_bits;
+
+        // Select one of the valid combinations of strata at random
+        let mut selected = None;
+        let mut valid_count = 0;
+        for stratum_x in first_x..first_x + (1 << strata_bits) {
+            for stratum_y in first_y..first_y + (1 << strata_bits) {
+                let candidate = (stratum_x << (32 - self.bits), stratum_y << (32 - self.bits));
+                if self.is_free(candidate) {
+                    valid_count += 1;
+                    if rng.gen_range(0..valid_count) == 0 {
+                        selected = Some(candidate);
+                    }
+                }
+            }
+        }
+
+        // Jitter the sample within the strata
+        let (x, y) = selected?;
+        let sample = (x | rng.gen::<u32>() >> self.bits, y | rng.gen::<u32>() >> self.bits);
+        self.insert(sample);
+        Some(sample)
+    }
+}
+
+#[inline]
+fn to_float(value: u32) -> f32 {
+    (value >> 8) as f32 / (1 << 24) as f32
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn samples(samples_per_pixel: u32) -> Vec<(f32, f32)> {
+        let sampler = Pmj02Sampler::new(Rectangle::new(-3, 7, -2, 8), samples_per_pixel);
+        sampler.tiles(1, 1).flatten().map(|sample| sample.sample_offset()).collect()
+    }
+
+    #[test]
+    fn pmj02_sampler() {
+        let rect = Rectangle::new(10, 20, 22, 30);
+        let sampler = Pmj02Sampler::new(rect.clone(), 3);
+
+        let mut tile_count = 0;
+        for tile in sampler.tiles(3, 2) {
+            tile_count += 1;
+
+            // Total rect size is 12 * 10, 3 samples per pixel, divided by 6 tiles
+            assert_eq!(tile.len(), 12 * 10 * 3 / 6, "wrong number of samples in tile");
+            for sample in tile {
+                assert!(rect.contains(sample.pixel().0, sample.pixel().1));
+                assert!((0.0..1.0).contains(&sample.wavelength_sample()));
+            }
+        }
+
+        assert_eq!(tile_count, 6, "wrong number of tiles");
+    }
+
+    #[test]
+    fn pmj02_sampler_elementary_intervals() {
+        // Every prefix of 2^k samples has one sample in each elementary interval of 2^i x 2^(k - i) cells
+        let samples = samples(256);
+        for k in 0..=8 {
+            let prefix = &samples[..1 << k];
+            for i in 0..=k {
+                let (columns, rows) = (1usize << i, 1usize << (k - i));
+                let mut cells = vec![0; columns * rows];
+                for &(x, y) in prefix {
+                    cells[(y * rows as f32) as usize * columns + (x * columns as f32) as usize] += 1;
+                }
+                assert!(cells.iter().all(|&c| c == 1), "prefix of {} samples is not stratified in {} x {} cells", prefix.len(), columns, rows);
+            }
+        }
+    }
+
+    #[test]
+    fn pmj02_sampler_progressive() {
+        let samples = samples(64);
+        assert_eq!(self::samples(5), samples[..5]);
+        assert!(samples.iter().all(|&(x, y)| (0.0..1.0).contains(&x) && (0.0..1.0).contains(&y)));
+
+        let other_pixel: Vec<_> = Pmj02Sampler::new(Rectangle::new(0, 0, 1, 1), 4).tiles(1, 1).flatten().map(|sample| sample.sample_offset()).collect();
+        assert_ne!(other_pixel, samples[..4]);
+    }
+
+    #[test]
+    fn pmj02_sampler_sequence() {
+        // Every prefix of 2^k samples of the whole precomputed sequence is stratified in all elementary intervals, also in the finest ones
+        let sequence = pmj02_sequence(3);
+        assert_eq!(sequence.len(), SEQUENCE_LENGTH);
+        for k in 0..=SEQUENCE_LENGTH.trailing_zeros() {
+            let mut occupied = vec![vec![false; 1 << k]; k as usize + 1];
+            for &sample in &sequence[..1 << k] {
+                for column_bits in 0..=k {
+                    let cell = ((top_bits(sample.1, k - column_bits) << column_bits) | top_bits(sample.0, column_bits)) as usize;
+                    assert!(!occupied[column_bits as usize][cell], "prefix of {} samples has two samples in one elementary interval", 1 << k);
+                    occupied[column_bits as usize][cell] = true;
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "samples per pixel")]
+    fn pmj02_sampler_zero_samples_per_pixel() {
+        Pmj02Sampler::new(Rectangle::new(0, 0, 4, 4), 0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn pmj02_sampler_serde() {
+        let sampler = Pmj02Sampler::new(Rectangle::new(10, 20, 22, 30), 6).with_pixel_order(PixelOrder::Hilbert);
+        let data = bincode::serialize(&sampler).unwrap();
+
+        let result: Pmj02Sampler = bincode::deserialize(&data).unwrap();
+        assert_eq!(*result.rectangle(), *sampler.rectangle());
+        assert_eq!(result.samples_per_pixel(), 6);
+        assert_eq!(result.pixel_order(), PixelOrder::Hilbert);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn pmj02_sampler_serde_zero_samples_per_pixel() {
+        let data = bincode::serialize(&(Rectangle::new(0, 0, 4, 4), 0u32, PixelOrder::Scanline)).unwrap();
+        assert!(bincode::deserialize::<Pmj02Sampler>(&data).is_err());
+    }
+}
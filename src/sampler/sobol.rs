@@ -0,0 +1,322 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::iter::FusedIterator;
+
+use crate::rectangle::{Coordinate, PixelOrder, PixelOrderIterator, Rectangle, RectangleTileIterator};
+use crate::sampler::{hash_pixel, ONE_MINUS_EPSILON, PixelSample, Sampler, SamplerTile};
+
+/// Progressive sampler based on an Owen-scrambled Sobol (0,2)-sequence.
+///
+/// Every prefix of 2^k samples of a pixel has one sample in each base-2 elementary interval of area 1/2^k, like the progressive multi-jittered (0,2)
+/// sequences by Christensen, Kensler and Kilpatrick (2018), so each prefix is well distributed and any number of samples per pixel can be used. Each pixel
+/// uses a different scrambling, derived from a hash of the pixel position.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "SobolSamplerData<C>"))]
+pub struct SobolSampler<C: Coordinate = u32> {
+    rectangle: Rectangle<C>,
+    samples_per_pixel: u32,
+    pixel_order: PixelOrder,
+}
+
+#[derive(Clone, Debug)]
+pub struct SobolSamplerTileIterator<C: Coordinate = u32> {
+    rect_iter: RectangleTileIterator<C>,
+    samples_per_pixel: u32,
+    pixel_order: PixelOrder,
+}
+
+#[derive(Clone, Debug)]
+pub struct SobolSamplerTile<C: Coordinate = u32> {
+    tile_rect: Rectangle<C>,
+    tile_rect_iter: PixelOrderIterator<C>,
+    samples_per_pixel: u32,
+
+    pixel_sample_count: u32,
+    pixel_x: C,
+    pixel_y: C,
+    pixel_seed: u32,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct SobolSamplerData<C: Coordinate> {
+    rectangle: Rectangle<C>,
+    samples_per_pixel: u32,
+    pixel_order: PixelOrder,
+}
+
+// ===== SobolSampler ==========================================================================================================================================
+
+impl<C: Coordinate> SobolSampler<C> {
+    #[inline]
+    pub fn new(rectangle: Rectangle<C>, samples_per_pixel: u32) -> SobolSampler<C> {
+        assert!(samples_per_pixel > 0, "samples per pixel must be greater than zero");
+        SobolSampler { rectangle, samples_per_pixel, pixel_order: PixelOrder::Scanline }
+    }
+
+    /// Returns this sampler with the given order in which the pixels within each tile are visited.
+    #[inline]
+    pub fn with_pixel_order(self, pixel_order: PixelOrder) -> SobolSampler<C> {
+        SobolSampler { pixel_order, ..self }
+    }
+
+    #[inline]
+    pub fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    #[inline]
+    pub fn pixel_order(&self) -> PixelOrder {
+        self.pixel_order
+    }
+}
+
+impl<C: Coordinate> Sampler<C> for SobolSampler<C> {
+    type Tile = SobolSamplerTile<C>;
+    type TileIter = SobolSamplerTileIterator<C>;
+
+    #[inline]
+    fn rectangle(&self) -> &Rectangle<C> {
+        &self.rectangle
+    }
+
+    #[inline]
+    fn tiles(&self, tile_count_x: u32, tile_count_y: u32) -> SobolSamplerTileIterator<C> {
+        SobolSamplerTileIterator::new(self.rectangle(), self.samples_per_pixel, tile_count_x, tile_count_y, self.pixel_order)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<C: Coordinate> TryFrom<SobolSamplerData<C>> for SobolSampler<C> {
+    type Error = String;
+
+    fn try_from(data: SobolSamplerData<C>) -> Result<SobolSampler<C>, String> {
+        if data.samples_per_pixel > 0 {
+            Ok(SobolSampler { rectangle: data.rectangle, samples_per_pixel: data.samples_per_pixel, pixel_order: data.pixel_order })
+        } else {
+            Err("samples per pixel must be greater than zero".to_string())
+        }
+    }
+}
+
+// ===== SobolSamplerTileIterator ==============================================================================================================================
+
+impl<C: Coordinate> SobolSamplerTileIterator<C> {
+    #[inline]
+    fn new(sampler_rect: &Rectangle<C>, samples_per_pixel: u32, tile_count_x: u32, tile_count_y: u32, pixel_order: PixelOrder) -> SobolSamplerTileIterator<C> {
+        SobolSamplerTileIterator { rect_iter: sampler_rect.tile_iter(tile_count_x, tile_count_y), samples_per_pixel, pixel_order }
+    }
+}
+
+impl<C: Coordinate> Iterator for SobolSamplerTileIterator<C> {
+    type Item = SobolSamplerTile<C>;
+
+    fn next(&mut self) -> Option<SobolSamplerTile<C>> {
+        self.rect_iter.next().map(|tile| SobolSamplerTile::new(tile, self.samples_per_pixel, self.pixel_order))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rect_iter.size_hint()
+    }
+}
+
+impl<C: Coordinate> ExactSizeIterator for SobolSamplerTileIterator<C> {}
+
+impl<C: Coordinate> FusedIterator for SobolSamplerTileIterator<C> {}
+
+// ===== SobolSamplerTile ======================================================================================================================================
+
+impl<C: Coordinate> SobolSamplerTile<C> {
+    fn new(tile_rect: Rectangle<C>, samples_per_pixel: u32, pixel_order: PixelOrder) -> SobolSamplerTile<C> {
+        let tile_rect_iter = tile_rect.pixel_order_iter(pixel_order);
+        let (pixel_x, pixel_y) = (tile_rect.left, tile_rect.top);
+
+        SobolSamplerTile {
+            tile_rect,
+            tile_rect_iter,
+            samples_per_pixel,
+
+            pixel_sample_count: samples_per_pixel, // So that the first time, we advance to the first pixel
+            pixel_x,
+            pixel_y,
+            pixel_seed: 0,
+        }
+    }
+}
+
+impl<C: Coordinate> SamplerTile<C> for SobolSamplerTile<C> {
+    #[inline]
+    fn rectangle(&self) -> &Rectangle<C> {
+        &self.tile_rect
+    }
+}
+
+impl<C: Coordinate> Iterator for SobolSamplerTile<C> {
+    type Item = PixelSample<C>;
+
+    fn next(&mut self) -> Option<PixelSample<C>> {
+        if self.pixel_sample_count >= self.samples_per_pixel {
+            if let Some((px, py)) = self.tile_rect_iter.next() {
+                // Advance to the next pixel in the tile
+                self.pixel_sample_count = 0;
+                self.pixel_x = px;
+                self.pixel_y = py;
+                self.pixel_seed = hash_pixel(px, py);
+            } else {
+                // No more pixels
+                return None;
+            }
+        }
+
+        // Generate the next sample for the current pixel
+        let index = self.pixel_sample_count;
+        self.pixel_sample_count += 1;
+        let sample_offset_x = to_float(owen_scramble(index.reverse_bits(), self.pixel_seed));
+        let sample_offset_y = to_float(owen_scramble(sobol_second_dimension(index), self.pixel_seed.wrapping_mul(0x9e37_79b9) ^ 0x6a09_e667));
+        let wavelength_sample = to_float(owen_scramble(index.reverse_bits(), self.pixel_seed.wrapping_mul(0x85eb_ca6b) ^ 0xbb67_ae85));
+
+        Some(PixelSample::new(self.pixel_x, self.pixel_y, sample_offset_x.min(ONE_MINUS_EPSILON), sample_offset_y.min(ONE_MINUS_EPSILON))
+            .with_wavelength_sample(wavelength_sample.min(ONE_MINUS_EPSILON)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (pixels_remaining, _) = self.tile_rect_iter.size_hint();
+        let remaining = pixels_remaining * self.samples_per_pixel as usize + (self.samples_per_pixel - self.pixel_sample_count) as usize;
+
+        (remaining, Some(remaining))
+    }
+}
+
+impl<C: Coordinate> ExactSizeIterator for SobolSamplerTile<C> {}
+
+impl<C: Coordinate> FusedIterator for SobolSamplerTile<C> {}
+
+// ===== Owen-scrambled Sobol (0,2)-sequence ===================================================================================================================
+
+/// Returns the second dimension of the Sobol sequence, which together with the van der Corput sequence (the bit-reversed index) forms a (0,2)-sequence.
+fn sobol_second_dimension(index: u32) -> u32 {
+    let (mut result, mut v, mut i) = (0, 1 << 31, index);
+    while i != 0 {
+        if i & 1 != 0 {
+            result ^= v;
+        }
+        i >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
+
+/// Applies a nested uniform (Owen) scrambling to a value in 0.32 fixed point format, with the hash function by Burley (2020). Owen scrambling keeps the
+/// stratification of the sequence in elementary intervals.
+fn owen_scramble(value: u32, seed: u32) -> u32 {
+    let mut x = value.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x.reverse_bits()
+}
+
+#[inline]
+fn to_float(value: u32) -> f32 {
+    (value >> 8) as f32 / (1 << 24) as f32
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn samples(samples_per_pixel: u32) -> Vec<(f32, f32)> {
+        let sampler = SobolSampler::new(Rectangle::new(-3, 7, -2, 8), samples_per_pixel);
+        sampler.tiles(1, 1).flatten().map(|sample| sample.sample_offset()).collect()
+    }
+
+    #[test]
+    fn sobol_sampler() {
+        let rect = Rectangle::new(10, 20, 22, 30);
+        let sampler = SobolSampler::new(rect.clone(), 3);
+
+        let mut tile_count = 0;
+        for tile in sampler.tiles(3, 2) {
+            tile_count += 1;
+
+            // Total rect size is 12 * 10, 3 samples per pixel, divided by 6 tiles
+            assert_eq!(tile.len(), 12 * 10 * 3 / 6, "wrong number of samples in tile");
+            for sample in tile {
+                assert!(rect.contains(sample.pixel().0, sample.pixel().1));
+                assert!((0.0..1.0).contains(&sample.wavelength_sample()));
+            }
+        }
+
+        assert_eq!(tile_count, 6, "wrong number of tiles");
+    }
+
+    #[test]
+    fn sobol_sampler_elementary_intervals() {
+        // Every prefix of 2^k samples has one sample in each elementary interval of 2^i x 2^(k - i) cells
+        let samples = samples(256);
+        for k in 0..=8 {
+            let prefix = &samples[..1 << k];
+            for i in 0..=k {
+                let (columns, rows) = (1usize << i, 1usize << (k - i));
+                let mut cells = vec![0; columns * rows];
+                for &(x, y) in prefix {
+                    cells[(y * rows as f32) as usize * columns + (x * columns as f32) as usize] += 1;
+                }
+                assert!(cells.iter().all(|&c| c == 1), "prefix of {} samples is not stratified in {} x {} cells", prefix.len(), columns, rows);
+            }
+        }
+    }
+
+    #[test]
+    fn sobol_sampler_progressive() {
+        let samples = samples(64);
+        assert_eq!(self::samples(5), samples[..5]);
+        assert!(samples.iter().all(|&(x, y)| (0.0..1.0).contains(&x) && (0.0..1.0).contains(&y)));
+
+        let other_pixel: Vec<_> = SobolSampler::new(Rectangle::new(0, 0, 1, 1), 4).tiles(1, 1).flatten().map(|sample| sample.sample_offset()).collect();
+        assert_ne!(other_pixel, samples[..4]);
+    }
+
+    #[test]
+    #[should_panic(expected = "samples per pixel")]
+    fn sobol_sampler_zero_samples_per_pixel() {
+        SobolSampler::new(Rectangle::new(0, 0, 4, 4), 0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn sobol_sampler_serde() {
+        let sampler = SobolSampler::new(Rectangle::new(10, 20, 22, 30), 6).with_pixel_order(PixelOrder::Hilbert);
+        let data = bincode::serialize(&sampler).unwrap();
+
+        let result: SobolSampler = bincode::deserialize(&data).unwrap();
+        assert_eq!(*result.rectangle(), *sampler.rectangle());
+        assert_eq!(result.samples_per_pixel(), 6);
+        assert_eq!(result.pixel_order(), PixelOrder::Hilbert);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn sobol_sampler_serde_zero_samples_per_pixel() {
+        let data = bincode::serialize(&(Rectangle::new(0, 0, 4, 4), 0u32, PixelOrder::Scanline)).unwrap();
+        assert!(bincode::deserialize::<SobolSampler>(&data).is_err());
+    }
+}
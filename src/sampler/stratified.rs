@@ -18,77 +18,116 @@ use rand::Rng;
 use rand_xoshiro::rand_core::SeedableRng;
 use rand_xoshiro::Xoshiro256PlusPlus;
 
-use crate::rectangle::{Rectangle, RectangleIndexIterator, RectangleTileIterator};
-use crate::sampler::{PixelSample, Sampler, SamplerTile};
+use crate::rectangle::{Coordinate, PixelOrder, PixelOrderIterator, Rectangle, RectangleTileIterator};
+use crate::sampler::{hash_pixel, ONE_MINUS_EPSILON, permute, PixelSample, Sampler, SamplerTile};
 
 #[derive(Clone, Debug)]
-pub struct StratifiedSampler {
-    rectangle: Rectangle,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "StratifiedSamplerData<C>"))]
+pub struct StratifiedSampler<C: Coordinate = u32> {
+    rectangle: Rectangle<C>,
     sqrt_samples_per_pixel: u32,
     jitter: bool,
+    pixel_order: PixelOrder,
 }
 
 #[derive(Clone, Debug)]
-pub struct StratifiedSamplerTileIterator {
-    rect_iter: RectangleTileIterator,
+pub struct StratifiedSamplerTileIterator<C: Coordinate = u32> {
+    rect_iter: RectangleTileIterator<C>,
     sqrt_samples_per_pixel: u32,
     jitter: bool,
+    pixel_order: PixelOrder,
 }
 
 #[derive(Clone, Debug)]
-pub struct StratifiedSamplerTile {
-    tile_rect: Rectangle,
-    tile_rect_iter: RectangleIndexIterator,
+pub struct StratifiedSamplerTile<C: Coordinate = u32> {
+    tile_rect: Rectangle<C>,
+    tile_rect_iter: PixelOrderIterator<C>,
     sqrt_samples_per_pixel: u32,
 
-    pixel_x: u32,
-    pixel_y: u32,
+    pixel_x: C,
+    pixel_y: C,
     stratum_x: u32,
     stratum_y: u32,
+    wavelength_pattern: u32,
 
     jitter: bool,
     rng: Xoshiro256PlusPlus,
 }
 
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct StratifiedSamplerData<C: Coordinate> {
+    rectangle: Rectangle<C>,
+    sqrt_samples_per_pixel: u32,
+    jitter: bool,
+    pixel_order: PixelOrder,
+}
+
 // ===== StratifiedSampler =====================================================================================================================================
 
-impl StratifiedSampler {
+impl<C: Coordinate> StratifiedSampler<C> {
+    #[inline]
+    pub fn new(rectangle: Rectangle<C>, sqrt_samples_per_pixel: u32, jitter: bool) -> StratifiedSampler<C> {
+        assert!(sqrt_samples_per_pixel > 0, "square root of the samples per pixel must be greater than zero");
+        StratifiedSampler { rectangle, sqrt_samples_per_pixel, jitter, pixel_order: PixelOrder::Scanline }
+    }
+
+    /// Returns this sampler with the given order in which the pixels within each tile are visited.
+    #[inline]
+    pub fn with_pixel_order(self, pixel_order: PixelOrder) -> StratifiedSampler<C> {
+        StratifiedSampler { pixel_order, ..self }
+    }
+
     #[inline]
-    pub fn new(rectangle: Rectangle, sqrt_samples_per_pixel: u32, jitter: bool) -> StratifiedSampler {
-        StratifiedSampler { rectangle, sqrt_samples_per_pixel, jitter }
+    pub fn pixel_order(&self) -> PixelOrder {
+        self.pixel_order
     }
 }
 
-impl Sampler for StratifiedSampler {
-    type Tile = StratifiedSamplerTile;
-    type TileIter = StratifiedSamplerTileIterator;
+impl<C: Coordinate> Sampler<C> for StratifiedSampler<C> {
+    type Tile = StratifiedSamplerTile<C>;
+    type TileIter = StratifiedSamplerTileIterator<C>;
 
     #[inline]
-    fn rectangle(&self) -> &Rectangle {
+    fn rectangle(&self) -> &Rectangle<C> {
         &self.rectangle
     }
 
     #[inline]
-    fn tiles(&self, tile_count_x: u32, tile_count_y: u32) -> StratifiedSamplerTileIterator {
-        StratifiedSamplerTileIterator::new(&self.rectangle, self.sqrt_samples_per_pixel, tile_count_x, tile_count_y, self.jitter)
+    fn tiles(&self, tile_count_x: u32, tile_count_y: u32) -> StratifiedSamplerTileIterator<C> {
+        StratifiedSamplerTileIterator::new(&self.rectangle, self.sqrt_samples_per_pixel, tile_count_x, tile_count_y, self.jitter, self.pixel_order)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<C: Coordinate> TryFrom<StratifiedSamplerData<C>> for StratifiedSampler<C> {
+    type Error = String;
+
+    fn try_from(data: StratifiedSamplerData<C>) -> Result<StratifiedSampler<C>, String> {
+        if data.sqrt_samples_per_pixel > 0 {
+            Ok(StratifiedSampler { rectangle: data.rectangle, sqrt_samples_per_pixel: data.sqrt_samples_per_pixel, jitter: data.jitter, pixel_order: data.pixel_order })
+        } else {
+            Err("square root of the samples per pixel must be greater than zero".to_string())
+        }
     }
 }
 
 // ===== StratifiedSamplerTileIterator =========================================================================================================================
 
-impl StratifiedSamplerTileIterator {
+impl<C: Coordinate> StratifiedSamplerTileIterator<C> {
     #[inline]
-    fn new(sampler_rect: &Rectangle, sqrt_samples_per_pixel: u32, tile_count_x: u32, tile_count_y: u32, jitter: bool) -> StratifiedSamplerTileIterator {
-        StratifiedSamplerTileIterator { rect_iter: sampler_rect.tile_iter(tile_count_x, tile_count_y), sqrt_samples_per_pixel, jitter }
+    fn new(sampler_rect: &Rectangle<C>, sqrt_samples_per_pixel: u32, tile_count_x: u32, tile_count_y: u32, jitter: bool, pixel_order: PixelOrder) -> StratifiedSamplerTileIterator<C> {
+        StratifiedSamplerTileIterator { rect_iter: sampler_rect.tile_iter(tile_count_x, tile_count_y), sqrt_samples_per_pixel, jitter, pixel_order }
     }
 }
 
-impl Iterator for StratifiedSamplerTileIterator {
-    type Item = StratifiedSamplerTile;
+impl<C: Coordinate> Iterator for StratifiedSamplerTileIterator<C> {
+    type Item = StratifiedSamplerTile<C>;
 
-    fn next(&mut self) -> Option<StratifiedSamplerTile> {
+    fn next(&mut self) -> Option<StratifiedSamplerTile<C>> {
         self.rect_iter.next().map(|tile| {
-            StratifiedSamplerTile::new(tile, self.sqrt_samples_per_pixel, self.jitter)
+            StratifiedSamplerTile::new(tile, self.sqrt_samples_per_pixel, self.jitter, self.pixel_order)
         })
     }
 
@@ -98,15 +137,15 @@ impl Iterator for StratifiedSamplerTileIterator {
     }
 }
 
-impl ExactSizeIterator for StratifiedSamplerTileIterator {}
+impl<C: Coordinate> ExactSizeIterator for StratifiedSamplerTileIterator<C> {}
 
-impl FusedIterator for StratifiedSamplerTileIterator {}
+impl<C: Coordinate> FusedIterator for StratifiedSamplerTileIterator<C> {}
 
 // ===== StratifiedSamplerTile =================================================================================================================================
 
-impl StratifiedSamplerTile {
-    fn new(tile_rect: Rectangle, sqrt_samples_per_pixel: u32, jitter: bool) -> StratifiedSamplerTile {
-        let tile_rect_iter = tile_rect.index_iter();
+impl<C: Coordinate> StratifiedSamplerTile<C> {
+    fn new(tile_rect: Rectangle<C>, sqrt_samples_per_pixel: u32, jitter: bool, pixel_order: PixelOrder) -> StratifiedSamplerTile<C> {
+        let tile_rect_iter = tile_rect.pixel_order_iter(pixel_order);
         let (pixel_x, pixel_y) = (tile_rect.left, tile_rect.top);
 
         StratifiedSamplerTile {
@@ -118,6 +157,7 @@ impl StratifiedSamplerTile {
             pixel_y,
             stratum_x: 0,
             stratum_y: sqrt_samples_per_pixel, // So that the first time, we advance to the first pixel
+            wavelength_pattern: 0,
 
             jitter,
             rng: Xoshiro256PlusPlus::from_entropy(),
@@ -125,17 +165,17 @@ impl StratifiedSamplerTile {
     }
 }
 
-impl SamplerTile for StratifiedSamplerTile {
+impl<C: Coordinate> SamplerTile<C> for StratifiedSamplerTile<C> {
     #[inline]
-    fn rectangle(&self) -> &Rectangle {
+    fn rectangle(&self) -> &Rectangle<C> {
         &self.tile_rect
     }
 }
 
-impl Iterator for StratifiedSamplerTile {
-    type Item = PixelSample;
+impl<C: Coordinate> Iterator for StratifiedSamplerTile<C> {
+    type Item = PixelSample<C>;
 
-    fn next(&mut self) -> Option<PixelSample> {
+    fn next(&mut self) -> Option<PixelSample<C>> {
         if self.stratum_y >= self.sqrt_samples_per_pixel {
             if let Some((px, py)) = self.tile_rect_iter.next() {
                 // Advance to the next pixel in the tile
@@ -143,6 +183,7 @@ impl Iterator for StratifiedSamplerTile {
                 self.pixel_y = py;
                 self.stratum_x = 0;
                 self.stratum_y = 0;
+                self.wavelength_pattern = if self.jitter { self.rng.gen() } else { hash_pixel(px, py) };
             } else {
                 // No more pixels
                 return None;
@@ -150,17 +191,22 @@ impl Iterator for StratifiedSamplerTile {
         }
 
         // Generate the next sample for the current pixel
-        let (jitter_x, jitter_y) = if self.jitter { self.rng.gen() } else { (0.5, 0.5) };
+        let (jitter_x, jitter_y, jitter_wavelength) = if self.jitter { self.rng.gen() } else { (0.5, 0.5, 0.5) };
         let sample_offset_x = (self.stratum_x as f32 + jitter_x) / self.sqrt_samples_per_pixel as f32;
         let sample_offset_y = (self.stratum_y as f32 + jitter_y) / self.sqrt_samples_per_pixel as f32;
 
+        // The wavelength samples of a pixel are stratified too, in a shuffled order so that they are not correlated with the sample offsets
+        let sample_count = self.sqrt_samples_per_pixel * self.sqrt_samples_per_pixel;
+        let wavelength_stratum = permute(self.stratum_y * self.sqrt_samples_per_pixel + self.stratum_x, sample_count, self.wavelength_pattern);
+        let wavelength_sample = ((wavelength_stratum as f32 + jitter_wavelength) / sample_count as f32).min(ONE_MINUS_EPSILON);
+
         self.stratum_x += 1;
         if self.stratum_x >= self.sqrt_samples_per_pixel {
             self.stratum_x = 0;
             self.stratum_y += 1;
         }
 
-        Some(PixelSample::new(self.pixel_x, self.pixel_y, sample_offset_x, sample_offset_y))
+        Some(PixelSample::new(self.pixel_x, self.pixel_y, sample_offset_x, sample_offset_y).with_wavelength_sample(wavelength_sample))
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
@@ -173,15 +219,16 @@ impl Iterator for StratifiedSamplerTile {
     }
 }
 
-impl ExactSizeIterator for StratifiedSamplerTile {}
+impl<C: Coordinate> ExactSizeIterator for StratifiedSamplerTile<C> {}
 
-impl FusedIterator for StratifiedSamplerTile {}
+impl<C: Coordinate> FusedIterator for StratifiedSamplerTile<C> {}
 
 // ===== Tests =================================================================================================================================================
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::color::{ColorSpace, SampledWavelengths, Spectrum};
 
     #[test]
     fn stratified_sampler() {
@@ -205,4 +252,64 @@ mod test {
 
         assert_eq!(tile_count, 6, "wrong number of tiles");
     }
+
+    #[test]
+    fn stratified_sampler_wavelengths() {
+        // With jitter off, the wavelength samples of a pixel should still cover the visible range evenly, so a constant spectrum comes out with the color of the constant spectrum
+        let sampler = StratifiedSampler::new(Rectangle::new(0, 0, 2, 2), 10, false);
+        let expected = Spectrum::constant(1.0).to_rgb(&ColorSpace::LINEAR_SRGB);
+
+        for tile in sampler.tiles(1, 1) {
+            let samples: Vec<_> = tile.collect();
+            for pixel_samples in samples.chunks(100) {
+                let mut spectrum = Spectrum::default();
+                for sample in pixel_samples {
+                    spectrum += Spectrum::from_wavelength_samples(&SampledWavelengths::sample(sample.wavelength_sample()), [1.0; SampledWavelengths::COUNT]);
+                }
+
+                let rgb = (spectrum / pixel_samples.len() as f32).to_rgb(&ColorSpace::LINEAR_SRGB);
+                assert!((rgb.r - expected.r).abs() < 1e-3 && (rgb.g - expected.g).abs() < 1e-3 && (rgb.b - expected.b).abs() < 1e-3, "wrong color: {:?}", rgb);
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_sampler_pixel_order() {
+        let rect = Rectangle::new(-3, 5, 13, 21);
+        let sampler = StratifiedSampler::new(rect.clone(), 2, false).with_pixel_order(PixelOrder::Hilbert);
+        assert_eq!(sampler.pixel_order(), PixelOrder::Hilbert);
+
+        let tile = sampler.tiles(1, 1).next().unwrap();
+        assert_eq!(tile.len(), 16 * 16 * 4);
+
+        let pixels: Vec<_> = tile.step_by(4).map(|sample| sample.pixel()).collect();
+        assert_eq!(pixels, rect.hilbert_iter().collect::<Vec<_>>());
+        assert_eq!(pixels[..4], [(-3, 5), (-2, 5), (-2, 6), (-3, 6)]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn stratified_sampler_serde() {
+        let sampler = StratifiedSampler::new(Rectangle::new(10, 20, 22, 30), 4, false).with_pixel_order(PixelOrder::Morton);
+        let data = bincode::serialize(&sampler).unwrap();
+
+        let result: StratifiedSampler = bincode::deserialize(&data).unwrap();
+        assert_eq!(*result.rectangle(), *sampler.rectangle());
+        assert_eq!(result.sqrt_samples_per_pixel, 4);
+        assert!(!result.jitter);
+        assert_eq!(result.pixel_order(), PixelOrder::Morton);
+    }
+
+    #[test]
+    #[should_panic(expected = "square root of the samples per pixel")]
+    fn stratified_sampler_zero_samples_per_pixel() {
+        StratifiedSampler::new(Rectangle::new(0, 0, 4, 4), 0, true);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn stratified_sampler_serde_zero_samples_per_pixel() {
+        let data = bincode::serialize(&(Rectangle::new(0, 0, 4, 4), 0u32, true, PixelOrder::Scanline)).unwrap();
+        assert!(bincode::deserialize::<StratifiedSampler>(&data).is_err());
+    }
 }
@@ -0,0 +1,100 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub use aces::*;
+pub use exposure::*;
+pub use hable::*;
+pub use reinhard::*;
+
+use crate::color::Rgb;
+use crate::raster::Raster;
+use crate::rectangle::Coordinate;
+
+mod exposure;
+mod reinhard;
+mod aces;
+mod hable;
+
+/// Tone mapping operator that maps unbounded linear color values to displayable values.
+pub trait ToneMapper: Send + Sync {
+    /// Maps a linear color value.
+    fn map(&self, color: Rgb) -> Rgb;
+}
+
+// ===== Raster ================================================================================================================================================
+
+impl<C: Coordinate> Raster<Rgb, C> {
+    /// Applies a tone mapping operator to all elements of this raster.
+    pub fn tone_map<T: ToneMapper>(&self, tone_mapper: &T) -> Raster<Rgb, C> {
+        self.map(|color| tone_mapper.map(color))
+    }
+
+    /// Returns the log-average luminance of this raster.
+    ///
+    /// Elements with a non-finite luminance are ignored. Returns zero if the raster does not contain any elements with a finite luminance.
+    pub fn log_average_luminance(&self) -> f32 {
+        const DELTA: f64 = 1e-4;
+
+        let mut sum = 0.0f64;
+        let mut count = 0usize;
+        for (x, y) in self.rectangle().index_iter() {
+            let luminance = self.get(x, y).luminance();
+            if luminance.is_finite() {
+                sum += (DELTA + f64::max(luminance as f64, 0.0)).ln();
+                count += 1;
+            }
+        }
+
+        if count > 0 { (sum / count as f64).exp() as f32 } else { 0.0 }
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use crate::rectangle::Rectangle;
+
+    use super::*;
+
+    #[test]
+    fn raster_tone_map() {
+        let mut raster = Raster::<Rgb>::new(Rectangle::new(0, 0, 2, 2));
+        raster.set(1, 0, Rgb::gray(3.0));
+
+        let result = raster.tone_map(&ReinhardToneMapper::new());
+        assert_eq!(result.get(0, 0), Rgb::BLACK);
+        assert!((result.get(1, 0).g - 0.75).abs() < 1e-5);
+    }
+
+    #[test]
+    fn raster_log_average_luminance() {
+        let mut raster = Raster::<Rgb>::new(Rectangle::new(0, 0, 2, 1));
+        raster.set(0, 0, Rgb::gray(0.5));
+        raster.set(1, 0, Rgb::gray(2.0));
+
+        // Geometric mean of 0.5 and 2.0 is 1.0
+        assert!((raster.log_average_luminance() - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn raster_log_average_luminance_ignores_non_finite() {
+        let mut raster = Raster::<Rgb>::new(Rectangle::new(0, 0, 3, 1));
+        raster.set(0, 0, Rgb::gray(0.5));
+        raster.set(1, 0, Rgb::gray(2.0));
+        raster.set(2, 0, Rgb::gray(f32::NAN));
+
+        assert!((raster.log_average_luminance() - 1.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,83 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::color::Rgb;
+use crate::tonemap::ToneMapper;
+
+/// ACES filmic tone mapping curve, using the fit by Krzysztof Narkowicz.
+///
+/// The output is clamped to the range 0..1.
+#[derive(Clone, Debug)]
+pub struct AcesFilmicToneMapper {}
+
+// ===== AcesFilmicToneMapper ==================================================================================================================================
+
+impl AcesFilmicToneMapper {
+    const A: f32 = 2.51;
+    const B: f32 = 0.03;
+    const C: f32 = 2.43;
+    const D: f32 = 0.59;
+    const E: f32 = 0.14;
+
+    #[inline]
+    pub fn new() -> AcesFilmicToneMapper {
+        AcesFilmicToneMapper {}
+    }
+
+    #[inline]
+    fn aces(v: f32) -> f32 {
+        let v = f32::max(v, 0.0);
+        ((v * (Self::A * v + Self::B)) / (v * (Self::C * v + Self::D) + Self::E)).clamp(0.0, 1.0)
+    }
+}
+
+impl Default for AcesFilmicToneMapper {
+    #[inline]
+    fn default() -> AcesFilmicToneMapper {
+        AcesFilmicToneMapper::new()
+    }
+}
+
+impl ToneMapper for AcesFilmicToneMapper {
+    #[inline]
+    fn map(&self, color: Rgb) -> Rgb {
+        color.map(AcesFilmicToneMapper::aces)
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn aces_filmic_tone_mapper_map() {
+        let tone_mapper = AcesFilmicToneMapper::new();
+        assert_eq!(tone_mapper.map(Rgb::BLACK), Rgb::BLACK);
+        assert!((tone_mapper.map(Rgb::gray(0.18)).r - 0.2667).abs() < 1e-3);
+        assert_eq!(tone_mapper.map(Rgb::gray(1000.0)), Rgb::WHITE);
+    }
+
+    #[test]
+    fn aces_filmic_tone_mapper_is_monotonic() {
+        let tone_mapper = AcesFilmicToneMapper::new();
+        let mut last = 0.0;
+        for i in 1..1000 {
+            let v = tone_mapper.map(Rgb::gray(i as f32 / 100.0)).r;
+            assert!(v >= last, "curve is not monotonic at {}", i);
+            last = v;
+        }
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::color::Rgb;
+use crate::raster::Raster;
+use crate::rectangle::Coordinate;
+use crate::tonemap::ToneMapper;
+
+/// Exposure operator, which scales color values by a constant factor.
+#[derive(Clone, Debug)]
+pub struct ExposureToneMapper {
+    scale: f32,
+}
+
+// ===== ExposureToneMapper ====================================================================================================================================
+
+impl ExposureToneMapper {
+    /// Creates an exposure operator for the given exposure value in stops.
+    #[inline]
+    pub fn new(exposure: f32) -> ExposureToneMapper {
+        ExposureToneMapper::with_scale(exposure.exp2())
+    }
+
+    #[inline]
+    pub fn with_scale(scale: f32) -> ExposureToneMapper {
+        ExposureToneMapper { scale }
+    }
+
+    /// Creates an exposure operator that maps the log-average luminance of the raster to the given key value (typically 0.18).
+    pub fn auto<C: Coordinate>(raster: &Raster<Rgb, C>, key: f32) -> ExposureToneMapper {
+        let log_average = raster.log_average_luminance();
+        ExposureToneMapper::with_scale(if log_average > 0.0 { key / log_average } else { 1.0 })
+    }
+
+    #[inline]
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+}
+
+impl ToneMapper for ExposureToneMapper {
+    #[inline]
+    fn map(&self, color: Rgb) -> Rgb {
+        color * self.scale
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use crate::rectangle::Rectangle;
+
+    use super::*;
+
+    #[test]
+    fn exposure_tone_mapper_new() {
+        assert_eq!(ExposureToneMapper::new(0.0).scale, 1.0);
+        assert_eq!(ExposureToneMapper::new(2.0).scale, 4.0);
+        assert_eq!(ExposureToneMapper::new(-1.0).scale, 0.5);
+    }
+
+    #[test]
+    fn exposure_tone_mapper_map() {
+        let tone_mapper = ExposureToneMapper::with_scale(2.0);
+        assert_eq!(tone_mapper.map(Rgb::new(0.1, 0.2, 0.3)), Rgb::new(0.2, 0.4, 0.6));
+    }
+
+    #[test]
+    fn exposure_tone_mapper_auto() {
+        let mut raster = Raster::<Rgb>::new(Rectangle::new(0, 0, 2, 1));
+        raster.set(0, 0, Rgb::gray(1.0));
+        raster.set(1, 0, Rgb::gray(4.0));
+
+        // Log-average luminance is 2.0
+        let tone_mapper = ExposureToneMapper::auto(&raster, 0.18);
+        assert!((tone_mapper.scale() - 0.09).abs() < 1e-4);
+    }
+
+    #[test]
+    fn exposure_tone_mapper_auto_black_raster() {
+        let raster = Raster::<Rgb>::new(Rectangle::new(0, 0, 2, 1));
+        let tone_mapper = ExposureToneMapper::auto(&raster, 0.18);
+        assert!(tone_mapper.scale().is_finite());
+    }
+}
@@ -0,0 +1,76 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::color::Rgb;
+use crate::tonemap::ToneMapper;
+
+/// Filmic tone mapping operator by John Hable, as used in Uncharted 2.
+#[derive(Clone, Debug)]
+pub struct HableToneMapper {
+    exposure_bias: f32,
+    white_scale: f32,
+}
+
+// ===== HableToneMapper =======================================================================================================================================
+
+impl HableToneMapper {
+    const A: f32 = 0.15; // Shoulder strength
+    const B: f32 = 0.50; // Linear strength
+    const C: f32 = 0.10; // Linear angle
+    const D: f32 = 0.20; // Toe strength
+    const E: f32 = 0.02; // Toe numerator
+    const F: f32 = 0.30; // Toe denominator
+
+    #[inline]
+    pub fn new(exposure_bias: f32, white_point: f32) -> HableToneMapper {
+        HableToneMapper { exposure_bias, white_scale: HableToneMapper::hable(white_point).recip() }
+    }
+
+    #[inline]
+    pub fn with_defaults() -> HableToneMapper {
+        HableToneMapper::new(2.0, 11.2)
+    }
+
+    #[inline]
+    fn hable(v: f32) -> f32 {
+        ((v * (Self::A * v + Self::C * Self::B) + Self::D * Self::E) / (v * (Self::A * v + Self::B) + Self::D * Self::F)) - Self::E / Self::F
+    }
+}
+
+impl ToneMapper for HableToneMapper {
+    #[inline]
+    fn map(&self, color: Rgb) -> Rgb {
+        color.map(|v| HableToneMapper::hable(f32::max(v, 0.0) * self.exposure_bias) * self.white_scale)
+    }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hable_tone_mapper_map() {
+        let tone_mapper = HableToneMapper::with_defaults();
+        assert!(tone_mapper.map(Rgb::BLACK).r.abs() < 1e-6);
+        assert!((tone_mapper.map(Rgb::gray(11.2 / 2.0)).r - 1.0).abs() < 1e-5, "white point is not mapped to 1");
+    }
+
+    #[test]
+    fn hable_tone_mapper_is_debug() {
+        let tone_mapper = HableToneMapper::with_defaults();
+        println!("{:?}", tone_mapper);
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright 2023 Jesper de Jong
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::color::Rgb;
+use crate::tonemap::ToneMapper;
+
+/// Reinhard tone mapping operator, applied to the luminance of the color.
+#[derive(Clone, Debug)]
+pub struct ReinhardToneMapper {}
+
+/// Extended Reinhard tone mapping operator with a white point, applied to the luminance of the color.
+///
+/// A luminance equal to the white point is mapped to 1; greater luminance values are mapped to values greater than 1, so the result may need to be
+/// clamped.
+#[derive(Clone, Debug)]
+pub struct ExtendedReinhardToneMapper {
+    white_point_sq: f32,
+}
+
+// ===== ReinhardToneMapper ====================================================================================================================================
+
+impl ReinhardToneMapper {
+    #[inline]
+    pub fn new() -> ReinhardToneMapper {
+        ReinhardToneMapper {}
+    }
+}
+
+impl Default for ReinhardToneMapper {
+    #[inline]
+    fn default() -> ReinhardToneMapper {
+        ReinhardToneMapper::new()
+    }
+}
+
+impl ToneMapper for ReinhardToneMapper {
+    #[inline]
+    fn map(&self, color: Rgb) -> Rgb {
+        scale_luminance(color, |l| l / (1.0 + l))
+    }
+}
+
+// ===== ExtendedReinhardToneMapper ============================================================================================================================
+
+impl ExtendedReinhardToneMapper {
+    #[inline]
+    pub fn new(white_point: f32) -> ExtendedReinhardToneMapper {
+        ExtendedReinhardToneMapper { white_point_sq: white_point * white_point }
+    }
+}
+
+impl ToneMapper for ExtendedReinhardToneMapper {
+    #[inline]
+    fn map(&self, color: Rgb) -> Rgb {
+        scale_luminance(color, |l| l * (1.0 + l / self.white_point_sq) / (1.0 + l))
+    }
+}
+
+#[inline]
+fn scale_luminance<F: Fn(f32) -> f32>(color: Rgb, map_fn: F) -> Rgb {
+    let luminance = color.luminance();
+    if luminance > 0.0 { color * (map_fn(luminance) / luminance) } else { Rgb::BLACK }
+}
+
+// ===== Tests =================================================================================================================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reinhard_tone_mapper_map() {
+        let tone_mapper = ReinhardToneMapper::new();
+        assert_eq!(tone_mapper.map(Rgb::BLACK), Rgb::BLACK);
+        assert!((tone_mapper.map(Rgb::gray(1.0)).r - 0.5).abs() < 1e-5);
+        assert!(tone_mapper.map(Rgb::gray(1e6)).r < 1.0);
+    }
+
+    #[test]
+    fn reinhard_tone_mapper_preserves_hue() {
+        let tone_mapper = ReinhardToneMapper::new();
+        let color = tone_mapper.map(Rgb::new(4.0, 2.0, 1.0));
+        assert!((color.r / color.g - 2.0).abs() < 1e-5);
+        assert!((color.g / color.b - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn extended_reinhard_tone_mapper_map() {
+        let tone_mapper = ExtendedReinhardToneMapper::new(4.0);
+        assert_eq!(tone_mapper.map(Rgb::BLACK), Rgb::BLACK);
+        assert!((tone_mapper.map(Rgb::gray(4.0)).r - 1.0).abs() < 1e-5, "white point is not mapped to 1");
+        assert!(tone_mapper.map(Rgb::gray(8.0)).r > 1.0, "values above the white point are not mapped above 1");
+    }
+
+    #[test]
+    fn extended_reinhard_tone_mapper_is_debug() {
+        let tone_mapper = ExtendedReinhardToneMapper::new(4.0);
+        println!("{:?}", tone_mapper);
+    }
+}